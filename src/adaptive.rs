@@ -0,0 +1,122 @@
+use crate::color::Color;
+
+use image::{ImageBuffer, RgbImage};
+
+/// Settings for spending samples where the image is still noisy. Every pixel
+/// gets `min_samples`, then more in batches of `batch` until the standard
+/// error of its brightness drops below `threshold` times its brightness, or
+/// it reaches `max_samples`. Dark pixels are judged as if they had a brightness
+/// of at least `floor` so that they do not keep sampling for invisible noise.
+#[derive(Clone, Debug)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    pub batch: u32,
+    pub threshold: f64,
+    pub floor: f64,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> AdaptiveSampling {
+        AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 256,
+            batch: 4,
+            threshold: 0.02,
+            floor: 0.05,
+        }
+    }
+}
+
+/// Running mean and variance of the samples of a pixel (Welford's algorithm).
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub samples: u32,
+    sum: Color,
+    mean: f64,
+    m2: f64,
+}
+
+impl Estimate {
+    pub fn new() -> Estimate {
+        Estimate {
+            samples: 0,
+            sum: Color::black(),
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, color: Color) {
+        let value = color.luminance();
+        self.samples += 1;
+        self.sum += color;
+
+        let delta = value - self.mean;
+        self.mean += delta / f64::from(self.samples);
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn color(&self) -> Color {
+        self.sum * f64::from(self.samples.max(1)).recip()
+    }
+
+    /// Standard error of the mean brightness.
+    pub fn error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / f64::from(self.samples - 1);
+        (variance / f64::from(self.samples)).sqrt()
+    }
+
+    pub fn converged(&self, settings: &AdaptiveSampling) -> bool {
+        self.samples >= settings.max_samples
+            || (self.samples >= settings.min_samples
+                && self.error() <= settings.threshold * self.mean.max(settings.floor))
+    }
+}
+
+impl Default for Estimate {
+    fn default() -> Estimate {
+        Estimate::new()
+    }
+}
+
+/// Heat map of samples per pixel, from black for none through red and yellow
+/// to white for `max` or more.
+pub fn sample_map(width: u32, height: u32, counts: &[u32], max: u32) -> RgbImage {
+    ImageBuffer::from_fn(width, height, |x, y| {
+        heat(f64::from(counts[(y * width + x) as usize]) / f64::from(max.max(1))).gamma_rgb(1.0)
+    })
+}
+
+/// Black over red and yellow to white as `t` goes from 0 to 1.
+pub fn heat(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    Color::new((t * 3.0).min(1.0), (t * 3.0 - 1.0).clamp(0.0, 1.0), (t * 3.0 - 2.0).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::adaptive::{AdaptiveSampling, Estimate};
+    use crate::color::Color;
+
+    #[test]
+    fn test_convergence() {
+        let settings = AdaptiveSampling::default();
+
+        let mut flat = Estimate::new();
+        for _ in 0..settings.min_samples {
+            flat.add(Color::new(0.5, 0.5, 0.5));
+        }
+        assert!(flat.converged(&settings));
+
+        let mut noisy = Estimate::new();
+        for i in 0..settings.min_samples {
+            noisy.add(if i % 2 == 0 { Color::black() } else { Color::white() });
+        }
+        assert!(!noisy.converged(&settings));
+        assert!((noisy.color().channels()[0] - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::color::Color;
+use crate::motion::Transform;
+use crate::renderer::Renderer;
+use crate::vector::Vec3;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Values that can be blended between two keyframes.
+pub trait Lerp: Copy {
+    fn lerp(a: Self, b: Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(a: f64, b: f64, t: f64) -> f64 {
+        a + (b - a) * t
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+        a + (b - a) * t
+    }
+}
+
+impl Lerp for Transform {
+    fn lerp(a: Transform, b: Transform, t: f64) -> Transform {
+        a.lerp(&b, t)
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(a: Color, b: Color, t: f64) -> Color {
+        a * (1.0 - t) + b * t
+    }
+}
+
+/// How a keyframe eases into the next one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    /// Hold the value until the next key.
+    Step,
+    Linear,
+    /// Smoothstep, slow at both ends.
+    Smooth,
+    EaseIn,
+    EaseOut,
+}
+
+impl Curve {
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Step => 0.0,
+            Curve::Linear => t,
+            Curve::Smooth => t * t * (3.0 - 2.0 * t),
+            Curve::EaseIn => t * t,
+            Curve::EaseOut => t * (2.0 - t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+    pub curve: Curve,
+}
+
+/// A value animated over time by keyframes. Before the first and after the
+/// last key the value is held constant. Tracks always have a key.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Lerp> Track<T> {
+    pub fn constant(value: T) -> Track<T> {
+        Track::new(0.0, value, Curve::Step)
+    }
+
+    /// A track starting from a first key, more following from `key`.
+    pub fn new(time: f64, value: T, curve: Curve) -> Track<T> {
+        Track {
+            keys: vec![Keyframe { time, value, curve }],
+        }
+    }
+
+    /// Adds a key, `curve` shaping the transition towards the following key.
+    pub fn key(mut self, time: f64, value: T, curve: Curve) -> Track<T> {
+        let index = self.keys.iter().position(|key| key.time > time).unwrap_or(self.keys.len());
+        self.keys.insert(index, Keyframe { time, value, curve });
+        self
+    }
+
+    pub fn sample(&self, time: f64) -> T {
+        let first = &self.keys[0];
+        if time <= first.time {
+            return first.value;
+        }
+
+        match self.keys.iter().position(|key| key.time > time) {
+            None => self.keys[self.keys.len() - 1].value,
+            Some(next) => {
+                let a = &self.keys[next - 1];
+                let b = &self.keys[next];
+                let t = a.curve.apply((time - a.time) / (b.time - a.time));
+                T::lerp(a.value, b.value, t)
+            }
+        }
+    }
+}
+
+/// A range of frames at a fixed frame rate, numbered from 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeline {
+    pub fps: f64,
+    pub frames: u32,
+}
+
+impl Timeline {
+    /// Scene time of `frame`, the first frame starting at time zero.
+    pub fn time(&self, frame: u32) -> f64 {
+        f64::from(frame.saturating_sub(1)) / self.fps
+    }
+
+    /// Seconds between two frames, handy for setting up a camera shutter.
+    pub fn frame_duration(&self) -> f64 {
+        self.fps.recip()
+    }
+
+    pub fn frame_path<P: AsRef<Path>>(dir: P, frame: u32) -> PathBuf {
+        dir.as_ref().join(format!("frame_{:04}.png", frame))
+    }
+
+    /// First frame in `dir` that has not been written yet. Frames are only
+    /// renamed into place once fully saved, so anything present is complete.
+    pub fn resume_from<P: AsRef<Path>>(&self, dir: P) -> u32 {
+        (1..=self.frames)
+            .find(|&frame| !Timeline::frame_path(&dir, frame).exists())
+            .unwrap_or(self.frames + 1)
+    }
+
+    /// Renders every missing frame into `dir`, building the scene for each
+    /// frame's time with `scene` and calling `written` with the number and
+    /// path of each frame once it is saved. Returns the number of frames rendered.
+    pub fn render_sequence<P, F, W>(&self, dir: P, scene: F, mut written: W) -> image::ImageResult<u32>
+    where
+        P: AsRef<Path>,
+        F: Fn(f64) -> Renderer,
+        W: FnMut(u32, &Path),
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut rendered = 0;
+        for frame in self.resume_from(dir)..=self.frames {
+            let path = Timeline::frame_path(dir, frame);
+            let partial = dir.join(format!(".frame_{:04}.partial.png", frame));
+
+            scene(self.time(frame)).render_image().save(&partial)?;
+            fs::rename(&partial, &path)?;
+
+            written(frame, &path);
+            rendered += 1;
+        }
+
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::animation::{Curve, Timeline, Track};
+
+    #[test]
+    fn test_track() {
+        let track = Track::new(2.0, 10.0, Curve::Step)
+            .key(0.0, 0.0, Curve::Linear)
+            .key(1.0, 4.0, Curve::Linear);
+
+        assert_eq!(track.sample(-1.0), 0.0);
+        assert_eq!(track.sample(0.5), 2.0);
+        assert_eq!(track.sample(1.5), 7.0);
+        assert_eq!(track.sample(3.0), 10.0);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let timeline = Timeline { fps: 24.0, frames: 48 };
+        assert_eq!(timeline.time(1), 0.0);
+        assert_eq!(timeline.time(25), 1.0);
+        assert!(Timeline::frame_path("out", 7).ends_with("frame_0007.png"));
+    }
+}
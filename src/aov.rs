@@ -0,0 +1,225 @@
+use crate::color::Color;
+use crate::vector::Vec3;
+
+use image::{ImageBuffer, Luma, Rgb};
+use std::io;
+use std::path::Path;
+
+/// Auxiliary outputs of a camera sample, recorded next to its final color.
+#[derive(Clone, Copy, Debug)]
+pub struct AovSample {
+    pub beauty: Color,
+    /// Distance to the first hit, infinite for the background.
+    pub depth: f64,
+    /// World space normal at the first hit.
+    pub normal: Vec3,
+    /// Color of the material that was hit.
+    pub albedo: Color,
+    /// Index of the shape that was hit.
+    pub object: Option<usize>,
+    /// Light reaching the camera straight from the lights off the first hit,
+    /// black for integrators that do not split it from `reflected`.
+    pub direct: Color,
+    /// Light reaching the camera through reflections and refractions at the
+    /// first hit, black for integrators that do not split it from `direct`.
+    pub reflected: Color,
+    /// How much of the point light is blocked at the first hit, 1 in full shadow.
+    pub shadow: f64,
+    /// Ambient occlusion at the first hit, 1 in the open and 0 in a crevice.
+    pub occlusion: f64,
+}
+
+impl AovSample {
+    pub fn background(color: Color) -> AovSample {
+        AovSample {
+            beauty: color,
+            depth: f64::INFINITY,
+            normal: Vec3::zero(),
+            albedo: Color::black(),
+            object: None,
+            direct: color,
+            reflected: Color::black(),
+            shadow: 0.0,
+            occlusion: 1.0,
+        }
+    }
+
+    /// Combines several samples of one pixel. Colors, normals, shadows and
+    /// occlusion are averaged, depth is averaged over the samples that hit
+    /// something and the object is the one hit by the first such sample.
+    pub fn average(samples: &[AovSample]) -> AovSample {
+        let weight = (samples.len() as f64).recip();
+        let hits: Vec<&AovSample> = samples.iter().filter(|sample| sample.object.is_some()).collect();
+
+        let mut average = AovSample::background(Color::black());
+        average.occlusion = 0.0;
+        for sample in samples {
+            average.beauty += sample.beauty * weight;
+            average.albedo += sample.albedo * weight;
+            average.direct += sample.direct * weight;
+            average.reflected += sample.reflected * weight;
+            average.normal = average.normal + sample.normal;
+            average.shadow += sample.shadow * weight;
+            average.occlusion += sample.occlusion * weight;
+        }
+
+        if !hits.is_empty() {
+            average.depth = hits.iter().map(|sample| sample.depth).sum::<f64>() / hits.len() as f64;
+            average.normal = average.normal.normalize();
+            average.object = hits[0].object;
+        }
+
+        average
+    }
+}
+
+/// The buffer a render pass writes to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    Beauty,
+    Depth,
+    Normal,
+    Albedo,
+    Object,
+    Direct,
+    Reflected,
+    Shadow,
+    Occlusion,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [
+        Aov::Beauty,
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Object,
+        Aov::Direct,
+        Aov::Reflected,
+        Aov::Shadow,
+        Aov::Occlusion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Beauty => "beauty",
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Object => "object",
+            Aov::Direct => "direct",
+            Aov::Reflected => "reflected",
+            Aov::Shadow => "shadow",
+            Aov::Occlusion => "occlusion",
+        }
+    }
+}
+
+/// Every render pass of an image, one sample per pixel in row order.
+#[derive(Debug)]
+pub struct AovBuffers {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<AovSample>,
+}
+
+impl AovBuffers {
+    /// Writes one image per pass, named `<prefix>_<pass>.<extension>`.
+    /// Colors are gamma corrected, depth is mapped from black at the camera to
+    /// white at the farthest hit, normals from `[-1, 1]` to `[0, 1]` and objects
+    /// get arbitrary but stable colors.
+    pub fn save_images(&self, prefix: &str, extension: &str, gamma: f64) -> image::ImageResult<()> {
+        let gamma_correction = gamma.recip();
+        for aov in Aov::ALL.iter() {
+            let path = format!("{}_{}.{}", prefix, aov.name(), extension);
+            match aov {
+                Aov::Depth => self.depth_image().save(path)?,
+                Aov::Shadow => self.gray_image(|sample| sample.shadow).save(path)?,
+                Aov::Occlusion => self.gray_image(|sample| sample.occlusion).save(path)?,
+                _ => self.rgb_image(|sample| AovBuffers::display_color(*aov, sample), gamma_correction).save(path)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes all passes as layers of a single float OpenEXR file.
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut channels = Vec::new();
+        let mut color = |layer: &str, get: fn(&AovSample) -> Color| {
+            for (i, channel) in ["R", "G", "B"].iter().enumerate() {
+                let values = self.pixels.iter().map(|sample| get(sample).channels()[i] as f32).collect();
+                channels.push((format!("{}{}", layer, channel), values));
+            }
+        };
+
+        // The beauty pass is the default layer, so plain viewers show it.
+        color("", |sample| sample.beauty);
+        color("albedo.", |sample| sample.albedo);
+        color("direct.", |sample| sample.direct);
+        color("reflected.", |sample| sample.reflected);
+
+        for (name, get) in [("normal.X", 0), ("normal.Y", 1), ("normal.Z", 2)].iter() {
+            let values = self.pixels.iter().map(|sample| {
+                let normal = sample.normal;
+                [normal.x, normal.y, normal.z][*get] as f32
+            }).collect();
+            channels.push((name.to_string(), values));
+        }
+
+        channels.push(("depth.Z".to_string(), self.pixels.iter().map(|sample| sample.depth as f32).collect()));
+        channels.push((
+            "object.id".to_string(),
+            self.pixels.iter().map(|sample| sample.object.map_or(-1.0, |id| id as f32)).collect(),
+        ));
+        channels.push(("shadow.Y".to_string(), self.pixels.iter().map(|sample| sample.shadow as f32).collect()));
+        channels.push((
+            "occlusion.Y".to_string(),
+            self.pixels.iter().map(|sample| sample.occlusion as f32).collect(),
+        ));
+
+        crate::exr::write(path, self.width, self.height, &channels)
+    }
+
+    fn display_color(aov: Aov, sample: &AovSample) -> Color {
+        match aov {
+            Aov::Beauty => sample.beauty,
+            Aov::Albedo => sample.albedo,
+            Aov::Direct => sample.direct,
+            Aov::Reflected => sample.reflected,
+            Aov::Normal => {
+                let n = sample.normal;
+                Color::new(n.x * 0.5 + 0.5, n.y * 0.5 + 0.5, n.z * 0.5 + 0.5)
+            }
+            Aov::Object => match sample.object {
+                None => Color::black(),
+                Some(id) => {
+                    // Scatter neighbouring ids over the color cube
+                    let hash = (id as u32 + 1).wrapping_mul(0x9E37_79B9);
+                    Color::from_u8((hash >> 24) as u8, (hash >> 16) as u8, (hash >> 8) as u8)
+                }
+            },
+            Aov::Depth | Aov::Shadow | Aov::Occlusion => Color::black(),
+        }
+    }
+
+    fn rgb_image<F: Fn(&AovSample) -> Color>(&self, get: F, gamma_correction: f64) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            get(&self.pixels[(y * self.width + x) as usize]).gamma_rgb(gamma_correction)
+        })
+    }
+
+    fn gray_image<F: Fn(&AovSample) -> f64>(&self, get: F) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            Luma([(get(&self.pixels[(y * self.width + x) as usize]).clamp(0.0, 1.0) * 255.0) as u8])
+        })
+    }
+
+    fn depth_image(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let far = self.pixels.iter()
+            .map(|sample| sample.depth)
+            .filter(|depth| depth.is_finite())
+            .fold(0.0, f64::max);
+
+        self.gray_image(|sample| if sample.depth.is_finite() && far > 0.0 { sample.depth / far } else { 1.0 })
+    }
+}
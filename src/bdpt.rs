@@ -0,0 +1,670 @@
+use crate::bsdf::{self, Bsdf, Frame};
+use crate::cfg::Cfg;
+use crate::color::Color;
+use crate::light::{Light, LightType};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shapes::Shape;
+use crate::vector::Vec3;
+
+use std::f64::consts::PI;
+
+/// Something a light subpath can start from.
+#[derive(Clone, Copy, Debug)]
+enum Emitter<'a> {
+    Point(&'a Light),
+    /// Index of an emissive shape that can be sampled.
+    Shape(usize),
+}
+
+/// What every connection needs to know about the scene.
+struct Context<'a> {
+    objects: &'a [Box<dyn Shape>],
+    emitters: Vec<Emitter<'a>>,
+    options: &'a Cfg,
+    time: f64,
+}
+
+/// A point on a camera or light subpath. Densities are per unit area, the
+/// forward one for how the subpath reached this vertex and the reverse one
+/// for how the other subpath would have.
+#[derive(Clone, Copy, Debug)]
+struct Vertex {
+    point: Vec3,
+    /// Zero for the camera and point lights, which have no surface.
+    normal: Vec3,
+    /// Scattering at surface vertices, `None` at the camera and on lights.
+    bsdf: Option<Bsdf>,
+    /// Emission of a surface, or what a light vertex gives off.
+    emission: Color,
+    object: Option<usize>,
+    beta: Color,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    light: bool,
+    /// Mirror-like surface, which cannot be connected to.
+    delta: bool,
+    /// Point light, which no ray can hit.
+    delta_light: bool,
+}
+
+impl Vertex {
+    fn camera(point: Vec3) -> Vertex {
+        Vertex {
+            point,
+            normal: Vec3::zero(),
+            bsdf: None,
+            emission: Color::black(),
+            object: None,
+            beta: Color::white(),
+            pdf_fwd: 1.0,
+            pdf_rev: 0.0,
+            light: false,
+            delta: false,
+            delta_light: false,
+        }
+    }
+
+    fn has_surface(&self) -> bool {
+        self.normal.norm() > 0.0
+    }
+
+    fn direction_to(&self, other: &Vertex) -> Vec3 {
+        (other.point - self.point).normalize()
+    }
+
+    /// Turns a solid angle density at this vertex into an area density at `next`.
+    fn convert(&self, pdf: f64, next: &Vertex) -> f64 {
+        let offset = next.point - self.point;
+        let mut pdf = pdf / offset.norm();
+        if next.has_surface() {
+            pdf *= next.normal.dot(offset.normalize()).abs();
+        }
+        pdf
+    }
+
+    /// Area density with which this vertex, reached from `prev`, samples `next`.
+    fn pdf(&self, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if self.light {
+            return self.pdf_light(next);
+        }
+        match (self.bsdf, prev) {
+            (Some(bsdf), Some(prev)) => {
+                let pdf = bsdf.pdf(self.normal, self.direction_to(prev), self.direction_to(next));
+                self.convert(pdf, next)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Area density with which an emitter at this vertex shoots light at `next`:
+    /// point lights uniformly in all directions, surfaces cosine weighted from
+    /// either of their sides.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let pdf = if self.has_surface() {
+            self.normal.dot(self.direction_to(next)).abs() / (2.0 * PI)
+        } else {
+            1.0 / (4.0 * PI)
+        };
+        self.convert(pdf, next)
+    }
+
+    /// Cosine of the angle to `other`, 1 for vertices without a surface.
+    fn cos(&self, other: &Vertex) -> f64 {
+        if self.has_surface() {
+            self.normal.dot(self.direction_to(other)).abs()
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Radiance along `ray` by bidirectional path tracing. Every sample traces a
+/// subpath from the camera and one from a randomly picked point light or
+/// emissive shape, then joins every vertex of the one to every vertex of the
+/// other, weighting each of these ways of building a path with the balance
+/// heuristic. Light subpaths are not joined to the camera itself, as that
+/// would land in other pixels.
+///
+/// Point lights do not fall off with distance in this renderer, so their
+/// light is scaled by the square of the distance to the first surface it
+/// reaches, as for the photon map. Ambient light and the background are only
+/// found by camera paths leaving the scene, like in `path::trace`.
+pub fn trace(
+    ray: Ray,
+    objects: &[Box<dyn Shape>],
+    lights: &[Light],
+    options: &Cfg,
+    background: Color,
+    sampler: &mut Sampler,
+) -> Color {
+    let max_depth = usize::from(options.max_rays);
+    let context = Context {
+        objects,
+        emitters: emitters(objects, lights),
+        options,
+        time: ray.time,
+    };
+
+    let mut radiance = Color::black();
+
+    let mut camera = vec![Vertex::camera(ray.origin)];
+    if let Some(beta) = walk(objects, ray, Color::white(), 1.0, &mut camera, max_depth + 2, sampler) {
+        radiance += beta * if camera.len() == 1 { background } else { Light::ambient(lights) };
+    }
+
+    let light = light_subpath(objects, &context.emitters, ray.time, max_depth + 1, sampler);
+
+    for t in 2..=camera.len() {
+        let pt = &camera[t - 1];
+
+        // Light focused by mirrors onto the first hit can only be found by
+        // joining it to the camera, so it comes from the photon map instead.
+        // Deeper vertices get it by joining light subpaths to them.
+        if let (Some(caustics), Some(bsdf), false, 2) = (&options.caustics, pt.bsdf, pt.delta, t) {
+            let wo = pt.direction_to(&camera[t - 2]);
+            radiance += pt.beta * caustics.radiance(pt.point, pt.normal, |wi| bsdf.eval(pt.normal, wo, wi));
+        }
+
+        for s in 0..=light.len() {
+            if s + t - 2 > max_depth {
+                break;
+            }
+            radiance += connect(&context, &light, &camera, s, t);
+        }
+    }
+
+    radiance
+}
+
+fn emitters<'a>(objects: &[Box<dyn Shape>], lights: &'a [Light]) -> Vec<Emitter<'a>> {
+    let mut emitters: Vec<Emitter> = lights
+        .iter()
+        .filter(|light| matches!(light.light_type, LightType::Point))
+        .map(Emitter::Point)
+        .collect();
+
+    for (index, shape) in objects.iter().enumerate() {
+        if shape.area() > 0.0 && shape.material().is_emissive() {
+            emitters.push(Emitter::Shape(index));
+        }
+    }
+
+    emitters
+}
+
+/// Area density of a light subpath starting at a surface vertex.
+fn pdf_light_origin(context: &Context, vertex: &Vertex) -> f64 {
+    let emitters = &context.emitters;
+    let sampled = emitters.iter().any(|emitter| matches!(emitter, Emitter::Shape(index) if Some(*index) == vertex.object));
+    match vertex.object {
+        Some(index) if sampled => 1.0 / (emitters.len() as f64 * context.objects[index].area()),
+        _ => 0.0,
+    }
+}
+
+fn light_subpath(
+    objects: &[Box<dyn Shape>],
+    emitters: &[Emitter],
+    time: f64,
+    max_vertices: usize,
+    sampler: &mut Sampler,
+) -> Vec<Vertex> {
+    if emitters.is_empty() {
+        return Vec::new();
+    }
+
+    let choice = ((sampler.next_f64() * emitters.len() as f64) as usize).min(emitters.len() - 1);
+    let pdf_choice = 1.0 / emitters.len() as f64;
+
+    let (origin, direction, pdf_dir) = match emitters[choice] {
+        Emitter::Point(light) => {
+            let z = 1.0 - 2.0 * sampler.next_f64();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * sampler.next_f64();
+            let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+
+            let emission = light.color * light.intensity;
+            let origin = Vertex {
+                point: light.position,
+                normal: Vec3::zero(),
+                bsdf: None,
+                emission,
+                object: None,
+                beta: emission * pdf_choice.recip(),
+                pdf_fwd: pdf_choice,
+                pdf_rev: 0.0,
+                light: true,
+                delta: false,
+                delta_light: true,
+            };
+            (origin, direction, 1.0 / (4.0 * PI))
+        }
+        Emitter::Shape(index) => {
+            let shape = &objects[index];
+            let (point, normal) = match shape.sample_surface(sampler) {
+                Some(sample) => sample,
+                None => return Vec::new(),
+            };
+
+            let mut direction = Frame::new(normal).to_world(bsdf::sample_cosine(sampler));
+            if sampler.next_f64() < 0.5 {
+                direction = -direction;
+            }
+
+            let pdf_pos = pdf_choice / shape.area();
+            let emission = shape.material().emission;
+            let origin = Vertex {
+                point,
+                normal,
+                bsdf: None,
+                emission,
+                object: Some(index),
+                beta: emission * pdf_pos.recip(),
+                pdf_fwd: pdf_pos,
+                pdf_rev: 0.0,
+                light: true,
+                delta: false,
+                delta_light: false,
+            };
+            (origin, direction, normal.dot(direction).abs() / (2.0 * PI))
+        }
+    };
+
+    let cos = if origin.has_surface() { origin.normal.dot(direction).abs() } else { 1.0 };
+    let beta = origin.beta * (cos / pdf_dir);
+    let side = if direction.dot(origin.normal) < 0.0 { -origin.normal } else { origin.normal };
+    let ray = Ray {
+        origin: origin.point.correct(side),
+        direction,
+        time,
+    };
+
+    let mut vertices = vec![origin];
+    walk(objects, ray, beta, pdf_dir, &mut vertices, max_vertices, sampler);
+    vertices
+}
+
+/// Extends a subpath by sampling BSDFs until it has `max_vertices` vertices,
+/// returning its throughput if it leaves the scene.
+fn walk(
+    objects: &[Box<dyn Shape>],
+    mut ray: Ray,
+    mut beta: Color,
+    mut pdf_dir: f64,
+    vertices: &mut Vec<Vertex>,
+    max_vertices: usize,
+    sampler: &mut Sampler,
+) -> Option<Color> {
+    let point_light = vertices[0].delta_light;
+    while vertices.len() < max_vertices {
+        let intersection = match Ray::intersect(ray, objects) {
+            Some(intersection) => intersection,
+            None => return Some(beta),
+        };
+
+        if point_light && vertices.len() == 1 {
+            beta = beta * (intersection.distance * intersection.distance);
+        }
+
+        let bsdf = intersection.material.bsdf();
+        let previous = vertices[vertices.len() - 1];
+        let mut vertex = Vertex {
+            point: intersection.hit_point,
+            normal: intersection.normal,
+            bsdf: Some(bsdf),
+            emission: intersection.material.emission,
+            object: Some(intersection.object),
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            light: false,
+            delta: bsdf.is_specular(),
+            delta_light: false,
+        };
+        vertex.pdf_fwd = previous.convert(pdf_dir, &vertex);
+        vertices.push(vertex);
+
+        if vertices.len() >= max_vertices {
+            break;
+        }
+
+        let wo = -ray.direction;
+        let sample = match bsdf.sample(intersection.normal, wo, sampler) {
+            Some(sample) => sample,
+            None => break,
+        };
+
+        let mut pdf_rev = bsdf.pdf(intersection.normal, sample.direction, wo);
+        pdf_dir = sample.pdf;
+        if vertex.delta {
+            pdf_dir = 0.0;
+            pdf_rev = 0.0;
+        }
+        beta = beta * sample.weight;
+
+        let count = vertices.len();
+        vertices[count - 2].pdf_rev = vertex.convert(pdf_rev, &vertices[count - 2]);
+
+        let side = if sample.direction.dot(intersection.normal) < 0.0 { -intersection.normal } else { intersection.normal };
+        ray = Ray {
+            origin: intersection.hit_point.correct(side),
+            direction: sample.direction,
+            time: ray.time,
+        };
+    }
+
+    None
+}
+
+fn visible(context: &Context, from: &Vertex, to: &Vertex) -> bool {
+    let direction = from.direction_to(to);
+    let origin = if !from.has_surface() {
+        from.point
+    } else if direction.dot(from.normal) < 0.0 {
+        from.point.correct(-from.normal)
+    } else {
+        from.point.correct(from.normal)
+    };
+
+    let distance = (to.point - origin).length();
+    let ray = Ray { origin, direction, time: context.time };
+    !Ray::intersect(ray, context.objects).is_some_and(|hit| hit.distance < distance * (1.0 - 1e-4))
+}
+
+/// Contribution of the path made of the first `s` light and `t` camera vertices.
+fn connect(context: &Context, light: &[Vertex], camera: &[Vertex], s: usize, t: usize) -> Color {
+    let pt = &camera[t - 1];
+    let pt_bsdf = match pt.bsdf {
+        Some(bsdf) => bsdf,
+        None => return Color::black(),
+    };
+
+    if s == 0 {
+        if !pt.emission.channels().iter().any(|channel| *channel > 0.0) {
+            return Color::black();
+        }
+        // Emitters that cannot be sampled are only ever found this way.
+        let weight = if pdf_light_origin(context, pt) > 0.0 {
+            mis_weight(context, light, camera, s, t)
+        } else {
+            1.0
+        };
+        return pt.beta * pt.emission * weight;
+    }
+
+    let qs = &light[s - 1];
+    if pt.delta || qs.delta {
+        return Color::black();
+    }
+
+    let wo = pt.direction_to(&camera[t - 2]);
+    let f_pt = pt_bsdf.eval(pt.normal, wo, pt.direction_to(qs));
+
+    let contribution = if s == 1 {
+        if qs.delta_light {
+            // The distance falloff cancels with the point light's scaling.
+            qs.beta * f_pt * pt.cos(qs)
+        } else {
+            qs.beta * f_pt * (pt.cos(qs) * qs.cos(pt) / (qs.point - pt.point).norm())
+        }
+    } else {
+        let qs_bsdf = match qs.bsdf {
+            Some(bsdf) => bsdf,
+            None => return Color::black(),
+        };
+        let f_qs = qs_bsdf.eval(qs.normal, qs.direction_to(pt), qs.direction_to(&light[s - 2]));
+        qs.beta * f_qs * f_pt * (pt.cos(qs) * qs.cos(pt) / (qs.point - pt.point).norm())
+    };
+
+    let contribution = pt.beta * contribution;
+    if !contribution.channels().iter().any(|channel| *channel > 0.0) {
+        return Color::black();
+    }
+
+    let shadows = s > 1 || context.options.shadows;
+    if shadows && !visible(context, pt, qs) {
+        return Color::black();
+    }
+
+    contribution * mis_weight(context, light, camera, s, t)
+}
+
+/// Balance heuristic weight of the `(s, t)` strategy against every other way
+/// the same path could have been built, except joining a light subpath
+/// straight to the camera, which this integrator never does.
+fn mis_weight(context: &Context, light: &[Vertex], camera: &[Vertex], s: usize, t: usize) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let mut light = light[..s].to_vec();
+    let mut camera = camera[..t].to_vec();
+
+    // Densities at the joined vertices and their neighbours, as if the path
+    // had been traced across the connection.
+    let pt = camera[t - 1];
+    let pt_minus = camera[t - 2];
+    camera[t - 1].delta = false;
+    if s > 0 {
+        let qs = light[s - 1];
+        let qs_minus = if s > 1 { Some(light[s - 2]) } else { None };
+
+        camera[t - 1].pdf_rev = qs.pdf(qs_minus.as_ref(), &pt);
+        camera[t - 2].pdf_rev = pt.pdf(Some(&qs), &pt_minus);
+        light[s - 1].pdf_rev = pt.pdf(Some(&pt_minus), &qs);
+        if let Some(qs_minus) = qs_minus {
+            light[s - 2].pdf_rev = qs.pdf(Some(&pt), &qs_minus);
+        }
+    } else {
+        camera[t - 1].pdf_rev = pdf_light_origin(context, &pt);
+        camera[t - 2].pdf_rev = pt.pdf_light(&pt_minus);
+    }
+
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+        if i > 1 && !camera[i].delta && !camera[i - 1].delta {
+            sum += ratio;
+        }
+    }
+
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+        let delta_light = if i > 0 { light[i - 1].delta } else { light[0].delta_light };
+        if !light[i].delta && !delta_light {
+            sum += ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdpt::{self, emitters, mis_weight, Context, Vertex};
+    use crate::bsdf::Bsdf;
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::light::{Light, LightType};
+    use crate::material::Material;
+    use crate::path;
+    use crate::photon::{PhotonMap, PhotonMapping};
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::shapes::mesh::Mesh;
+    use crate::shapes::plane::Plane;
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    use std::sync::Arc;
+
+    /// A grey box open towards +z, lit by a glowing ball inside of it.
+    fn lit_box() -> Vec<Box<dyn Shape>> {
+        let grey = Material {
+            bsdf: Some(Bsdf::Lambertian { albedo: Color::white() * 0.6 }),
+            ..Material::neutral()
+        };
+        let wall = |position: Vec3, normal: Vec3| -> Box<dyn Shape> { Box::new(Plane { position, normal, material: grey }) };
+
+        vec![
+            wall(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            wall(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            wall(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            wall(Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+            wall(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0)),
+            Box::new(Sphere {
+                position: Vec3::new(0.0, 0.4, -0.3),
+                radius: 0.25,
+                material: Material {
+                    bsdf: Some(Bsdf::Lambertian { albedo: Color::black() }),
+                    emission: Color::white() * 4.0,
+                    ..Material::neutral()
+                },
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_converges_to_path_tracing() {
+        let objects = lit_box();
+        let options = Cfg { max_rays: 3, ..Cfg::default() };
+        let runs = 10000;
+
+        for direction in [Vec3::new(0.0, -0.4, -1.0), Vec3::new(0.5, 0.2, -1.0), Vec3::new(-0.3, 0.0, -1.0)] {
+            let ray = Ray {
+                origin: Vec3::new(0.0, 0.0, 2.5),
+                direction: direction.normalize(),
+                time: 0.0,
+            };
+            let mut sampler = Sampler::new(7);
+            let mean = |trace: &dyn Fn(&mut Sampler) -> Color, sampler: &mut Sampler| {
+                (0..runs).map(|_| trace(sampler).channels()[0]).sum::<f64>() / f64::from(runs)
+            };
+
+            let expected = mean(&|sampler| path::trace(ray, &objects, &[], &options, Color::black(), sampler), &mut sampler);
+            let found = mean(&|sampler| bdpt::trace(ray, &objects, &[], &options, Color::black(), sampler), &mut sampler);
+            assert!((found - expected).abs() < expected * 0.05, "{} against {}", found, expected);
+        }
+    }
+
+    /// Vertices along `points`, each surface one with its normal, with the
+    /// densities of tracing them in that order from `origin`.
+    fn subpath(origin: Vertex, points: &[(Vec3, Vec3, Option<usize>)]) -> Vec<Vertex> {
+        let mut vertices = vec![origin];
+        for (point, normal, object) in points {
+            let previous = vertices.len().checked_sub(2).map(|i| vertices[i]);
+            let last = vertices[vertices.len() - 1];
+            let mut vertex = Vertex {
+                point: *point,
+                normal: *normal,
+                bsdf: Some(Bsdf::Lambertian { albedo: Color::white() }),
+                object: *object,
+                light: false,
+                ..Vertex::camera(*point)
+            };
+            vertex.pdf_fwd = match previous {
+                Some(previous) => last.pdf(Some(&previous), &vertex),
+                None if last.light => last.pdf_light(&vertex),
+                None => last.convert(1.0, &vertex),
+            };
+            vertices.push(vertex);
+        }
+
+        for i in 0..vertices.len().saturating_sub(2) {
+            let (back, next) = (vertices[i], vertices[i + 2]);
+            vertices[i].pdf_rev = vertices[i + 1].pdf(Some(&next), &back);
+        }
+        vertices
+    }
+
+    #[test]
+    fn test_mis_weights() {
+        // Camera, floor, wall, and a point on the glowing ball.
+        let objects = lit_box();
+        let context = Context {
+            objects: &objects,
+            emitters: emitters(&objects, &[]),
+            options: &Cfg::default(),
+            time: 0.0,
+        };
+        let camera = Vec3::new(0.0, 0.5, 2.5);
+        let floor = (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Some(0));
+        let wall = (Vec3::new(1.0, 0.0, -0.2), Vec3::new(-1.0, 0.0, 0.0), Some(3));
+        let ball = (Vec3::new(0.25, 0.4, -0.3), Vec3::new(1.0, 0.0, 0.0), Some(5));
+
+        let camera_path = subpath(Vertex::camera(camera), &[floor, wall, ball]);
+        let light_origin = Vertex {
+            point: ball.0,
+            normal: ball.1,
+            bsdf: None,
+            object: ball.2,
+            pdf_fwd: 1.0 / (context.emitters.len() as f64 * objects[5].area()),
+            light: true,
+            ..Vertex::camera(ball.0)
+        };
+        let light_path = subpath(light_origin, &[wall, floor]);
+
+        // Every way of building the path but joining it to the camera itself.
+        let total: f64 = (0..=2).map(|s| mis_weight(&context, &light_path, &camera_path, s, 4 - s)).sum();
+        assert!((total - 1.0).abs() < 1e-9, "{}", total);
+    }
+
+    #[test]
+    fn test_caustics_counted_once() {
+        // A mirror above a point light throws a caustic on the floor, away
+        // from the spot on the back wall the camera looks at. Light reaching
+        // the camera by way of the caustic is found by joining light subpaths,
+        // so the photon map may only add what lands where the camera looks.
+        let grey = Material {
+            bsdf: Some(Bsdf::Lambertian { albedo: Color::white() * 0.8 }),
+            ..Material::neutral()
+        };
+        let mirror = Material {
+            bsdf: Some(Bsdf::Conductor { eta: Color::white() * 0.2, k: Color::white() * 3.0, roughness: 0.0 }),
+            ..Material::neutral()
+        };
+        let objects: Vec<Box<dyn Shape>> = vec![
+            Box::new(Plane { position: Vec3::zero(), normal: Vec3::new(0.0, 1.0, 0.0), material: grey }),
+            Box::new(Plane { position: Vec3::new(0.0, 0.0, -5.0), normal: Vec3::new(0.0, 0.0, 1.0), material: grey }),
+            Box::new(Mesh::new(
+                vec![Vec3::new(-0.4, 2.5, -0.4), Vec3::new(0.4, 2.5, -0.4), Vec3::new(0.0, 2.5, 0.4)],
+                vec![[0, 1, 2]],
+                mirror,
+            )),
+        ];
+        let lights = vec![Light {
+            light_type: LightType::Point,
+            position: Vec3::new(0.0, 2.0, 0.0),
+            intensity: 1.0,
+            color: Color::white(),
+        }];
+        let settings = PhotonMapping { photons: 20000, ..PhotonMapping::default() };
+        let caustics = PhotonMap::build(&objects, &lights, &settings);
+        assert!(!caustics.is_empty());
+
+        let without = Cfg { max_rays: 3, ..Cfg::default() };
+        let with = Cfg { caustics: Some(Arc::new(caustics)), ..without.clone() };
+        let ray = Ray {
+            origin: Vec3::new(0.0, 1.0, 4.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let runs = 10000;
+        let mean = |options: &Cfg| {
+            let mut sampler = Sampler::new(11);
+            (0..runs)
+                .map(|_| bdpt::trace(ray, &objects, &lights, options, Color::black(), &mut sampler).channels()[0])
+                .sum::<f64>()
+                / f64::from(runs)
+        };
+
+        let (expected, found) = (mean(&without), mean(&with));
+        assert!((found - expected).abs() < expected * 1e-9, "{} against {}", found, expected);
+    }
+}
@@ -1,9 +1,18 @@
+use rusty_tracer::adaptive::AdaptiveSampling;
+use rusty_tracer::animation::{Curve, Timeline, Track};
 use rusty_tracer::camera::Camera;
 use rusty_tracer::color::Color;
+use rusty_tracer::denoise::Denoiser;
+use rusty_tracer::export::{self, ExportOptions};
+use rusty_tracer::import::{self, ImportOptions, SceneOptions};
 use rusty_tracer::light::Light;
 use rusty_tracer::light::LightType;
 use rusty_tracer::material::Material;
-use rusty_tracer::cfg::Cfg;
+use rusty_tracer::medium::Medium;
+use rusty_tracer::bsdf::Bsdf;
+use rusty_tracer::cfg::{Cfg, IntegratorKind};
+use rusty_tracer::occlusion::AmbientOcclusion;
+use rusty_tracer::photon::{PhotonMap, PhotonMapping};
 use rusty_tracer::renderer::Renderer;
 use rusty_tracer::vector::Vec3;
 use rusty_tracer::shapes::sphere::Sphere;
@@ -11,73 +20,30 @@ use rusty_tracer::shapes::plane::Plane;
 use rusty_tracer::shapes::aabb::Aabb;
 use rusty_tracer::shapes::naabb::Naabb;
 use rusty_tracer::shapes::triangle::Triangle;
+use rusty_tracer::shapes::volume::{self, GridVolume, RawFormat};
+use rusty_tracer::shapes::voxels::VoxelGrid;
 use rusty_tracer::rotate::Rotation;
 
-use pixels::{wgpu::Surface, Pixels, SurfaceTexture};
-use winit::dpi::LogicalSize;
-use winit::event::{Event, VirtualKeyCode};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
-use winit_input_helper::WinitInputHelper;
-use std::time::Instant;
-
-fn display(width: u32, height: u32, buffer: Vec<u32>) {
-    let event_loop = EventLoop::new();
-    let mut input = WinitInputHelper::new();
-    let window = {
-        let size = LogicalSize::new(width as f64 / 2.0, height as f64 / 2.0);
-        WindowBuilder::new()
-            .with_title("Rusty Tracer")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
-    };
+use std::sync::Arc;
 
-    let mut hidpi_factor = window.scale_factor();
+mod navigation;
+mod overlay;
+mod viewer;
 
-    let mut pixels = {
-        let surface = Surface::create(&window);
-        let surface_texture = SurfaceTexture::new(width, height, surface);
-        Pixels::new(width, height, surface_texture).unwrap()
-    };
-    let mut frame = pixels.get_frame();
-    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-        let c = buffer[i];
-        pixel.copy_from_slice(&[((c >> 16) & 255) as u8, ((c >> 8) & 255) as u8, (c & 255) as u8, 255]);
-    }
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
 
-    event_loop.run(move |event, _, control_flow| {
-        // Draw the current frame
-        if let Event::RedrawRequested(_) = event {
-            if !pixels.render().is_ok() {
-                *control_flow = ControlFlow::Exit;
-                return;
-            }
-        }
-        // Handle input events
-        if input.update(event) {
-            // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                *control_flow = ControlFlow::Exit;
-                return;
-            }
-            // Adjust high DPI factor
-            if let Some(factor) = input.scale_factor_changed() {
-                hidpi_factor = factor;
-            }
-            // Resize the window
-            if let Some(size) = input.window_resized() {
-                pixels.resize(size.width, size.height);
-            }
-            // Update internal state and request a redraw
-            window.request_redraw();
-        }
-    });
+/// Polished gold, from its measured complex index of refraction.
+fn gold() -> Bsdf {
+    Bsdf::Conductor {
+        eta: Color::new(0.143, 0.374, 1.442),
+        k: Color::new(3.983, 2.385, 1.603),
+        roughness: 0.25,
+    }
 }
 
-
-fn main() {
+/// The demo scene at `time` seconds into its animation.
+fn scene(time: f64) -> Renderer {
     let options = Cfg {
         max_rays: 4,
         gamma: 0.85,
@@ -86,17 +52,24 @@ fn main() {
         shadows: true,
         reflections: true,
         opacity: true,
+        ..Cfg::default()
     };
 
-    let width = 1920;
-    let height = 1080;
+    let width = WIDTH;
+    let height = HEIGHT;
     let aspect_ratio = width as f64 / height as f64;
 
-    let renderer = Renderer {
+    let camera_origin = Track::new(0.0, Vec3::new(-10., -3., 10.), Curve::Smooth)
+        .key(4.0, Vec3::new(10., -3., 10.), Curve::Smooth);
+    let purple_light = Track::new(0.0, 0.8, Curve::Linear)
+        .key(2.0, 0.2, Curve::Linear)
+        .key(4.0, 0.8, Curve::Linear);
+
+    Renderer {
         width,
         height,
         camera: Camera::new(
-            Vec3::new(-10., -3., 10.),
+            camera_origin.sample(time),
             Vec3::new(0., 0., -20.),
             60.,
             aspect_ratio,
@@ -113,7 +86,8 @@ fn main() {
                     specular: 50.0,
                     specular_exponent: 5.0,
                     reflectiveness: 0.5,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
             Box::new(Naabb {
@@ -126,6 +100,9 @@ fn main() {
                     specular_exponent: 5.0,
                     reflectiveness: 0.6,
                     opacity: 1.0,
+                    roughness: 0.25,
+                    bsdf: Some(gold()),
+                    ..Material::neutral()
                 },
                 rotation: Rotation::new(45.0, 0.0, 0.0),
             }),
@@ -139,6 +116,9 @@ fn main() {
                     specular_exponent: 5.0,
                     reflectiveness: 0.6,
                     opacity: 1.0,
+                    roughness: 0.25,
+                    bsdf: Some(gold()),
+                    ..Material::neutral()
                 },
             }),
             Box::new(Sphere {
@@ -150,7 +130,8 @@ fn main() {
                     specular: 50.0,
                     specular_exponent: 100.0,
                     reflectiveness: 0.0,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
             Box::new(Sphere {
@@ -162,7 +143,8 @@ fn main() {
                     specular: 50.0,
                     specular_exponent: 100.0,
                     reflectiveness: 0.0,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
             Box::new(Sphere {
@@ -174,7 +156,8 @@ fn main() {
                     specular: 5.0,
                     specular_exponent: 500.0,
                     reflectiveness: 0.0,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
             Box::new(Sphere {
@@ -186,7 +169,8 @@ fn main() {
                     specular: 0.2,
                     specular_exponent: 2.0,
                     reflectiveness: 0.0,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
             Box::new(Sphere {
@@ -198,7 +182,8 @@ fn main() {
                     specular: 3.0,
                     specular_exponent: 50.0,
                     reflectiveness: 0.0,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
             Box::new(Sphere {
@@ -210,7 +195,28 @@ fn main() {
                     specular: 50.0,
                     specular_exponent: 100.0,
                     reflectiveness: 1.0,
-                    opacity: 1.0
+                    opacity: 1.0,
+                    ..Material::neutral()
+                },
+            }),
+            Box::new(Sphere {
+                position: Vec3::new(12.0, 14.0, -25.0),
+                radius: 1.5,
+                material: Material {
+                    color: Color::white(),
+                    emission: Color::new(4.0, 3.2, 2.4),
+                    ..Material::neutral()
+                },
+            }),
+            Box::new(Sphere {
+                position: Vec3::new(-4.0, -5.5, -8.0),
+                radius: 2.5,
+                material: Material {
+                    color: Color::white(),
+                    reflectiveness: 0.05,
+                    opacity: 0.0,
+                    bsdf: Some(Bsdf::Dielectric { ior: 1.5, roughness: 0.0, tint: Color::white() }),
+                    ..Material::neutral()
                 },
             }),
             Box::new(Plane {
@@ -222,7 +228,9 @@ fn main() {
                     specular: 0.2,
                     specular_exponent: 5.0,
                     reflectiveness: 0.6,
-                    opacity: 1.0
+                    roughness: 0.15,
+                    opacity: 1.0,
+                    ..Material::neutral()
                 },
             }),
         ],
@@ -236,7 +244,7 @@ fn main() {
             Light {
                 light_type: LightType::Point,
                 position: Vec3::new(40.0, 20.0, 20.0),
-                intensity: 0.8,
+                intensity: purple_light.sample(time),
                 color: Color::new(0.66, 0.0, 0.66),
             },
             Light {
@@ -254,23 +262,246 @@ fn main() {
         ],
         bg_color: Color::black(),
         options,
+    }
+}
+
+/// Reads a raw grid of `dims` samples, stored as bytes or as 32 bit floats
+/// depending on the size of the file.
+fn read_grid(path: &str, dims: (usize, usize, usize)) -> std::io::Result<ndarray::Array3<f64>> {
+    let samples = (dims.0 * dims.1 * dims.2) as u64;
+    let format = if std::fs::metadata(path)?.len() == samples * 4 {
+        RawFormat::F32
+    } else {
+        RawFormat::U8
+    };
+    volume::read_raw(path, dims, format)
+}
+
+/// Smoke from the density grid at `path` in a box next to the glass sphere,
+/// burning where `fire` gives temperatures in kelvin.
+fn load_volume(path: &str, fire: Option<String>, dims: (usize, usize, usize)) -> std::io::Result<GridVolume> {
+    let medium = Medium {
+        absorption: Color::new(0.2, 0.2, 0.2),
+        scattering: Color::new(2.0, 2.0, 2.0),
+        anisotropy: 0.2,
     };
+    let volume = GridVolume::new(read_grid(path, dims)?, Vec3::new(-13.0, -8.0, -10.0), Vec3::new(8.0, 8.0, 8.0), medium);
+
+    match fire {
+        Some(fire) => Ok(volume.with_temperatures(read_grid(&fire, dims)?, 0.5)),
+        None => Ok(volume),
+    }
+}
+
+/// Value following `flag` on the command line, if given.
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // `--sequence <dir> [--frames <n>] [--fps <n>]` renders numbered frames
+    // instead of opening the viewer, picking up after the last finished frame.
+    if let Some(dir) = arg_value(&args, "--sequence") {
+        let timeline = Timeline {
+            fps: arg_value(&args, "--fps").and_then(|v| v.parse().ok()).unwrap_or(24.0),
+            frames: arg_value(&args, "--frames").and_then(|v| v.parse().ok()).unwrap_or(96),
+        };
+
+        match timeline.render_sequence(&dir, scene, |_, path| println!("Wrote {}", path.display())) {
+            Ok(rendered) => println!("Rendered {} frames into {}", rendered, dir),
+            Err(err) => println!("Failed to render sequence. Encountered error {}", err),
+        }
+        return;
+    }
 
-    let now = Instant::now();
+    // `--aovs <prefix>` writes every render pass as images and a layered EXR.
+    if let Some(prefix) = arg_value(&args, "--aovs") {
+        let renderer = scene(0.0);
+        let buffers = renderer.render_aovs();
 
-    //let buf: Vec<u8> = renderer.render();
+        match buffers.save_images(&prefix, "png", renderer.options.gamma) {
+            Ok(()) => println!("Saved passes to {}_*.png", prefix),
+            Err(err) => println!("Failed to save passes. Encountered error {}", err),
+        }
+        match buffers.save_exr(format!("{}.exr", prefix)) {
+            Ok(()) => println!("Saved layers to {}.exr", prefix),
+            Err(err) => println!("Failed to save layers. Encountered error {}", err),
+        }
+
+        // `--denoise` also writes the beauty pass filtered with the other passes as guides.
+        if args.iter().any(|arg| arg == "--denoise") {
+            let path = format!("{}_denoised.png", prefix);
+            match Denoiser::default().denoise_image(&buffers, renderer.options.gamma).save(&path) {
+                Ok(()) => println!("Saved denoised image to {}", path),
+                Err(err) => println!("Failed to save denoised image. Encountered error {}", err),
+            }
+        }
+        return;
+    }
+
+    // `--sample-map <path>` renders with adaptive sampling and saves a heat map
+    // of the samples every pixel needed, and the image itself beside it.
+    if let Some(path) = arg_value(&args, "--sample-map") {
+        let mut renderer = scene(0.0);
+        renderer.options.adaptive = Some(AdaptiveSampling::default());
+        let (image, map) = renderer.render_image_with_counts();
+
+        match map.save(&path) {
+            Ok(()) => println!("Saved sample map to {}", path),
+            Err(err) => println!("Failed to save sample map. Encountered error {}", err),
+        }
+        let image_path = format!("{}_image.png", path.trim_end_matches(".png"));
+        match image.save(&image_path) {
+            Ok(()) => println!("Saved image to {}", image_path),
+            Err(err) => println!("Failed to save image. Encountered error {}", err),
+        }
+        return;
+    }
+
+    let mut renderer = scene(0.0);
+
+    // `--scene <path>` renders a glTF, GLB, pbrt or POV-Ray scene in place of
+    // the built in one, keeping the integrator and ray depth the scene asks for.
+    if let Some(path) = arg_value(&args, "--scene") {
+        let options = SceneOptions {
+            width: WIDTH,
+            height: HEIGHT,
+            ..SceneOptions::default()
+        };
+        match import::load_scene(&path, &options) {
+            Ok(imported) => {
+                let options = Cfg {
+                    integrator: imported.options.integrator.clone(),
+                    max_rays: imported.options.max_rays,
+                    ..renderer.options
+                };
+                renderer = Renderer { options, ..imported };
+            }
+            Err(err) => println!("Failed to load scene. Encountered error {}", err),
+        }
+    }
+
+    // `--adaptive` samples each pixel until it is clean instead of a fixed number of times.
+    if args.iter().any(|arg| arg == "--adaptive") {
+        renderer.options.adaptive = Some(AdaptiveSampling::default());
+    }
+
+    // `--path` path traces the scene's BSDFs instead of Whitted style shading.
+    if args.iter().any(|arg| arg == "--path") {
+        renderer.options.integrator = IntegratorKind::Path.build();
+    }
+
+    // `--caustics` traces a photon map of the light the glass sphere focuses.
+    if args.iter().any(|arg| arg == "--caustics") {
+        let map = PhotonMap::build(&renderer.objects, &renderer.lights, &PhotonMapping::default());
+        renderer.options.caustics = Some(Arc::new(map));
+    }
+
+    // `--bidirectional` renders with the bidirectional path tracer.
+    if args.iter().any(|arg| arg == "--bidirectional") {
+        renderer.options.integrator = IntegratorKind::Bidirectional.build();
+    }
 
-    //match image::save_buffer("result.png", buf.as_slice(), width, height, image::ColorType::Rgb8) {
-    //    Ok(()) => println!("Saved image to result.png"),
-    //    Err(err) => println!("Failed to save image. Encounter error {}", err),
-    //}
+    // `--ao` darkens ambient light in corners and crevices, `--clay` shows
+    // nothing but that occlusion.
+    if args.iter().any(|arg| arg == "--ao") {
+        renderer.options.ambient_occlusion = Some(AmbientOcclusion::default());
+    }
+    if args.iter().any(|arg| arg == "--clay") {
+        renderer.options.integrator = IntegratorKind::AmbientOcclusion.build();
+    }
+
+    // `--fog` fills the scene with thin fog, lit into shafts by the lights,
+    // `--smoke` puts a ball of thick smoke next to the glass sphere.
+    if args.iter().any(|arg| arg == "--fog") {
+        renderer.options.fog = Some(Medium::fog(0.02));
+    }
+    if args.iter().any(|arg| arg == "--smoke") {
+        renderer.objects.push(Box::new(Sphere {
+            position: Vec3::new(-9.0, -4.5, -6.0),
+            radius: 3.5,
+            material: Material {
+                medium: Some(Medium {
+                    absorption: Color::new(0.05, 0.05, 0.05),
+                    scattering: Color::new(0.6, 0.6, 0.6),
+                    anisotropy: 0.0,
+                }),
+                ..Material::neutral()
+            },
+        }));
+    }
+
+    // `--volume <path> [--dims <x>x<y>x<z>] [--fire <path>]` adds smoke, or
+    // fire, from raw grids of densities and temperatures.
+    if let Some(path) = arg_value(&args, "--volume") {
+        let dims = arg_value(&args, "--dims")
+            .and_then(|dims| {
+                let sizes: Vec<usize> = dims.split('x').filter_map(|size| size.parse().ok()).collect();
+                match sizes[..] {
+                    [x, y, z] => Some((x, y, z)),
+                    _ => None,
+                }
+            })
+            .unwrap_or((64, 64, 64));
+        match load_volume(&path, arg_value(&args, "--fire"), dims) {
+            Ok(volume) => renderer.objects.push(Box::new(volume)),
+            Err(err) => println!("Failed to load volume. Encountered error {}", err),
+        }
+    }
+
+    // `--vox <path>` stands a MagicaVoxel model on the floor in front of the camera.
+    if let Some(path) = arg_value(&args, "--vox") {
+        match VoxelGrid::from_vox(&path, Vec3::new(-2.0, -8.0, -12.0), 0.25) {
+            Ok(model) => renderer.objects.push(Box::new(model)),
+            Err(err) => println!("Failed to load voxel model. Encountered error {}", err),
+        }
+    }
+
+    // `--mesh <path> [--scale <f>] [--smooth]` places a PLY or STL model at the
+    // center of the scene, scaled by f, such as 0.001 for millimetres.
+    if let Some(path) = arg_value(&args, "--mesh") {
+        let options = ImportOptions {
+            scale: arg_value(&args, "--scale").and_then(|v| v.parse().ok()).unwrap_or(1.0),
+            recenter: true,
+            smooth_normals: args.iter().any(|arg| arg == "--smooth"),
+            offset: Vec3::new(-2.0, -4.0, -12.0),
+            ..ImportOptions::default()
+        };
+        match import::load_mesh(&path, &options) {
+            Ok(mesh) => renderer.objects.push(Box::new(mesh)),
+            Err(err) => println!("Failed to load mesh. Encountered error {}", err),
+        }
+    }
+
+    // `--integrator <name>` picks any integrator by name, including the debug
+    // views: normals, depth, uv, facing and bvh.
+    if let Some(name) = arg_value(&args, "--integrator") {
+        match IntegratorKind::from_name(&name) {
+            Some(kind) => renderer.options.integrator = kind.build(),
+            None => eprintln!("unknown integrator {}", name),
+        }
+    }
+
+    // `--samples <n>` refines the viewer's image over n progressive passes.
+    if let Some(samples) = arg_value(&args, "--samples").and_then(|v| v.parse().ok()) {
+        renderer.options.samples = samples;
+    }
+
+    // `--export <path>` writes the scene as OBJ, glTF or GLB instead of showing it.
+    if let Some(path) = arg_value(&args, "--export") {
+        match export::export_scene(&renderer, &path, &ExportOptions::default()) {
+            Ok(exported) => println!(
+                "Exported {} shapes and {} lights to {}, leaving out {} shapes",
+                exported.shapes, exported.lights, path, exported.skipped
+            ),
+            Err(err) => println!("Failed to export scene. Encountered error {}", err),
+        }
+        return;
+    }
 
-    //renderer.render_to_file("result.png".to_string());
-    let buf: Vec<u32> = renderer.render();
-    let duration = now.elapsed();
-    println!(
-        "{} milliseconds elapsed.",
-        duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
-    );
-    display(width, height, buf);
+    viewer::display(renderer);
 }
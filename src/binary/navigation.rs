@@ -0,0 +1,129 @@
+use rusty_tracer::camera::Camera;
+use rusty_tracer::vector::Vec3;
+
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+const ORBIT_SPEED: f64 = 0.005;
+const PAN_SPEED: f64 = 0.0015;
+const ZOOM_STEP: f64 = 0.9;
+/// Fly speed in multiples of the distance to the target per second.
+const FLY_SPEED: f64 = 0.5;
+const MAX_PITCH: f64 = 1.5;
+
+/// Mouse and keyboard camera controls for the viewer.
+///
+/// - left drag orbits around the point the camera looks at
+/// - right drag pans
+/// - the scroll wheel zooms towards the target
+/// - WASD flies through the scene, Q and E move down and up, shift speeds up
+pub struct Navigator {
+    origin: Vec3,
+    target: Vec3,
+}
+
+impl Navigator {
+    pub fn new(camera: &Camera) -> Navigator {
+        Navigator {
+            origin: camera.origin(),
+            target: camera.look_at(),
+        }
+    }
+
+    /// `camera` moved to where the navigation has taken it.
+    pub fn camera(&self, camera: &Camera) -> Camera {
+        camera.moved(self.origin, self.target)
+    }
+
+    /// Applies the input of the last `dt` seconds, returning whether the camera moved.
+    pub fn update(&mut self, input: &WinitInputHelper, dt: f64) -> bool {
+        let (dx, dy) = input.mouse_diff();
+        let (dx, dy) = (f64::from(dx), f64::from(dy));
+        let dragged = dx != 0.0 || dy != 0.0;
+        let mut moved = false;
+
+        if input.mouse_held(0) && dragged {
+            self.orbit(dx * ORBIT_SPEED, dy * ORBIT_SPEED);
+            moved = true;
+        } else if input.mouse_held(1) && dragged {
+            self.pan(dx, dy);
+            moved = true;
+        }
+
+        let scroll = f64::from(input.scroll_diff());
+        if scroll != 0.0 {
+            self.zoom(ZOOM_STEP.powf(scroll));
+            moved = true;
+        }
+
+        let keys = [
+            (VirtualKeyCode::W, 0.0, 0.0, 1.0),
+            (VirtualKeyCode::S, 0.0, 0.0, -1.0),
+            (VirtualKeyCode::D, 1.0, 0.0, 0.0),
+            (VirtualKeyCode::A, -1.0, 0.0, 0.0),
+            (VirtualKeyCode::E, 0.0, 1.0, 0.0),
+            (VirtualKeyCode::Q, 0.0, -1.0, 0.0),
+        ];
+        let mut fly = Vec3::zero();
+        for &(key, right, up, forward) in keys.iter() {
+            if input.key_held(key) {
+                fly = fly + Vec3::new(right, up, forward);
+            }
+        }
+        if fly != Vec3::zero() {
+            let boost = if input.held_shift() { 4.0 } else { 1.0 };
+            self.fly(fly * (FLY_SPEED * boost * dt));
+            moved = true;
+        }
+
+        moved
+    }
+
+    fn distance(&self) -> f64 {
+        (self.origin - self.target).length()
+    }
+
+    /// Forward, right and up vectors of the current view.
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.target - self.origin).normalize();
+        let right = forward.cross(Vec3::new(0.0, 1.0, 0.0)).normalize();
+        let up = right.cross(forward);
+        (forward, right, up)
+    }
+
+    fn orbit(&mut self, yaw: f64, pitch: f64) {
+        let offset = self.origin - self.target;
+        let radius = offset.length();
+
+        let current_yaw = offset.x.atan2(offset.z) - yaw;
+        let current_pitch = ((offset.y / radius).asin() + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+
+        self.origin = self.target
+            + Vec3::new(
+                current_yaw.sin() * current_pitch.cos(),
+                current_pitch.sin(),
+                current_yaw.cos() * current_pitch.cos(),
+            ) * radius;
+    }
+
+    fn pan(&mut self, dx: f64, dy: f64) {
+        let (_, right, up) = self.basis();
+        let shift = (up * dy - right * dx) * (PAN_SPEED * self.distance());
+        self.origin = self.origin + shift;
+        self.target = self.target + shift;
+    }
+
+    fn zoom(&mut self, factor: f64) {
+        let offset = self.origin - self.target;
+        self.origin = self.target + offset * factor.max(1e-3);
+    }
+
+    /// Moves camera and target together by `step` in view space, scaled by the
+    /// distance to the target so the speed suits the size of the scene.
+    fn fly(&mut self, step: Vec3) {
+        let (forward, right, up) = self.basis();
+        let shift = (right * step.x + up * step.y + forward * step.z) * self.distance();
+        self.origin = self.origin + shift;
+        self.target = self.target + shift;
+    }
+}
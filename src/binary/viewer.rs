@@ -0,0 +1,433 @@
+use crate::navigation::Navigator;
+use crate::overlay;
+
+use rusty_tracer::camera::Camera;
+use rusty_tracer::cfg::Cfg;
+use rusty_tracer::color::Color;
+use rusty_tracer::renderer::Renderer;
+use rusty_tracer::tiles::{CancelToken, Tile};
+
+use pixels::{wgpu::Surface, Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{Event, VirtualKeyCode};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::WindowBuilder;
+use winit_input_helper::WinitInputHelper;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the window checks for finished tiles while idle.
+const REFRESH: Duration = Duration::from_millis(16);
+/// Pixel size of the quick renders shown while the camera is moving.
+const PREVIEW_SCALE: u32 = 4;
+/// How long the input has to rest before rendering at full quality again.
+const SETTLE: Duration = Duration::from_millis(250);
+
+/// A hotkey and the change it makes to the render settings.
+type Toggle = (VirtualKeyCode, fn(&mut Cfg));
+
+const TOGGLES: [Toggle; 6] = [
+    (VirtualKeyCode::Key1, |options| options.shadows = !options.shadows),
+    (VirtualKeyCode::Key2, |options| options.reflections = !options.reflections),
+    (VirtualKeyCode::Key3, |options| options.diffuse = !options.diffuse),
+    (VirtualKeyCode::Key4, |options| options.specular = !options.specular),
+    (VirtualKeyCode::Equals, |options| options.max_rays = options.max_rays.saturating_add(1)),
+    (VirtualKeyCode::Minus, |options| options.max_rays = options.max_rays.saturating_sub(1).max(1)),
+];
+
+/// Asks the render thread to render the scene from `camera` with `options`, at
+/// `1 / scale` of the full resolution and with a single pass when `scale` is above one.
+struct RenderJob {
+    camera: Camera,
+    options: Cfg,
+    scale: u32,
+    generation: u64,
+    cancel: CancelToken,
+}
+
+/// Sent from the render thread whenever a tile of a pass is done.
+struct TileUpdate {
+    generation: u64,
+    tile: Tile,
+    pass: u32,
+    passes: u32,
+    completed: usize,
+    total: usize,
+    colors: Vec<Color>,
+}
+
+/// Running average of all the passes of one job rendered so far.
+struct Accumulator {
+    width: u32,
+    height: u32,
+    scale: u32,
+    sums: Vec<Color>,
+    counts: Vec<u32>,
+}
+
+impl Accumulator {
+    fn new(width: u32, height: u32, scale: u32) -> Accumulator {
+        let len = (width.div_ceil(scale) * height.div_ceil(scale)) as usize;
+        Accumulator {
+            width,
+            height,
+            scale,
+            sums: vec![Color::black(); len],
+            counts: vec![0; len],
+        }
+    }
+
+    /// Adds a tile's samples and writes the new averages into the RGBA `frame`.
+    fn add(&mut self, update: &TileUpdate, frame: &mut [u8], gamma_correction: f64) {
+        let tile = update.tile;
+
+        for (i, color) in update.colors.iter().enumerate() {
+            let x = tile.x + i as u32 % tile.width;
+            let y = tile.y + i as u32 / tile.width;
+            let index = (y * self.width.div_ceil(self.scale) + x) as usize;
+
+            self.sums[index] += *color;
+            self.counts[index] += 1;
+            self.draw(x, y, frame, gamma_correction);
+        }
+    }
+
+    /// Writes every pixel into `frame` again, after the gamma changed.
+    fn redraw(&self, frame: &mut [u8], gamma_correction: f64) {
+        for y in 0..self.height.div_ceil(self.scale) {
+            for x in 0..self.width.div_ceil(self.scale) {
+                self.draw(x, y, frame, gamma_correction);
+            }
+        }
+    }
+
+    /// Writes the average of pixel `(x, y)` into `frame`, covering a `scale` x
+    /// `scale` block of the window.
+    fn draw(&self, x: u32, y: u32, frame: &mut [u8], gamma_correction: f64) {
+        let index = (y * self.width.div_ceil(self.scale) + x) as usize;
+        if self.counts[index] == 0 {
+            return;
+        }
+
+        let (r, g, b) = (self.sums[index] * f64::from(self.counts[index]).recip()).to_tuple(gamma_correction);
+
+        for fy in y * self.scale..((y + 1) * self.scale).min(self.height) {
+            for fx in x * self.scale..((x + 1) * self.scale).min(self.width) {
+                let offset = ((fy * self.width + fx) * 4) as usize;
+                frame[offset..offset + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+}
+
+/// The viewer's side of the render thread: which job is current, the settings
+/// it renders with and the image it has produced so far.
+struct Session {
+    jobs: Sender<RenderJob>,
+    generation: u64,
+    cancel: CancelToken,
+    options: Cfg,
+    accumulator: Accumulator,
+    canvas: Vec<u8>,
+    started: Instant,
+    finished: Option<Duration>,
+    pass: u32,
+    passes: u32,
+    fraction: f64,
+}
+
+impl Session {
+    fn new(jobs: Sender<RenderJob>, width: u32, height: u32, options: Cfg) -> Session {
+        Session {
+            jobs,
+            generation: 0,
+            cancel: CancelToken::new(),
+            options,
+            accumulator: Accumulator::new(width, height, 1),
+            canvas: vec![0; (width * height * 4) as usize],
+            started: Instant::now(),
+            finished: None,
+            pass: 0,
+            passes: 0,
+            fraction: 0.0,
+        }
+    }
+
+    /// Abandons the current job and starts rendering `camera` at `1 / scale` resolution.
+    fn submit(&mut self, camera: Camera, scale: u32) {
+        self.cancel.cancel();
+        self.cancel = CancelToken::new();
+        self.generation += 1;
+        self.accumulator = Accumulator::new(self.accumulator.width, self.accumulator.height, scale);
+        self.started = Instant::now();
+        self.finished = None;
+        self.fraction = 0.0;
+
+        self.jobs
+            .send(RenderJob {
+                camera,
+                options: self.options.clone(),
+                scale,
+                generation: self.generation,
+                cancel: self.cancel.clone(),
+            })
+            .ok();
+    }
+
+    /// Blends a finished tile into the canvas, returning whether it belonged to the current job.
+    fn receive(&mut self, update: TileUpdate) -> bool {
+        if update.generation != self.generation {
+            return false;
+        }
+
+        let gamma_correction = self.options.gamma.recip();
+        self.accumulator.add(&update, &mut self.canvas, gamma_correction);
+
+        self.pass = update.pass;
+        self.passes = update.passes;
+        self.fraction = update.completed as f64 / update.total as f64;
+        if update.pass + 1 == update.passes && update.completed == update.total {
+            self.finished = Some(self.started.elapsed());
+        }
+        true
+    }
+
+    fn set_gamma(&mut self, gamma: f64) {
+        self.options.gamma = gamma.max(0.05);
+        self.accumulator.redraw(&mut self.canvas, self.options.gamma.recip());
+    }
+
+    /// Saves the rendered image, without the overlay, next to the working directory.
+    fn screenshot(&self) -> image::ImageResult<PathBuf> {
+        let path = (1..)
+            .map(|n| PathBuf::from(format!("screenshot_{:04}.png", n)))
+            .find(|path| !path.exists())
+            .unwrap();
+
+        let (width, height) = (self.accumulator.width, self.accumulator.height);
+        image::save_buffer(&path, &self.canvas, width, height, image::ColorType::Rgba8)?;
+        Ok(path)
+    }
+
+    /// Render time, resolution and settings shown on top of the image.
+    fn overlay(&self) -> Vec<String> {
+        let on = |flag: bool| if flag { "ON" } else { "OFF" };
+        let scale = self.accumulator.scale;
+        let status = match self.finished {
+            Some(time) => format!("{} MS", time.as_millis()),
+            None => format!("PASS {}/{} {:.0}%", self.pass + 1, self.passes.max(1), self.fraction * 100.0),
+        };
+
+        vec![
+            format!(
+                "{}X{}  {}",
+                self.accumulator.width.div_ceil(scale),
+                self.accumulator.height.div_ceil(scale),
+                status
+            ),
+            format!("1 SHADOWS {}  2 REFLECTIONS {}", on(self.options.shadows), on(self.options.reflections)),
+            format!("3 DIFFUSE {}  4 SPECULAR {}", on(self.options.diffuse), on(self.options.specular)),
+            format!(
+                "+/- RAYS {}  [ ] GAMMA {:.2}  SAMPLES {}",
+                self.options.max_rays, self.options.gamma, self.options.samples
+            ),
+            "P SCREENSHOT  H HIDE".to_string(),
+        ]
+    }
+
+    fn stop(&self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Runs render jobs on a background thread, streaming finished tiles back.
+/// Progressive passes refine the image, one sample per pixel each, and any
+/// job still queued behind the current one replaces it.
+fn spawn_renderer(mut renderer: Renderer) -> (Sender<RenderJob>, Receiver<TileUpdate>) {
+    let (job_sender, jobs) = mpsc::channel::<RenderJob>();
+    let (sender, updates) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (width, height) = (renderer.width, renderer.height);
+
+        while let Ok(mut job) = jobs.recv() {
+            while let Ok(newer) = jobs.try_recv() {
+                job = newer;
+            }
+
+            renderer.camera = job.camera.clone();
+            renderer.options = job.options.clone();
+            renderer.width = width.div_ceil(job.scale);
+            renderer.height = height.div_ceil(job.scale);
+            if job.scale > 1 {
+                renderer.options.samples = 1;
+                renderer.options.adaptive = None;
+            }
+
+            let passes = if renderer.options.adaptive.is_some() {
+                1
+            } else {
+                renderer.options.samples.max(1)
+            };
+            let start = Instant::now();
+
+            for pass in 0..passes {
+                let result = renderer.render_pass(pass, &job.cancel, |progress, colors| {
+                    // The window may already be gone, in which case nobody is listening.
+                    sender
+                        .send(TileUpdate {
+                            generation: job.generation,
+                            tile: progress.tile,
+                            pass,
+                            passes,
+                            completed: progress.completed,
+                            total: progress.total,
+                            colors: colors.to_vec(),
+                        })
+                        .ok();
+                });
+
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            if job.scale == 1 && !job.cancel.is_cancelled() {
+                let duration = start.elapsed();
+                println!(
+                    "{} milliseconds elapsed.",
+                    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+                );
+            }
+        }
+    });
+
+    (job_sender, updates)
+}
+
+pub fn display(renderer: Renderer) {
+    let width = renderer.width;
+    let height = renderer.height;
+    let options = renderer.options.clone();
+    let base_camera = renderer.camera.clone();
+
+    let event_loop = EventLoop::new();
+    let mut input = WinitInputHelper::new();
+    let window = {
+        let size = LogicalSize::new(width as f64 / 2.0, height as f64 / 2.0);
+        WindowBuilder::new()
+            .with_title("Rusty Tracer")
+            .with_inner_size(size)
+            .with_min_inner_size(size)
+            .build(&event_loop)
+            .unwrap()
+    };
+
+    let mut pixels = {
+        let surface = Surface::create(&window);
+        let surface_texture = SurfaceTexture::new(width, height, surface);
+        Pixels::new(width, height, surface_texture).unwrap()
+    };
+
+    let (jobs, updates) = spawn_renderer(renderer);
+    let mut session = Session::new(jobs, width, height, options);
+    let mut show_overlay = true;
+    let mut navigator = Navigator::new(&base_camera);
+    let mut last_input = Instant::now();
+    let mut last_move: Option<Instant> = None;
+
+    session.submit(navigator.camera(&base_camera), 1);
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + REFRESH);
+
+        // Blend in whatever the render thread finished since the last check
+        if let Event::MainEventsCleared = event {
+            let mut changed = false;
+            for update in updates.try_iter() {
+                changed |= session.receive(update);
+            }
+
+            if changed {
+                window.set_title(&format!(
+                    "Rusty Tracer - pass {}/{} - {:.0}%",
+                    session.pass + 1,
+                    session.passes,
+                    session.fraction * 100.0
+                ));
+                window.request_redraw();
+            }
+
+            // Once the camera stops, render it again at full quality
+            if last_move.is_some_and(|moved| moved.elapsed() > SETTLE) {
+                last_move = None;
+                session.submit(navigator.camera(&base_camera), 1);
+            }
+        }
+        // Draw the current frame
+        if let Event::RedrawRequested(_) = event {
+            let frame = pixels.get_frame();
+            frame.copy_from_slice(&session.canvas);
+            if show_overlay {
+                overlay::draw(frame, width as usize, &session.overlay());
+            }
+
+            if pixels.render().is_err() {
+                session.stop();
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+        }
+        // Handle input events
+        if input.update(event) {
+            // Close events
+            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                session.stop();
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            // Resize the window
+            if let Some(size) = input.window_resized() {
+                pixels.resize(size.width, size.height);
+            }
+            // Flip render settings and render again with them
+            let mut toggled = false;
+            for (key, toggle) in TOGGLES.iter() {
+                if input.key_pressed(*key) {
+                    toggle(&mut session.options);
+                    toggled = true;
+                }
+            }
+            if toggled {
+                session.submit(navigator.camera(&base_camera), 1);
+            }
+            // Gamma only changes how the samples are displayed
+            if input.key_pressed(VirtualKeyCode::LBracket) {
+                session.set_gamma(session.options.gamma - 0.05);
+            }
+            if input.key_pressed(VirtualKeyCode::RBracket) {
+                session.set_gamma(session.options.gamma + 0.05);
+            }
+            if input.key_pressed(VirtualKeyCode::H) {
+                show_overlay = !show_overlay;
+            }
+            if input.key_pressed(VirtualKeyCode::P) {
+                match session.screenshot() {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Failed to save screenshot. Encountered error {}", err),
+                }
+            }
+            // Move the camera and preview the new view at low resolution
+            let dt = last_input.elapsed().as_secs_f64();
+            last_input = Instant::now();
+            if navigator.update(&input, dt) {
+                last_move = Some(Instant::now());
+                session.submit(navigator.camera(&base_camera), PREVIEW_SCALE);
+            }
+            // Update internal state and request a redraw
+            window.request_redraw();
+        }
+    });
+}
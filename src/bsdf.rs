@@ -0,0 +1,556 @@
+use crate::color::Color;
+use crate::sampler::Sampler;
+use crate::vector::Vec3;
+
+use std::f64::consts::PI;
+
+/// Smallest GGX alpha used, so that perfectly smooth surfaces stay well behaved.
+const MIN_ALPHA: f64 = 1e-3;
+/// Below this alpha a lobe is considered a mirror, see `BsdfSample::specular`.
+const SPECULAR_ALPHA: f64 = 1e-2;
+
+/// Physically based scattering functions for the path tracing integrators.
+/// Directions point away from the surface: `wo` towards the viewer and `wi`
+/// towards the light, and `normal` is the outward facing shading normal.
+#[derive(Clone, Copy, Debug)]
+pub enum Bsdf {
+    /// Perfectly matte surface.
+    Lambertian { albedo: Color },
+    /// Rough metal, given by the real (`eta`) and imaginary (`k`) parts of its
+    /// index of refraction per color channel.
+    Conductor { eta: Color, k: Color, roughness: f64 },
+    /// Rough glass-like boundary between air and a medium of index `ior`, with
+    /// transmitted light tinted by `tint`.
+    Dielectric { ior: f64, roughness: f64, tint: Color },
+    Principled(Principled),
+}
+
+/// Disney style "principled" material, every parameter but `ior` from 0 to 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Principled {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    /// Strength of the non-metallic specular highlight, 0.5 being an index of refraction of 1.5.
+    pub specular: f64,
+    /// Second, clear and glossy specular layer on top.
+    pub clearcoat: f64,
+    /// Soft reflection at grazing angles, as on cloth.
+    pub sheen: f64,
+    /// How much of the non-metallic base is refracted instead of diffused.
+    pub transmission: f64,
+    pub ior: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled {
+            base_color: Color::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            sheen: 0.0,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+/// A direction picked by `Bsdf::sample`.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    pub direction: Vec3,
+    /// `f * |cos| / pdf`, what the path throughput gets multiplied by.
+    pub weight: Color,
+    pub pdf: f64,
+    /// Whether the direction came from a (near) mirror lobe, which light
+    /// sampling cannot hit and which caustic paths are made of.
+    pub specular: bool,
+}
+
+/// Orthonormal basis around a normal, with `z` along the normal.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    t: Vec3,
+    b: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    /// Builds a tangent frame without branches or normalization (Duff et al. 2017).
+    pub fn new(n: Vec3) -> Frame {
+        let sign = 1f64.copysign(n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        Frame {
+            t: Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            b: Vec3::new(b, sign + n.y * n.y * a, -n.y),
+            n,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.t), v.dot(self.b), v.dot(self.n))
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        self.t * v.x + self.b * v.y + self.n * v.z
+    }
+}
+
+/// Cosine weighted direction in the hemisphere around `z`.
+pub fn sample_cosine(sampler: &mut Sampler) -> Vec3 {
+    let r = sampler.next_f64().sqrt();
+    let phi = 2.0 * PI * sampler.next_f64();
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - r * r).max(0.0).sqrt())
+}
+
+fn reflect(wo: Vec3, h: Vec3) -> Vec3 {
+    h * (2.0 * wo.dot(h)) - wo
+}
+
+/// Refracts `wo` through a surface with normal `h` on its side, `eta` being
+/// the ratio of the index on `wo`'s side to the one on the other side.
+fn refract(wo: Vec3, h: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = wo.dot(h);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo * eta + h * (eta * cos_i - cos_t))
+}
+
+fn alpha(roughness: f64) -> f64 {
+    (roughness * roughness).max(MIN_ALPHA)
+}
+
+/// GGX normal distribution, in the local frame.
+fn ggx_d(h: Vec3, alpha: f64) -> f64 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let d = h.z * h.z * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+fn smith_g1(v: Vec3, alpha: f64) -> f64 {
+    let cos = v.z.abs();
+    let a2 = alpha * alpha;
+    2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+}
+
+fn smith_g(wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    smith_g1(wo, alpha) * smith_g1(wi, alpha)
+}
+
+/// Microfacet normal drawn proportionally to `D(h) * cos(h)`.
+fn sample_ggx(alpha: f64, sampler: &mut Sampler) -> Vec3 {
+    let u = sampler.next_f64();
+    let phi = 2.0 * PI * sampler.next_f64();
+    let cos = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    Vec3::new(sin * phi.cos(), sin * phi.sin(), cos)
+}
+
+/// Microfacet normal around `normal` of a GGX surface with `roughness`, in
+/// world space, for scattering mirror rays into a glossy lobe.
+pub fn sample_microfacet_normal(normal: Vec3, roughness: f64, sampler: &mut Sampler) -> Vec3 {
+    Frame::new(normal).to_world(sample_ggx(alpha(roughness), sampler))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric boundary, `ior` being the
+/// index behind the surface relative to the one in front of it.
+pub fn fresnel_dielectric(cos_i: f64, ior: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 { (-cos_i, ior.recip()) } else { (cos_i, ior) };
+
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// Fresnel reflectance of a conductor with complex index `eta + ik`, per channel.
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let (eta, k) = (eta.channels(), k.channels());
+
+    let mut reflectance = [0.0; 3];
+    for i in 0..3 {
+        let (e2, k2) = (eta[i] * eta[i], k[i] * k[i]);
+        let t0 = e2 - k2 - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * e2 * k2).sqrt();
+        let t1 = a2b2 + cos2;
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        reflectance[i] = (rs + rp) / 2.0;
+    }
+
+    Color::new(reflectance[0], reflectance[1], reflectance[2])
+}
+
+fn schlick(f0: Color, cos: f64) -> Color {
+    let t = (1.0 - cos).clamp(0.0, 1.0).powi(5);
+    f0 * (1.0 - t) + Color::white() * t
+}
+
+/// Microfacet reflection for directions on the same side, given the Fresnel
+/// term of the microfacet normal.
+fn microfacet_reflection<F: Fn(f64) -> Color>(wo: Vec3, wi: Vec3, alpha: f64, fresnel: F) -> Color {
+    if wo.z * wi.z <= 0.0 {
+        return Color::black();
+    }
+    let h = (wo + wi).normalize();
+    let h = if h.z < 0.0 { -h } else { h };
+    let (wo, wi) = if wo.z < 0.0 { (-wo, -wi) } else { (wo, wi) };
+
+    fresnel(wo.dot(h).abs()) * (ggx_d(h, alpha) * smith_g(wo, wi, alpha) / (4.0 * wo.z * wi.z))
+}
+
+fn microfacet_reflection_pdf(wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    if wo.z * wi.z <= 0.0 {
+        return 0.0;
+    }
+    let h = (wo + wi).normalize();
+    let h = if h.z < 0.0 { -h } else { h };
+    ggx_d(h, alpha) * h.z / (4.0 * wo.dot(h).abs())
+}
+
+fn sample_microfacet_reflection(wo: Vec3, alpha: f64, sampler: &mut Sampler) -> Option<Vec3> {
+    let side = 1f64.copysign(wo.z);
+    let wi = reflect(wo, sample_ggx(alpha, sampler) * side);
+    if wi.z * wo.z > 0.0 {
+        Some(wi)
+    } else {
+        None
+    }
+}
+
+/// Half vector of a refraction between `wo` and `wi` through a boundary to a
+/// medium of relative index `ior`, facing `+z`, and the index ratio used.
+fn refraction_half(wo: Vec3, wi: Vec3, ior: f64) -> Option<(Vec3, f64)> {
+    if wo.z * wi.z >= 0.0 {
+        return None;
+    }
+    let eta = if wo.z > 0.0 { ior } else { ior.recip() };
+    let h = (wo + wi * eta).normalize();
+    let h = if h.z < 0.0 { -h } else { h };
+    if wo.dot(h) * wi.dot(h) > 0.0 {
+        return None;
+    }
+    Some((h, eta))
+}
+
+/// Rough refraction (Walter et al. 2007) for directions on opposite sides.
+fn microfacet_transmission(wo: Vec3, wi: Vec3, alpha: f64, ior: f64) -> f64 {
+    let (h, eta) = match refraction_half(wo, wi, ior) {
+        Some(half) => half,
+        None => return 0.0,
+    };
+
+    let fresnel = fresnel_dielectric(wo.dot(h), ior);
+    let denom = wo.dot(h) + eta * wi.dot(h);
+    // Radiance gets compressed into a smaller solid angle when entering a denser medium
+    let factor = eta.recip();
+
+    ((1.0 - fresnel) * ggx_d(h, alpha) * smith_g(wo, wi, alpha) * eta * eta
+        * wi.dot(h).abs() * wo.dot(h).abs() * factor * factor
+        / (wi.z * wo.z * denom * denom))
+        .abs()
+}
+
+fn microfacet_transmission_pdf(wo: Vec3, wi: Vec3, alpha: f64, ior: f64) -> f64 {
+    let (h, eta) = match refraction_half(wo, wi, ior) {
+        Some(half) => half,
+        None => return 0.0,
+    };
+
+    let denom = wo.dot(h) + eta * wi.dot(h);
+    ggx_d(h, alpha) * h.z * (eta * eta * wi.dot(h) / (denom * denom)).abs()
+}
+
+fn sample_microfacet_transmission(wo: Vec3, alpha: f64, ior: f64, sampler: &mut Sampler) -> Option<Vec3> {
+    let side = 1f64.copysign(wo.z);
+    let h = sample_ggx(alpha, sampler) * side;
+    let eta = if wo.z > 0.0 { ior.recip() } else { ior };
+    refract(wo, h, eta).filter(|wi| wi.z * wo.z < 0.0)
+}
+
+impl Principled {
+    fn alpha(&self) -> f64 {
+        alpha(self.roughness)
+    }
+
+    fn specular_f0(&self) -> Color {
+        let dielectric = Color::white() * (0.08 * self.specular);
+        dielectric * (1.0 - self.metallic) + self.base_color * self.metallic
+    }
+
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn transmission_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    /// Probabilities of sampling the diffuse, specular, clearcoat and
+    /// transmission lobes, in that order.
+    fn lobe_weights(&self) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight() * self.base_color.luminance().max(0.05),
+            0.25 + 0.75 * self.metallic,
+            0.25 * self.clearcoat,
+            self.transmission_weight(),
+        ];
+        let total: f64 = weights.iter().sum();
+        [weights[0] / total, weights[1] / total, weights[2] / total, weights[3] / total]
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let mut f = Color::black();
+
+        if wo.z * wi.z > 0.0 {
+            let diffuse = self.diffuse_weight();
+            if diffuse > 0.0 {
+                let h = (wo + wi).normalize();
+                let grazing = (1.0 - wi.dot(h).abs()).powi(5);
+                f += self.base_color * (diffuse / PI) + Color::white() * (diffuse * self.sheen * grazing);
+            }
+
+            let f0 = self.specular_f0();
+            f += microfacet_reflection(wo, wi, self.alpha(), |cos| schlick(f0, cos));
+
+            if self.clearcoat > 0.0 {
+                let coat = microfacet_reflection(wo, wi, 0.05, |cos| schlick(Color::white() * 0.04, cos));
+                f += coat * (0.25 * self.clearcoat);
+            }
+        } else if self.transmission_weight() > 0.0 {
+            let t = microfacet_transmission(wo, wi, self.alpha(), self.ior);
+            f += self.base_color * (self.transmission_weight() * t);
+        }
+
+        f
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let [diffuse, specular, clearcoat, transmission] = self.lobe_weights();
+        if wo.z * wi.z > 0.0 {
+            diffuse * wi.z.abs() / PI
+                + specular * microfacet_reflection_pdf(wo, wi, self.alpha())
+                + clearcoat * microfacet_reflection_pdf(wo, wi, 0.05)
+        } else {
+            transmission * microfacet_transmission_pdf(wo, wi, self.alpha(), self.ior)
+        }
+    }
+
+    fn sample_direction(&self, wo: Vec3, sampler: &mut Sampler) -> Option<(Vec3, bool)> {
+        let [diffuse, specular, clearcoat, _] = self.lobe_weights();
+        let u = sampler.next_f64();
+        let smooth = self.alpha() < SPECULAR_ALPHA;
+
+        if u < diffuse {
+            let wi = sample_cosine(sampler);
+            Some((if wo.z < 0.0 { -wi } else { wi }, false))
+        } else if u < diffuse + specular {
+            sample_microfacet_reflection(wo, self.alpha(), sampler).map(|wi| (wi, smooth))
+        } else if u < diffuse + specular + clearcoat {
+            sample_microfacet_reflection(wo, 0.05, sampler).map(|wi| (wi, false))
+        } else {
+            sample_microfacet_transmission(wo, self.alpha(), self.ior, sampler).map(|wi| (wi, smooth))
+        }
+    }
+}
+
+impl Bsdf {
+    /// Scattered radiance per unit of incoming irradiance from `wi` towards `wo`.
+    pub fn eval(&self, normal: Vec3, wo: Vec3, wi: Vec3) -> Color {
+        let frame = Frame::new(normal);
+        self.eval_local(frame.to_local(wo), frame.to_local(wi))
+    }
+
+    /// Solid angle density with which `sample` picks `wi`.
+    pub fn pdf(&self, normal: Vec3, wo: Vec3, wi: Vec3) -> f64 {
+        let frame = Frame::new(normal);
+        self.pdf_local(frame.to_local(wo), frame.to_local(wi))
+    }
+
+    /// Picks an incoming direction for `wo`, roughly following the lobes of the BSDF.
+    pub fn sample(&self, normal: Vec3, wo: Vec3, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let frame = Frame::new(normal);
+        let wo = frame.to_local(wo);
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let (wi, specular) = match self {
+            Bsdf::Lambertian { .. } => {
+                let wi = sample_cosine(sampler);
+                (if wo.z < 0.0 { -wi } else { wi }, false)
+            }
+            Bsdf::Conductor { roughness, .. } => {
+                let alpha = alpha(*roughness);
+                (sample_microfacet_reflection(wo, alpha, sampler)?, alpha < SPECULAR_ALPHA)
+            }
+            Bsdf::Dielectric { ior, roughness, .. } => {
+                let alpha = alpha(*roughness);
+                let h = sample_ggx(alpha, sampler) * 1f64.copysign(wo.z);
+                let reflectance = fresnel_dielectric(wo.dot(h) * 1f64.copysign(wo.z), *ior);
+                let eta = if wo.z > 0.0 { ior.recip() } else { *ior };
+
+                let wi = if sampler.next_f64() < reflectance {
+                    reflect(wo, h)
+                } else {
+                    refract(wo, h, eta)?
+                };
+                (wi, alpha < SPECULAR_ALPHA)
+            }
+            Bsdf::Principled(principled) => principled.sample_direction(wo, sampler)?,
+        };
+
+        let pdf = self.pdf_local(wo, wi);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: frame.to_world(wi),
+            weight: self.eval_local(wo, wi) * (wi.z.abs() / pdf),
+            pdf,
+            specular,
+        })
+    }
+
+    fn eval_local(&self, wo: Vec3, wi: Vec3) -> Color {
+        match self {
+            Bsdf::Lambertian { albedo } => {
+                if wo.z * wi.z > 0.0 {
+                    *albedo * PI.recip()
+                } else {
+                    Color::black()
+                }
+            }
+            Bsdf::Conductor { eta, k, roughness } => {
+                microfacet_reflection(wo, wi, alpha(*roughness), |cos| fresnel_conductor(cos, *eta, *k))
+            }
+            Bsdf::Dielectric { ior, roughness, tint } => {
+                let alpha = alpha(*roughness);
+                if wo.z * wi.z > 0.0 {
+                    let side = 1f64.copysign(wo.z);
+                    microfacet_reflection(wo, wi, alpha, |cos| Color::white() * fresnel_dielectric(cos * side, *ior))
+                } else {
+                    *tint * microfacet_transmission(wo, wi, alpha, *ior)
+                }
+            }
+            Bsdf::Principled(principled) => principled.eval(wo, wi),
+        }
+    }
+
+    fn pdf_local(&self, wo: Vec3, wi: Vec3) -> f64 {
+        match self {
+            Bsdf::Lambertian { .. } => {
+                if wo.z * wi.z > 0.0 {
+                    wi.z.abs() / PI
+                } else {
+                    0.0
+                }
+            }
+            Bsdf::Conductor { roughness, .. } => microfacet_reflection_pdf(wo, wi, alpha(*roughness)),
+            Bsdf::Dielectric { ior, roughness, .. } => {
+                let alpha = alpha(*roughness);
+                if wo.z * wi.z > 0.0 {
+                    let h = (wo + wi).normalize();
+                    let reflectance = fresnel_dielectric(wo.dot(h).abs() * 1f64.copysign(wo.z), *ior);
+                    reflectance * microfacet_reflection_pdf(wo, wi, alpha)
+                } else {
+                    match refraction_half(wo, wi, *ior) {
+                        Some((h, _)) => {
+                            let reflectance = fresnel_dielectric(wo.dot(h), *ior);
+                            (1.0 - reflectance) * microfacet_transmission_pdf(wo, wi, alpha, *ior)
+                        }
+                        None => 0.0,
+                    }
+                }
+            }
+            Bsdf::Principled(principled) => principled.pdf(wo, wi),
+        }
+    }
+
+    /// Rough overall color of the surface: its diffuse color plus its
+    /// reflectance at normal incidence. Used for ambient light and albedo passes.
+    pub fn albedo(&self) -> Color {
+        match self {
+            Bsdf::Lambertian { albedo } => *albedo,
+            Bsdf::Conductor { eta, k, .. } => fresnel_conductor(1.0, *eta, *k),
+            Bsdf::Dielectric { ior, .. } => Color::white() * fresnel_dielectric(1.0, *ior),
+            Bsdf::Principled(principled) => principled.base_color * principled.diffuse_weight() + principled.specular_f0(),
+        }
+    }
+
+    /// Whether the BSDF only scatters into a narrow mirror-like lobe.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Bsdf::Lambertian { .. } => false,
+            Bsdf::Conductor { roughness, .. } | Bsdf::Dielectric { roughness, .. } => alpha(*roughness) < SPECULAR_ALPHA,
+            Bsdf::Principled(principled) => {
+                principled.alpha() < SPECULAR_ALPHA && principled.diffuse_weight() == 0.0 && principled.clearcoat == 0.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bsdf::{fresnel_dielectric, Bsdf, Principled};
+    use crate::color::Color;
+    use crate::sampler::Sampler;
+    use crate::vector::Vec3;
+
+    /// Monte Carlo estimate of the albedo for light leaving along `wo`.
+    fn albedo(bsdf: &Bsdf, wo: Vec3) -> f64 {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let mut sampler = Sampler::new(3);
+        let n = 20000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            if let Some(sample) = bsdf.sample(normal, wo, &mut sampler) {
+                total += sample.weight.luminance();
+            }
+        }
+        total / f64::from(n)
+    }
+
+    #[test]
+    fn test_fresnel() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel_dielectric(-0.1, 1.5), 1.0);
+    }
+
+    #[test]
+    fn test_energy() {
+        let wo = Vec3::new(0.3, 0.0, 1.0).normalize();
+
+        let lambert = Bsdf::Lambertian { albedo: Color::white() };
+        assert!((albedo(&lambert, wo) - 1.0).abs() < 1e-9);
+
+        let glass = Bsdf::Dielectric { ior: 1.5, roughness: 0.3, tint: Color::white() };
+        let glass_albedo = albedo(&glass, wo);
+        assert!(glass_albedo > 0.3 && glass_albedo <= 1.05);
+
+        let plastic = Bsdf::Principled(Principled::default());
+        let plastic_albedo = albedo(&plastic, wo);
+        assert!(plastic_albedo > 0.6 && plastic_albedo < 1.05);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+/// Items kept together in a leaf of the hierarchy.
+const LEAF_SIZE: usize = 4;
+
+/// An axis aligned box, used to bound items in a `Bvh`.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    /// Bounds containing nothing, that any union grows from.
+    pub fn empty() -> Bounds {
+        Bounds {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Bounds {
+        points.iter().fold(Bounds::empty(), |bounds, point| bounds.grow(*point))
+    }
+
+    pub fn grow(self, point: Vec3) -> Bounds {
+        Bounds {
+            min: Vec3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+            max: Vec3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+        }
+    }
+
+    pub fn union(self, other: Bounds) -> Bounds {
+        self.grow(other.min).grow(other.max)
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Whether `point` lies inside, or within `tolerance` of the box.
+    pub fn contains(&self, point: Vec3, tolerance: f64) -> bool {
+        point.x >= self.min.x - tolerance && point.x <= self.max.x + tolerance
+            && point.y >= self.min.y - tolerance && point.y <= self.max.y + tolerance
+            && point.z >= self.min.z - tolerance && point.z <= self.max.z + tolerance
+    }
+
+    /// Slab test of a ray given by its origin and inverted direction, against
+    /// the part of it closer than `t_max`.
+    fn hit(&self, origin: Vec3, inv_dir: Vec3, t_max: f64) -> bool {
+        let t0s = (self.min - origin) * inv_dir;
+        let t1s = (self.max - origin) * inv_dir;
+
+        let tmin = t0s.x.min(t1s.x).max(t0s.y.min(t1s.y)).max(t0s.z.min(t1s.z));
+        let tmax = t0s.x.max(t1s.x).min(t0s.y.max(t1s.y)).min(t0s.z.max(t1s.z));
+
+        tmin <= tmax && tmax > 0.0 && tmin < t_max
+    }
+}
+
+fn coordinate(point: Vec3, axis: u8) -> f64 {
+    match axis {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z,
+    }
+}
+
+/// A node of the hierarchy. Leaves hold `count` items starting at `start` in
+/// the item list, inner nodes have their first child right after them and
+/// their second at `start`.
+#[derive(Debug)]
+struct Node {
+    bounds: Bounds,
+    start: usize,
+    count: usize,
+    axis: u8,
+}
+
+/// Bounding volume hierarchy over a list of items, split at the median along
+/// the axis their centroids spread the most in.
+#[derive(Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    items: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy over the items with the given bounds. Items are
+    /// referred to by their index in `bounds`.
+    pub fn build(bounds: &[Bounds]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            items: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            bvh.build_node(bounds, 0, bounds.len());
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[Bounds], start: usize, end: usize) -> usize {
+        let index = self.nodes.len();
+        let items = &mut self.items[start..end];
+        let node_bounds = items.iter().fold(Bounds::empty(), |total, item| total.union(bounds[*item]));
+
+        if items.len() <= LEAF_SIZE {
+            self.nodes.push(Node {
+                bounds: node_bounds,
+                start,
+                count: items.len(),
+                axis: 0,
+            });
+            return index;
+        }
+
+        let centroids = items.iter().fold(Bounds::empty(), |total, item| total.grow(bounds[*item].centroid()));
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |a, b| {
+            coordinate(bounds[*a].centroid(), axis).total_cmp(&coordinate(bounds[*b].centroid(), axis))
+        });
+
+        self.nodes.push(Node {
+            bounds: node_bounds,
+            start: 0,
+            count: 0,
+            axis,
+        });
+        self.build_node(bounds, start, start + middle);
+        let second = self.build_node(bounds, start + middle, end);
+        self.nodes[index].start = second;
+
+        index
+    }
+
+    /// Closest item hit by `ray` and its distance, `hit` giving the distance
+    /// along the ray to an item if it is hit at all. Also returns the number of
+    /// node and item tests made on the way.
+    pub fn intersect<F: FnMut(usize) -> Option<f64>>(&self, ray: Ray, mut hit: F) -> (Option<(usize, f64)>, u32) {
+        let mut closest = None;
+        let mut t_max = f64::INFINITY;
+        let mut tests = 0;
+
+        if self.nodes.is_empty() {
+            return (closest, tests);
+        }
+
+        let inv_dir = 1.0 / ray.direction;
+        let mut stack = [0usize; 64];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let node = &self.nodes[stack[len]];
+            tests += 1;
+
+            if !node.bounds.hit(ray.origin, inv_dir, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for item in &self.items[node.start..node.start + node.count] {
+                    tests += 1;
+                    if let Some(t) = hit(*item) {
+                        if t < t_max {
+                            t_max = t;
+                            closest = Some((*item, t));
+                        }
+                    }
+                }
+            } else {
+                // Visit the child nearer along the ray first, so that hits in
+                // it cut the other one short.
+                let first = stack[len] + 1;
+                let (near, far) = if coordinate(ray.direction, node.axis) < 0.0 {
+                    (node.start, first)
+                } else {
+                    (first, node.start)
+                };
+                stack[len] = far;
+                stack[len + 1] = near;
+                len += 2;
+            }
+        }
+
+        (closest, tests)
+    }
+
+    /// Calls `f` for every item whose bounds contain `point`, give or take `tolerance`.
+    pub fn for_each_containing<F: FnMut(usize)>(&self, point: Vec3, tolerance: f64, mut f: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.contains(point, tolerance) {
+                continue;
+            }
+
+            if node.count > 0 {
+                self.items[node.start..node.start + node.count].iter().for_each(|item| f(*item));
+            } else {
+                stack.push(index + 1);
+                stack.push(node.start);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bvh::{Bounds, Bvh};
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_closest_hit() {
+        // Unit spheres scattered around, hit as their bounding boxes.
+        let mut sampler = Sampler::new(3);
+        let centers: Vec<Vec3> = (0..200)
+            .map(|_| Vec3::new(sampler.next_f64(), sampler.next_f64(), sampler.next_f64()) * 40.0)
+            .collect();
+        let bounds: Vec<Bounds> = centers
+            .iter()
+            .map(|c| Bounds::from_points(&[*c - Vec3::new(0.5, 0.5, 0.5), *c + Vec3::new(0.5, 0.5, 0.5)]))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+
+        let sphere = |ray: Ray, center: Vec3| {
+            let oc = ray.origin - center;
+            let b = oc.dot(ray.direction);
+            let disc = b * b - (oc.dot(oc) - 0.25);
+            if disc < 0.0 {
+                return None;
+            }
+            Some(-b - disc.sqrt()).filter(|t| *t > 0.0)
+        };
+
+        for _ in 0..100 {
+            let target = Vec3::new(sampler.next_f64(), sampler.next_f64(), sampler.next_f64()) * 40.0;
+            let origin = Vec3::new(-10.0, 20.0, -10.0);
+            let ray = Ray {
+                origin,
+                direction: (target - origin).normalize(),
+                time: 0.0,
+            };
+
+            let brute = (0..centers.len())
+                .filter_map(|i| sphere(ray, centers[i]).map(|t| (i, t)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let (found, tests) = bvh.intersect(ray, |i| sphere(ray, centers[i]));
+
+            assert_eq!(found.map(|hit| hit.0), brute.map(|hit| hit.0));
+            assert!(tests > 0);
+        }
+    }
+}
@@ -1,15 +1,21 @@
 use crate::ray::Ray;
 use crate::vector::Vec3;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Camera {
     origin: Vec3,
+    look_at: Vec3,
+    fov: f64,
+    aspect_ratio: f64,
+    roll: f64,
     corner: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
     w: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -30,22 +36,75 @@ impl Camera {
 
         Camera {
             origin,
+            look_at,
+            fov,
+            aspect_ratio,
+            roll,
             corner,
             horizontal,
             vertical,
             u,
             v,
             w,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
+    /// Keeps the shutter open from `open` to `close`, so rays are spread over that
+    /// interval and moving shapes blur when several samples are taken per pixel.
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.shutter_open = open;
+        self.shutter_close = close.max(open);
+        self
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn look_at(&self) -> Vec3 {
+        self.look_at
+    }
+
+    pub fn fov(&self) -> f64 {
+        self.fov
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    pub fn roll(&self) -> f64 {
+        self.roll
+    }
+
+    /// The directions to the right of the image, up in it and back out of
+    /// it towards the camera.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        (self.u, self.v, self.w)
+    }
+
+    /// The same camera moved to `origin`, looking at `look_at`.
+    pub fn moved(&self, origin: Vec3, look_at: Vec3) -> Camera {
+        Camera::new(origin, look_at, self.fov, self.aspect_ratio, self.roll)
+            .with_shutter(self.shutter_open, self.shutter_close)
+    }
+
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+        self.get_ray_at(s, t, 0.0)
+    }
+
+    /// Like `get_ray`, with `shutter` in `[0, 1)` picking the moment within the
+    /// shutter interval the ray is sent at.
+    pub fn get_ray_at(&self, s: f64, t: f64, shutter: f64) -> Ray {
         let direction =
             self.corner + (self.horizontal * s) + (self.vertical * t) - self.origin;
 
         Ray {
             origin: self.origin,
             direction: direction.normalize(),
+            time: self.shutter_open + (self.shutter_close - self.shutter_open) * shutter,
         }
     }
 }
\ No newline at end of file
@@ -1,4 +1,55 @@
-#[derive(Debug)]
+use crate::adaptive::AdaptiveSampling;
+use crate::integrator::Integrator;
+use crate::medium::Medium;
+use crate::occlusion::AmbientOcclusion;
+use crate::photon::PhotonMap;
+use crate::tiles::TileOrder;
+
+use std::sync::Arc;
+
+/// The integrators that can be picked by name, see `IntegratorKind::build`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorKind {
+    /// Direct light plus mirror reflections, using the Phong material parameters.
+    Whitted,
+    /// Unidirectional path tracing of the materials' BSDFs, needing many samples per pixel.
+    Path,
+    /// Bidirectional path tracing, joining paths from the camera and the lights.
+    Bidirectional,
+    /// Plain white surfaces darkened by ambient occlusion only, for clay renders.
+    AmbientOcclusion,
+    /// Shading normals as colors.
+    Normals,
+    /// Distance to the first hit, white at the camera and black from `far` on.
+    Depth { far: f64 },
+    /// Texture coordinates as colors.
+    Uv,
+    /// How squarely surfaces face the camera.
+    FacingRatio,
+    /// Heat map of the intersection tests made per ray, white at `max`.
+    BvhCost { max: u32 },
+}
+
+impl IntegratorKind {
+    /// The integrator going by `name` on the command line, debug ones with
+    /// their default settings.
+    pub fn from_name(name: &str) -> Option<IntegratorKind> {
+        match name {
+            "whitted" => Some(IntegratorKind::Whitted),
+            "path" => Some(IntegratorKind::Path),
+            "bidirectional" => Some(IntegratorKind::Bidirectional),
+            "clay" => Some(IntegratorKind::AmbientOcclusion),
+            "normals" => Some(IntegratorKind::Normals),
+            "depth" => Some(IntegratorKind::Depth { far: 50.0 }),
+            "uv" => Some(IntegratorKind::Uv),
+            "facing" => Some(IntegratorKind::FacingRatio),
+            "bvh" => Some(IntegratorKind::BvhCost { max: 64 }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Cfg {
     pub max_rays: u8,
     pub gamma: f64,
@@ -7,4 +58,49 @@ pub struct Cfg {
     pub shadows: bool,
     pub reflections: bool,
     pub opacity: bool,
+    pub samples: u32,
+    /// Replaces the fixed `samples` per pixel with as many as each pixel needs.
+    pub adaptive: Option<AdaptiveSampling>,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Computes the color of every camera ray, see `IntegratorKind::build`.
+    pub integrator: Arc<dyn Integrator>,
+    /// Points sampled on every emissive shape to light a hit point with.
+    pub light_samples: u32,
+    /// Reflection rays traced off rough materials hit by camera rays. Deeper
+    /// reflections trace a single one to keep the ray count in check.
+    pub glossy_samples: u32,
+    /// Photons focused by mirrors and glass, gathered on diffuse surfaces.
+    pub caustics: Option<Arc<PhotonMap>>,
+    /// Darkens ambient light where nearby geometry blocks it.
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    /// Participating medium filling the whole scene outside of shapes with a medium of their own.
+    pub fog: Option<Medium>,
+    /// Steps marched through participating media along every ray.
+    pub volume_steps: u32,
+}
+
+impl Default for Cfg {
+    fn default() -> Cfg {
+        Cfg {
+            max_rays: 4,
+            gamma: 1.0,
+            diffuse: true,
+            specular: true,
+            shadows: true,
+            reflections: true,
+            opacity: true,
+            samples: 1,
+            adaptive: None,
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            integrator: IntegratorKind::Whitted.build(),
+            light_samples: 4,
+            glossy_samples: 8,
+            caustics: None,
+            ambient_occlusion: None,
+            fog: None,
+            volume_steps: 32,
+        }
+    }
 }
\ No newline at end of file
@@ -28,26 +28,51 @@ impl Color {
         }
     }
 
+    /// Color of a black body glowing at `kelvin`, from Planck's law at a red,
+    /// a green and a blue wavelength, scaled to a luminance of 1.
+    pub fn blackbody(kelvin: f64) -> Color {
+        // Second radiation constant hc/k, in m·K.
+        const C2: f64 = 1.4388e-2;
+        let planck = |wavelength: f64| wavelength.powi(-5) / ((C2 / (wavelength * kelvin.max(1.0))).exp() - 1.0);
+
+        let color = Color::new(planck(610e-9), planck(550e-9), planck(465e-9));
+        let luminance = color.luminance();
+        if luminance > 0.0 && luminance.is_finite() {
+            color * luminance.recip()
+        } else {
+            Color::black()
+        }
+    }
+
+    /// Perceived brightness (Rec. 709 weights).
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn channels(&self) -> [f64; 3] {
+        [self.r, self.g, self.b]
+    }
+
     pub fn gamma_rgb(&self, gamma_correction: f64) -> image::Rgb<u8> {
         image::Rgb([
-            (self.r.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
-            (self.g.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
-            (self.b.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
+            (self.r.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
+            (self.g.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
+            (self.b.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
         ])
     }
 
     pub fn to_u32(&self, gamma_correction: f64) -> u32 {
-        let r = (self.r.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u32;
-        let g = (self.g.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u32;
-        let b = (self.b.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u32;
+        let r = (self.r.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u32;
+        let g = (self.g.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u32;
+        let b = (self.b.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u32;
         0x00ffffff & (r << 16) | (g << 8) | b
     }
 
     pub fn to_tuple(&self, gamma_correction: f64) -> (u8, u8, u8) {
         (
-            (self.r.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
-            (self.g.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
-            (self.b.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
+            (self.r.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
+            (self.g.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
+            (self.b.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
         )
     }
 }
@@ -96,4 +121,21 @@ impl Mul for Color {
             b: self.b * rhs.b,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::color::Color;
+
+    #[test]
+    fn test_blackbody() {
+        // Embers glow red, daylight is close to white.
+        let [r, g, b] = Color::blackbody(1500.0).channels();
+        assert!(r > g && g > b);
+        let [dr, dg, db] = Color::blackbody(6500.0).channels();
+        assert!(r / b > dr / db);
+        assert!([dr, dg, db].iter().all(|channel| (channel - 1.0).abs() < 0.15));
+
+        assert!((Color::blackbody(3000.0).luminance() - 1.0).abs() < 1e-9);
+    }
+}
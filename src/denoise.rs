@@ -0,0 +1,168 @@
+use crate::aov::{AovBuffers, AovSample};
+use crate::color::Color;
+
+use image::{ImageBuffer, RgbImage};
+use rayon::prelude::*;
+
+/// B3 spline weights of the à-trous wavelet.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) for noisy low
+/// sample renders. Each iteration blurs with a 5x5 kernel whose taps spread
+/// twice as far as in the previous one, and every tap is weighted down by how
+/// much its color, normal, albedo and depth differ from the center pixel, so
+/// noise is smoothed out within surfaces but not across their edges.
+#[derive(Clone, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    /// Tolerance for color differences, halved after every iteration.
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    pub sigma_albedo: f64,
+    /// Tolerance for depth differences, relative to the depth of the center pixel.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.2,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+fn distance_sqr(a: Color, b: Color) -> f64 {
+    let (a, b) = (a.channels(), b.channels());
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+impl Denoiser {
+    /// Filters the beauty pass of `buffers`, guided by its other passes.
+    pub fn denoise(&self, buffers: &AovBuffers) -> Vec<Color> {
+        let width = buffers.width as i64;
+        let height = buffers.height as i64;
+        let guides = &buffers.pixels;
+
+        let mut colors: Vec<Color> = guides.iter().map(|sample| sample.beauty).collect();
+        let mut sigma_color = self.sigma_color;
+
+        for iteration in 0..self.iterations {
+            let step = 1i64 << iteration;
+            let source = &colors;
+
+            colors = (0..width * height)
+                .into_par_iter()
+                .map(|pixel| {
+                    let (x, y) = (pixel % width, pixel / width);
+                    let center = &guides[pixel as usize];
+                    let center_color = source[pixel as usize];
+
+                    let mut sum = Color::black();
+                    let mut total = 0.0;
+
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x + (i as i64 - 2) * step;
+                            let qy = y + (j as i64 - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                                continue;
+                            }
+
+                            let index = (qy * width + qx) as usize;
+                            let weight = kx * ky
+                                * self.edge_weight(center, &guides[index])
+                                * (-distance_sqr(center_color, source[index]) / (sigma_color * sigma_color)).exp();
+
+                            sum += source[index] * weight;
+                            total += weight;
+                        }
+                    }
+
+                    if total > 0.0 {
+                        sum * total.recip()
+                    } else {
+                        center_color
+                    }
+                })
+                .collect();
+
+            sigma_color /= 2.0;
+        }
+
+        colors
+    }
+
+    /// Denoises `buffers` into an image, gamma corrected like `Renderer::render_image`.
+    pub fn denoise_image(&self, buffers: &AovBuffers, gamma: f64) -> RgbImage {
+        let colors = self.denoise(buffers);
+        let gamma_correction = gamma.recip();
+        ImageBuffer::from_fn(buffers.width, buffers.height, |x, y| {
+            colors[(y * buffers.width + x) as usize].gamma_rgb(gamma_correction)
+        })
+    }
+
+    /// How alike two pixels' geometry and materials are, from 1 for the same
+    /// surface down to 0 for unrelated ones.
+    fn edge_weight(&self, p: &AovSample, q: &AovSample) -> f64 {
+        match (p.object.is_some(), q.object.is_some()) {
+            (false, false) => return 1.0,
+            (true, true) => {}
+            _ => return 0.0,
+        }
+
+        let normal = (p.normal - q.normal).norm() / (self.sigma_normal * self.sigma_normal);
+        let albedo = distance_sqr(p.albedo, q.albedo) / (self.sigma_albedo * self.sigma_albedo);
+        let depth = (p.depth - q.depth).abs() / (self.sigma_depth * p.depth.max(crate::EPSILON));
+
+        (-(normal + albedo + depth)).exp()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aov::{AovBuffers, AovSample};
+    use crate::color::Color;
+    use crate::denoise::Denoiser;
+    use crate::sampler::Sampler;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_smooths_noise_but_keeps_edges() {
+        let (width, height) = (32, 32);
+        let mut sampler = Sampler::new(7);
+        let pixels = (0..width * height)
+            .map(|pixel| {
+                let left = pixel % width < width / 2;
+                let base = if left { 0.2 } else { 0.8 };
+                let noisy = base + (sampler.next_f64() - 0.5) * 0.2;
+                AovSample {
+                    beauty: Color::new(noisy, noisy, noisy),
+                    depth: 10.0,
+                    normal: if left { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) },
+                    albedo: Color::white(),
+                    object: Some(0),
+                    ..AovSample::background(Color::black())
+                }
+            })
+            .collect();
+        let buffers = AovBuffers { width, height, pixels };
+
+        let denoised = Denoiser::default().denoise(&buffers);
+
+        let error = |colors: &[Color]| {
+            colors.iter().enumerate()
+                .map(|(pixel, color)| {
+                    let expected = if pixel as u32 % width < width / 2 { 0.2 } else { 0.8 };
+                    (color.channels()[0] - expected).abs()
+                })
+                .fold(0.0, f64::max)
+        };
+        let noisy: Vec<Color> = buffers.pixels.iter().map(|sample| sample.beauty).collect();
+
+        assert!(error(&denoised) < error(&noisy) / 2.0);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::bsdf::{Bsdf, Principled};
+use crate::color::Color;
+use crate::material::Material;
+use crate::renderer::Renderer;
+use crate::vector::Vec3;
+
+use std::io;
+use std::path::Path;
+
+pub mod gltf;
+pub mod obj;
+
+/// Triangles approximating a shape's surface, see `Shape::tessellate`. Faces
+/// wind counter-clockwise seen from the front, and there is one normal, and
+/// one uv if there are any, per vertex.
+#[derive(Clone, Debug)]
+pub struct Tessellation {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub triangles: Vec<[usize; 3]>,
+    pub material: Material,
+}
+
+impl Tessellation {
+    /// The six faces of the box from `min` to `max`, each with its own four
+    /// corners so that its normal stays flat.
+    pub fn cuboid(min: Vec3, max: Vec3, material: Material) -> Tessellation {
+        let mut tessellation = Tessellation {
+            vertices: Vec::with_capacity(24),
+            normals: Vec::with_capacity(24),
+            uvs: Vec::new(),
+            triangles: Vec::with_capacity(12),
+            material,
+        };
+        let corner = |x: bool, y: bool, z: bool| {
+            Vec3::new(if x { max.x } else { min.x }, if y { max.y } else { min.y }, if z { max.z } else { min.z })
+        };
+
+        // Each face as its normal and its corners counter-clockwise around it.
+        let faces = [
+            (Vec3::new(1.0, 0.0, 0.0), [(true, false, false), (true, true, false), (true, true, true), (true, false, true)]),
+            (Vec3::new(-1.0, 0.0, 0.0), [(false, false, false), (false, false, true), (false, true, true), (false, true, false)]),
+            (Vec3::new(0.0, 1.0, 0.0), [(false, true, false), (false, true, true), (true, true, true), (true, true, false)]),
+            (Vec3::new(0.0, -1.0, 0.0), [(false, false, false), (true, false, false), (true, false, true), (false, false, true)]),
+            (Vec3::new(0.0, 0.0, 1.0), [(false, false, true), (true, false, true), (true, true, true), (false, true, true)]),
+            (Vec3::new(0.0, 0.0, -1.0), [(false, false, false), (false, true, false), (true, true, false), (true, false, false)]),
+        ];
+        for (normal, corners) in &faces {
+            let start = tessellation.vertices.len();
+            for (x, y, z) in corners {
+                tessellation.vertices.push(corner(*x, *y, *z));
+                tessellation.normals.push(*normal);
+            }
+            tessellation.triangles.push([start, start + 1, start + 2]);
+            tessellation.triangles.push([start, start + 2, start + 3]);
+        }
+        tessellation
+    }
+
+    /// The tessellation with its vertices moved by `point` and its normals
+    /// by `normal`, both being the same rotation or translation.
+    pub fn transformed<P, N>(mut self, point: P, normal: N) -> Tessellation
+    where
+        P: Fn(Vec3) -> Vec3,
+        N: Fn(Vec3) -> Vec3,
+    {
+        self.vertices = self.vertices.into_iter().map(point).collect();
+        self.normals = self.normals.into_iter().map(|n| normal(n).normalize()).collect();
+        self
+    }
+}
+
+/// Metallic-roughness parameters standing in for a material's BSDF, for
+/// formats that describe materials that way.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Pbr {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub transmission: f64,
+    pub ior: f64,
+}
+
+impl Pbr {
+    pub fn of(material: &Material) -> Pbr {
+        match material.bsdf() {
+            Bsdf::Principled(Principled {
+                base_color,
+                metallic,
+                roughness,
+                transmission,
+                ior,
+                ..
+            }) => Pbr {
+                base_color,
+                metallic,
+                roughness,
+                transmission,
+                ior,
+            },
+            Bsdf::Lambertian { albedo } => Pbr {
+                base_color: albedo,
+                metallic: 0.0,
+                roughness: 1.0,
+                transmission: 0.0,
+                ior: 1.5,
+            },
+            // Metals show their reflectance at normal incidence as their color.
+            Bsdf::Conductor { eta, k, roughness } => {
+                let [eta, k] = [eta.channels(), k.channels()];
+                let f0 = |i: usize| ((eta[i] - 1.0).powi(2) + k[i] * k[i]) / ((eta[i] + 1.0).powi(2) + k[i] * k[i]);
+                Pbr {
+                    base_color: Color::new(f0(0), f0(1), f0(2)),
+                    metallic: 1.0,
+                    roughness,
+                    transmission: 0.0,
+                    ior: 1.5,
+                }
+            }
+            Bsdf::Dielectric { ior, roughness, tint } => Pbr {
+                base_color: tint,
+                metallic: 0.0,
+                roughness,
+                transmission: 1.0,
+                ior,
+            },
+        }
+    }
+}
+
+/// How finely curved shapes are tessellated, and how far planes reach.
+#[derive(Clone, Copy, Debug)]
+pub struct ExportOptions {
+    /// Segments around a sphere's equator, half as many from pole to pole.
+    pub segments: u32,
+    /// Side of the square an infinite plane is cut down to.
+    pub plane_size: f64,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions {
+            segments: 32,
+            plane_size: 100.0,
+        }
+    }
+}
+
+/// What went into an exported file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exported {
+    /// Shapes written as meshes.
+    pub shapes: usize,
+    /// Shapes left out because they cannot be tessellated, such as volumes.
+    pub skipped: usize,
+    /// Lights written, leaving out ambient lights.
+    pub lights: usize,
+}
+
+/// The tessellations of the scene's shapes and how many could not be tessellated.
+pub fn tessellate_scene(renderer: &Renderer, options: &ExportOptions) -> (Vec<Tessellation>, usize) {
+    let tessellations: Vec<Tessellation> = renderer.objects.iter().filter_map(|shape| shape.tessellate(options)).collect();
+    let skipped = renderer.objects.len() - tessellations.len();
+    (tessellations, skipped)
+}
+
+/// Writes the scene as OBJ and MTL, or as glTF or GLB, going by the extension of `path`.
+pub fn export_scene<P: AsRef<Path>>(renderer: &Renderer, path: P, options: &ExportOptions) -> io::Result<Exported> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_ascii_lowercase();
+
+    match extension.as_str() {
+        "obj" => obj::write(renderer, path, options),
+        "gltf" | "glb" => gltf::write(renderer, path, options),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no exporter for '{}'", path.display()),
+        )),
+    }
+}
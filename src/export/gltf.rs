@@ -0,0 +1,434 @@
+use crate::bvh::Bounds;
+use crate::export::{tessellate_scene, ExportOptions, Exported, Pbr, Tessellation};
+use crate::import::gltf::{falloff_distance, CHUNK_BIN, CHUNK_JSON, GLB_MAGIC};
+use crate::import::json::Json;
+use crate::light::LightType;
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::renderer::Renderer;
+use crate::vector::Vec3;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FLOAT: usize = 5126;
+const UNSIGNED_INT: usize = 5125;
+const ARRAY_BUFFER: usize = 34962;
+const ELEMENT_ARRAY_BUFFER: usize = 34963;
+
+/// Writes the scene as glTF 2.0, as a `.gltf` file with its buffer embedded
+/// as a data URI or as a binary `.glb` file, going by the extension of `path`.
+///
+/// Every shape that can be tessellated becomes a mesh with a
+/// metallic-roughness material. The camera becomes a perspective camera, and
+/// point lights `KHR_lights_punctual` point lights, whose intensity is scaled
+/// up again by the falloff `gltf::load` applies, so the scene imports as it
+/// was. Ambient lights have no counterpart and are left out.
+pub fn write<P: AsRef<Path>>(renderer: &Renderer, path: P, options: &ExportOptions) -> io::Result<Exported> {
+    let path = path.as_ref();
+    let binary = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("glb"));
+
+    let (tessellations, skipped) = tessellate_scene(renderer, options);
+    let mut document = Document::default();
+    for tessellation in &tessellations {
+        document.add_mesh(tessellation);
+    }
+    document.add_camera(renderer);
+
+    let mut vertices: Vec<Vec3> = tessellations.iter().flat_map(|tessellation| tessellation.vertices.iter().cloned()).collect();
+    if vertices.is_empty() {
+        vertices.push(Vec3::zero());
+    }
+    let bounds = Bounds::from_points(&vertices);
+    let mut lights = 0;
+    for light in &renderer.lights {
+        if let LightType::Point = light.light_type {
+            let distance = falloff_distance(light.position, &bounds);
+            let [r, g, b] = light.color.channels();
+            document.lights.push(Json::object(vec![
+                ("type", "point".into()),
+                ("color", Json::numbers(&[r, g, b])),
+                ("intensity", (light.intensity * distance * distance).into()),
+            ]));
+            document.nodes.push(Json::object(vec![
+                ("translation", Json::numbers(&[light.position.x, light.position.y, light.position.z])),
+                ("extensions", Json::object(vec![("KHR_lights_punctual", Json::object(vec![("light", lights.into())]))])),
+            ]));
+            lights += 1;
+        }
+    }
+    if lights > 0 {
+        document.use_extension("KHR_lights_punctual");
+    }
+
+    if binary {
+        fs::write(path, document.to_glb())?;
+    } else {
+        fs::write(path, document.to_gltf())?;
+    }
+
+    Ok(Exported {
+        shapes: tessellations.len(),
+        skipped,
+        lights,
+    })
+}
+
+/// The parts of a glTF file as they are built up, and the one buffer all
+/// their data goes into.
+#[derive(Default)]
+struct Document {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Json>,
+    accessors: Vec<Json>,
+    meshes: Vec<Json>,
+    materials: Vec<Json>,
+    cameras: Vec<Json>,
+    lights: Vec<Json>,
+    nodes: Vec<Json>,
+    extensions_used: Vec<&'static str>,
+}
+
+impl Document {
+    fn use_extension(&mut self, name: &'static str) {
+        if !self.extensions_used.contains(&name) {
+            self.extensions_used.push(name);
+        }
+    }
+
+    /// Appends `bytes` to the buffer, starting on a multiple of 4 as accessors
+    /// require, and returns the buffer view holding them.
+    fn add_view(&mut self, bytes: &[u8], target: usize) -> usize {
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+        self.buffer_views.push(Json::object(vec![
+            ("buffer", 0.into()),
+            ("byteOffset", self.buffer.len().into()),
+            ("byteLength", bytes.len().into()),
+            ("target", target.into()),
+        ]));
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    /// Adds an accessor of `f32` vectors of `N` components, with their bounds
+    /// when they are positions.
+    fn add_floats<const N: usize>(&mut self, values: &[[f64; N]], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = values.iter().flatten().flat_map(|value| (*value as f32).to_le_bytes()).collect();
+        let view = self.add_view(&bytes, ARRAY_BUFFER);
+
+        let mut accessor = vec![
+            ("bufferView", view.into()),
+            ("componentType", FLOAT.into()),
+            ("count", values.len().into()),
+            ("type", if N == 2 { "VEC2" } else { "VEC3" }.into()),
+        ];
+        if with_bounds {
+            // The bounds must match the values as stored, in single precision.
+            let (mut min, mut max) = ([f64::INFINITY; N], [f64::NEG_INFINITY; N]);
+            for value in values {
+                for i in 0..N {
+                    let stored = f64::from(value[i] as f32);
+                    min[i] = min[i].min(stored);
+                    max[i] = max[i].max(stored);
+                }
+            }
+            accessor.push(("min", Json::numbers(&min)));
+            accessor.push(("max", Json::numbers(&max)));
+        }
+        self.accessors.push(Json::object(accessor));
+        self.accessors.len() - 1
+    }
+
+    fn add_indices(&mut self, triangles: &[[usize; 3]]) -> usize {
+        let bytes: Vec<u8> = triangles.iter().flatten().flat_map(|index| (*index as u32).to_le_bytes()).collect();
+        let view = self.add_view(&bytes, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(Json::object(vec![
+            ("bufferView", view.into()),
+            ("componentType", UNSIGNED_INT.into()),
+            ("count", (triangles.len() * 3).into()),
+            ("type", "SCALAR".into()),
+        ]));
+        self.accessors.len() - 1
+    }
+
+    /// Adds the tessellation as a mesh of one primitive, with its own material
+    /// and a node placing it.
+    fn add_mesh(&mut self, tessellation: &Tessellation) {
+        let positions: Vec<[f64; 3]> = tessellation.vertices.iter().map(|v| [v.x, v.y, v.z]).collect();
+        let normals: Vec<[f64; 3]> = tessellation.normals.iter().map(|n| [n.x, n.y, n.z]).collect();
+
+        let mut attributes = vec![
+            ("POSITION", self.add_floats(&positions, true).into()),
+            ("NORMAL", self.add_floats(&normals, false).into()),
+        ];
+        if !tessellation.uvs.is_empty() {
+            let uvs: Vec<[f64; 2]> = tessellation.uvs.iter().map(|(u, v)| [*u, *v]).collect();
+            attributes.push(("TEXCOORD_0", self.add_floats(&uvs, false).into()));
+        }
+        let indices = self.add_indices(&tessellation.triangles);
+        let material = self.add_material(&tessellation.material);
+
+        self.meshes.push(Json::object(vec![(
+            "primitives",
+            Json::Array(vec![Json::object(vec![
+                ("attributes", Json::object(attributes)),
+                ("indices", indices.into()),
+                ("material", material.into()),
+            ])]),
+        )]));
+        self.nodes.push(Json::object(vec![("mesh", (self.meshes.len() - 1).into())]));
+    }
+
+    fn add_material(&mut self, material: &Material) -> usize {
+        let pbr = Pbr::of(material);
+        let [r, g, b] = pbr.base_color.channels();
+
+        let mut members = vec![(
+            "pbrMetallicRoughness",
+            Json::object(vec![
+                ("baseColorFactor", Json::numbers(&[r, g, b, material.opacity])),
+                ("metallicFactor", pbr.metallic.into()),
+                ("roughnessFactor", pbr.roughness.into()),
+            ]),
+        )];
+        let mut extensions = Vec::new();
+
+        // Emissive factors stop at 1, any more goes into the strength.
+        let emission = material.emission.channels();
+        let strength = emission.iter().cloned().fold(0.0, f64::max);
+        if strength > 0.0 {
+            let scale = strength.max(1.0);
+            members.push(("emissiveFactor", Json::numbers(&emission.map(|channel| channel / scale))));
+            if scale > 1.0 {
+                self.use_extension("KHR_materials_emissive_strength");
+                extensions.push(("KHR_materials_emissive_strength", Json::object(vec![("emissiveStrength", scale.into())])));
+            }
+        }
+        if material.opacity < 1.0 {
+            members.push(("alphaMode", "BLEND".into()));
+        }
+        if pbr.transmission > 0.0 {
+            self.use_extension("KHR_materials_transmission");
+            self.use_extension("KHR_materials_ior");
+            extensions.push((
+                "KHR_materials_transmission",
+                Json::object(vec![("transmissionFactor", pbr.transmission.into())]),
+            ));
+            extensions.push(("KHR_materials_ior", Json::object(vec![("ior", pbr.ior.into())])));
+        }
+        if !extensions.is_empty() {
+            members.push(("extensions", Json::object(extensions)));
+        }
+
+        self.materials.push(Json::object(members));
+        self.materials.len() - 1
+    }
+
+    /// Adds the renderer's camera, turned so that its -z axis looks where the
+    /// camera does and its y axis is up in the image.
+    fn add_camera(&mut self, renderer: &Renderer) {
+        let camera = &renderer.camera;
+        let (u, v, w) = camera.basis();
+        let mut rotation = Mat4::identity();
+        for (i, axis) in [u, v, w].iter().enumerate() {
+            rotation.rows[0][i] = axis.x;
+            rotation.rows[1][i] = axis.y;
+            rotation.rows[2][i] = axis.z;
+        }
+        let origin = camera.origin();
+
+        self.cameras.push(Json::object(vec![
+            ("type", "perspective".into()),
+            (
+                "perspective",
+                Json::object(vec![
+                    ("yfov", camera.fov().to_radians().into()),
+                    ("aspectRatio", camera.aspect_ratio().into()),
+                    ("znear", 0.01.into()),
+                ]),
+            ),
+        ]));
+        self.nodes.push(Json::object(vec![
+            ("camera", (self.cameras.len() - 1).into()),
+            ("translation", Json::numbers(&[origin.x, origin.y, origin.z])),
+            ("rotation", Json::numbers(&rotation.to_quaternion())),
+        ]));
+    }
+
+    /// The document's JSON, its buffer given by `uri` or, for GLB, left to
+    /// the binary chunk.
+    fn json(&self, uri: Option<String>) -> Json {
+        let roots: Vec<Json> = (0..self.nodes.len()).map(Json::from).collect();
+        let mut members = vec![
+            ("asset", Json::object(vec![("version", "2.0".into()), ("generator", "rusty_tracer".into())])),
+            ("scene", 0.into()),
+            ("scenes", Json::Array(vec![Json::object(vec![("nodes", Json::Array(roots))])])),
+            ("nodes", Json::Array(self.nodes.clone())),
+        ];
+        let lists = [
+            ("meshes", &self.meshes),
+            ("materials", &self.materials),
+            ("cameras", &self.cameras),
+            ("accessors", &self.accessors),
+            ("bufferViews", &self.buffer_views),
+        ];
+        for (name, list) in &lists {
+            if !list.is_empty() {
+                members.push((name, Json::Array(list.to_vec())));
+            }
+        }
+        if !self.buffer.is_empty() {
+            let mut buffer = vec![("byteLength", self.buffer.len().into())];
+            if let Some(uri) = uri {
+                buffer.push(("uri", Json::String(uri)));
+            }
+            members.push(("buffers", Json::Array(vec![Json::object(buffer)])));
+        }
+        if !self.lights.is_empty() {
+            members.push((
+                "extensions",
+                Json::object(vec![("KHR_lights_punctual", Json::object(vec![("lights", Json::Array(self.lights.clone()))]))]),
+            ));
+        }
+        if !self.extensions_used.is_empty() {
+            let names = self.extensions_used.iter().map(|name| Json::from(*name)).collect();
+            members.push(("extensionsUsed", Json::Array(names)));
+        }
+        Json::object(members)
+    }
+
+    fn to_gltf(&self) -> String {
+        let uri = format!("data:application/octet-stream;base64,{}", encode_base64(&self.buffer));
+        self.json(Some(uri)).to_string()
+    }
+
+    /// The GLB container: a header, then the JSON chunk padded with spaces and
+    /// the binary chunk padded with zeros, both to a multiple of 4 bytes.
+    fn to_glb(&self) -> Vec<u8> {
+        let mut json = self.json(None).to_string().into_bytes();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut binary = self.buffer.clone();
+        while !binary.len().is_multiple_of(4) {
+            binary.push(0);
+        }
+
+        let mut length = 12 + 8 + json.len();
+        if !binary.is_empty() {
+            length += 8 + binary.len();
+        }
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend_from_slice(GLB_MAGIC);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        for (kind, chunk) in &[(CHUNK_JSON, &json), (CHUNK_BIN, &binary)] {
+            if !chunk.is_empty() {
+                bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(&kind.to_le_bytes());
+                bytes.extend_from_slice(chunk);
+            }
+        }
+        bytes
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | u32::from(*byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::camera::Camera;
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::export::gltf::{encode_base64, write};
+    use crate::export::ExportOptions;
+    use crate::import::gltf::load;
+    use crate::import::SceneOptions;
+    use crate::light::{Light, LightType};
+    use crate::material::Material;
+    use crate::renderer::Renderer;
+    use crate::shapes::aabb::Aabb;
+    use crate::shapes::sphere::Sphere;
+    use crate::vector::Vec3;
+
+    use std::fs;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(encode_base64(b"hi"), "aGk=");
+        assert_eq!(encode_base64(b"abc"), "YWJj");
+
+        let material = Material {
+            color: Color::new(0.2, 0.4, 0.8),
+            diffuse: 0.9,
+            emission: Color::new(4.0, 2.0, 0.0),
+            ..Material::neutral()
+        };
+        let renderer = Renderer {
+            width: 32,
+            height: 16,
+            camera: Camera::new(Vec3::new(1.0, 2.0, 8.0), Vec3::new(0.0, 0.5, 0.0), 50.0, 2.0, 30.0),
+            objects: vec![
+                Box::new(Sphere {
+                    position: Vec3::new(-1.0, 0.0, 0.0),
+                    radius: 1.0,
+                    material,
+                }),
+                Box::new(Aabb {
+                    min: Vec3::new(0.5, -1.0, -1.0),
+                    max: Vec3::new(2.5, 1.0, 1.0),
+                    material: Material::neutral(),
+                }),
+            ],
+            lights: vec![Light {
+                light_type: LightType::Point,
+                position: Vec3::new(0.0, 6.0, 3.0),
+                intensity: 0.7,
+                color: Color::white(),
+            }],
+            bg_color: Color::black(),
+            options: Cfg::default(),
+        };
+
+        for name in &["rusty_tracer_export_test.glb", "rusty_tracer_export_test.gltf"] {
+            let path = std::env::temp_dir().join(name);
+            let exported = write(&renderer, &path, &ExportOptions::default()).unwrap();
+            assert_eq!((exported.shapes, exported.skipped, exported.lights), (2, 0, 1));
+
+            let options = SceneOptions {
+                ambient: 0.0,
+                ..SceneOptions::default()
+            };
+            let imported = load(&path, &options).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(imported.objects.len(), 2);
+            assert!((imported.camera.origin() - renderer.camera.origin()).length() < 1e-6);
+            assert!((imported.camera.fov() - 50.0).abs() < 1e-6);
+            assert!((imported.camera.roll() - 30.0).abs() < 1e-6);
+            assert_eq!(imported.lights.len(), 1);
+            assert!((imported.lights[0].intensity - 0.7).abs() < 1e-6);
+
+            let emission = imported.objects[0].material().emission;
+            assert!((emission.channels()[0] - 4.0).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::export::{tessellate_scene, ExportOptions, Exported, Pbr, Tessellation};
+use crate::light::LightType;
+use crate::material::Material;
+use crate::renderer::Renderer;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes the scene as a Wavefront OBJ file and its materials as an MTL file
+/// of the same name beside it. OBJ has no cameras or lights, so they are only
+/// described in comments at the top of the file.
+pub fn write<P: AsRef<Path>>(renderer: &Renderer, path: P, options: &ExportOptions) -> io::Result<Exported> {
+    let path = path.as_ref();
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path.file_name().and_then(|name| name.to_str()).unwrap_or("materials.mtl");
+
+    let (tessellations, skipped) = tessellate_scene(renderer, options);
+
+    let mut obj = BufWriter::new(File::create(path)?);
+    let lights = write_header(&mut obj, renderer)?;
+    writeln!(obj, "mtllib {}", mtl_name)?;
+    write_objects(&mut obj, &tessellations)?;
+    obj.flush()?;
+
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    for (i, tessellation) in tessellations.iter().enumerate() {
+        write_material(&mut mtl, i, &tessellation.material)?;
+    }
+    mtl.flush()?;
+
+    Ok(Exported {
+        shapes: tessellations.len(),
+        skipped,
+        lights,
+    })
+}
+
+/// Comments describing the camera and the point lights, returning how many
+/// lights there are.
+fn write_header<W: Write>(out: &mut W, renderer: &Renderer) -> io::Result<usize> {
+    let camera = &renderer.camera;
+    let (origin, look_at) = (camera.origin(), camera.look_at());
+    writeln!(out, "# Exported by rusty_tracer")?;
+    writeln!(
+        out,
+        "# camera origin {} {} {} look_at {} {} {} fov {} roll {}",
+        origin.x,
+        origin.y,
+        origin.z,
+        look_at.x,
+        look_at.y,
+        look_at.z,
+        camera.fov(),
+        camera.roll()
+    )?;
+
+    let mut lights = 0;
+    for light in &renderer.lights {
+        if let LightType::Point = light.light_type {
+            let [r, g, b] = light.color.channels();
+            let position = light.position;
+            writeln!(
+                out,
+                "# light position {} {} {} intensity {} color {} {} {}",
+                position.x, position.y, position.z, light.intensity, r, g, b
+            )?;
+            lights += 1;
+        }
+    }
+    Ok(lights)
+}
+
+/// One object per tessellation. Indices count from 1 across the whole file.
+fn write_objects<W: Write>(out: &mut W, tessellations: &[Tessellation]) -> io::Result<()> {
+    let mut vertex_offset = 1;
+    let mut uv_offset = 1;
+
+    for (i, tessellation) in tessellations.iter().enumerate() {
+        writeln!(out, "o object{}", i)?;
+        writeln!(out, "usemtl material{}", i)?;
+        for vertex in &tessellation.vertices {
+            writeln!(out, "v {} {} {}", vertex.x, vertex.y, vertex.z)?;
+        }
+        // OBJ puts the origin of texture coordinates at the bottom left.
+        for (u, v) in &tessellation.uvs {
+            writeln!(out, "vt {} {}", u, 1.0 - v)?;
+        }
+        for normal in &tessellation.normals {
+            writeln!(out, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+
+        let has_uvs = !tessellation.uvs.is_empty();
+        for triangle in &tessellation.triangles {
+            write!(out, "f")?;
+            for index in triangle {
+                let vertex = index + vertex_offset;
+                if has_uvs {
+                    write!(out, " {}/{}/{}", vertex, index + uv_offset, vertex)?;
+                } else {
+                    write!(out, " {}//{}", vertex, vertex)?;
+                }
+            }
+            writeln!(out)?;
+        }
+
+        vertex_offset += tessellation.vertices.len();
+        uv_offset += tessellation.uvs.len();
+    }
+    Ok(())
+}
+
+/// The material in MTL terms, with the PBR extension's roughness, metallic
+/// and index of refraction alongside the Phong colors.
+fn write_material<W: Write>(out: &mut W, index: usize, material: &Material) -> io::Result<()> {
+    let pbr = Pbr::of(material);
+    let [r, g, b] = pbr.base_color.channels();
+    let [er, eg, eb] = material.emission.channels();
+    let specular = if pbr.metallic > 0.0 { pbr.base_color.channels() } else { [material.specular; 3] };
+
+    writeln!(out, "newmtl material{}", index)?;
+    writeln!(out, "Kd {} {} {}", r, g, b)?;
+    writeln!(out, "Ks {} {} {}", specular[0], specular[1], specular[2])?;
+    writeln!(out, "Ns {}", material.specular_exponent)?;
+    writeln!(out, "Ke {} {} {}", er, eg, eb)?;
+    writeln!(out, "d {}", material.opacity)?;
+    writeln!(out, "Ni {}", pbr.ior)?;
+    writeln!(out, "Pr {}", pbr.roughness)?;
+    writeln!(out, "Pm {}", pbr.metallic)?;
+    writeln!(out, "illum 2")?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::camera::Camera;
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::export::obj;
+    use crate::export::ExportOptions;
+    use crate::material::Material;
+    use crate::renderer::Renderer;
+    use crate::shapes::aabb::Aabb;
+    use crate::vector::Vec3;
+
+    use std::fs;
+
+    #[test]
+    fn test_write() {
+        let renderer = Renderer {
+            width: 16,
+            height: 16,
+            camera: Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), 60.0, 1.0, 0.0),
+            objects: vec![Box::new(Aabb {
+                min: Vec3::new(-1.0, -1.0, -1.0),
+                max: Vec3::new(1.0, 1.0, 1.0),
+                material: Material::neutral(),
+            })],
+            lights: Vec::new(),
+            bg_color: Color::black(),
+            options: Cfg::default(),
+        };
+
+        let path = std::env::temp_dir().join("rusty_tracer_export_test.obj");
+        let exported = obj::write(&renderer, &path, &ExportOptions::default()).unwrap();
+        assert_eq!((exported.shapes, exported.skipped), (1, 0));
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("mtllib rusty_tracer_export_test.mtl"));
+        assert_eq!(text.lines().filter(|line| line.starts_with("v ")).count(), 24);
+        assert_eq!(text.lines().filter(|line| line.starts_with("f ")).count(), 12);
+        assert!(text.contains("f 1//1 2//2 3//3"));
+        assert!(fs::read_to_string(path.with_extension("mtl")).unwrap().contains("newmtl material0"));
+        fs::remove_file(path.with_extension("mtl")).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes an uncompressed, single part scanline OpenEXR file with one 32 bit
+/// float channel per entry of `channels`. Names follow the usual `layer.channel`
+/// convention, and every channel holds `width * height` values in row order.
+pub fn write<P: AsRef<Path>>(path: P, width: u32, height: u32, channels: &[(String, Vec<f32>)]) -> io::Result<()> {
+    let pixels = (width * height) as usize;
+    if let Some((name, _)) = channels.iter().find(|(_, values)| values.len() != pixels) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("channel {} does not have {} values", name, pixels),
+        ));
+    }
+
+    // Readers expect the channel list sorted by name.
+    let mut channels: Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut list = Vec::new();
+    for (name, _) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+        list.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    // One scanline per chunk, each chunk being its y, its size and then each
+    // channel's values for that line.
+    let line_size = channels.len() * width as usize * 4;
+    let chunk_size = 8 + line_size;
+    let table_end = header.len() + height as usize * 8;
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&header)?;
+    for y in 0..height as usize {
+        out.write_all(&((table_end + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    for y in 0..height as usize {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+        for (_, values) in &channels {
+            for value in &values[y * width as usize..(y + 1) * width as usize] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    #[test]
+    fn test_layout() {
+        let path = std::env::temp_dir().join("rusty_tracer_layout.exr");
+        let channels = vec![
+            ("R".to_string(), vec![1.0; 6]),
+            ("depth.Z".to_string(), vec![2.0; 6]),
+        ];
+        super::write(&path, 3, 2, &channels).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+        // The last chunk is the second scanline: y, size, then R and depth.Z values.
+        let line = 3 * 2 * 4;
+        let last = &bytes[bytes.len() - line - 8..];
+        assert_eq!(&last[..4], &1i32.to_le_bytes());
+        assert_eq!(&last[4..8], &(line as i32).to_le_bytes());
+        assert_eq!(&last[8..12], &1f32.to_le_bytes());
+        assert_eq!(&last[last.len() - 4..], &2f32.to_le_bytes());
+    }
+}
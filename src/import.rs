@@ -0,0 +1,294 @@
+use crate::bvh::Bounds;
+use crate::camera::Camera;
+use crate::cfg::IntegratorKind;
+use crate::color::Color;
+use crate::material::Material;
+use crate::renderer::Renderer;
+use crate::shapes::mesh::Mesh;
+use crate::vector::Vec3;
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+pub mod gltf;
+pub(crate) mod json;
+pub mod pbrt;
+pub mod ply;
+pub mod pov;
+pub mod stl;
+
+/// Why a file could not be imported.
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    /// The file breaks the rules of its format. Text formats give the line
+    /// the problem was found on.
+    Malformed { line: Option<usize>, message: String },
+    /// The file is valid but asks for something the importer cannot do.
+    /// Text formats give the line it was asked for on.
+    Unsupported { line: Option<usize>, message: String },
+}
+
+impl ImportError {
+    pub(crate) fn malformed<S: Into<String>>(message: S) -> ImportError {
+        ImportError::Malformed {
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn at_line<S: Into<String>>(line: usize, message: S) -> ImportError {
+        ImportError::Malformed {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn unsupported<S: Into<String>>(message: S) -> ImportError {
+        ImportError::Unsupported {
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn unsupported_at_line<S: Into<String>>(line: usize, message: S) -> ImportError {
+        ImportError::Unsupported {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "{}", err),
+            ImportError::Malformed { line: Some(line), message } => write!(f, "line {}: {}", line, message),
+            ImportError::Malformed { line: None, message } => write!(f, "{}", message),
+            ImportError::Unsupported { line: Some(line), message } => write!(f, "line {}: unsupported: {}", line, message),
+            ImportError::Unsupported { line: None, message } => write!(f, "unsupported: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> ImportError {
+        ImportError::Io(err)
+    }
+}
+
+/// How an imported model is placed in the scene and shaded.
+#[derive(Clone, Copy, Debug)]
+pub struct ImportOptions {
+    /// Factor the file's coordinates are multiplied by, such as 0.001 to turn
+    /// millimetres into metres.
+    pub scale: f64,
+    /// Moves the center of the model's bounding box to the origin, before `offset`.
+    pub recenter: bool,
+    /// Gives meshes without vertex normals smooth ones, averaged over the faces
+    /// around each vertex, instead of shading every face flat.
+    pub smooth_normals: bool,
+    /// Where the model's origin ends up.
+    pub offset: Vec3,
+    /// Material of the whole model, tinted by vertex colors where it has any.
+    pub material: Material,
+}
+
+impl Default for ImportOptions {
+    fn default() -> ImportOptions {
+        ImportOptions {
+            scale: 1.0,
+            recenter: false,
+            smooth_normals: false,
+            offset: Vec3::zero(),
+            material: Material {
+                color: Color::white(),
+                diffuse: 0.8,
+                specular: 0.1,
+                specular_exponent: 10.0,
+                ..Material::neutral()
+            },
+        }
+    }
+}
+
+/// Loads a PLY or STL file as a mesh, going by its extension.
+pub fn load_mesh<P: AsRef<Path>>(path: P, options: &ImportOptions) -> Result<Mesh, ImportError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_ascii_lowercase();
+
+    match extension.as_str() {
+        "ply" => ply::load(path, options),
+        "stl" => stl::load(path, options),
+        _ => Err(ImportError::unsupported(format!("no mesh importer for '{}'", path.display()))),
+    }
+}
+
+/// What a scene is rendered with where its file leaves it open.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneOptions {
+    pub width: u32,
+    pub height: u32,
+    pub background: Color,
+    /// Intensity of a white ambient light added to the scene, none at 0.
+    pub ambient: f64,
+    /// Factor the intensities of the file's lights are multiplied by.
+    pub light_scale: f64,
+    /// Integrator the scene is rendered with, unless the file picks its own.
+    pub integrator: IntegratorKind,
+}
+
+impl Default for SceneOptions {
+    fn default() -> SceneOptions {
+        SceneOptions {
+            width: 1280,
+            height: 720,
+            background: Color::black(),
+            ambient: 0.1,
+            light_scale: 1.0,
+            integrator: IntegratorKind::Whitted,
+        }
+    }
+}
+
+/// Loads a glTF, GLB, pbrt or POV-Ray file as a scene ready to render, going by its extension.
+pub fn load_scene<P: AsRef<Path>>(path: P, options: &SceneOptions) -> Result<Renderer, ImportError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_ascii_lowercase();
+
+    match extension.as_str() {
+        "gltf" | "glb" => gltf::load(path, options),
+        "pbrt" => pbrt::load(path, options),
+        "pov" => pov::load(path, options),
+        _ => Err(ImportError::unsupported(format!("no scene importer for '{}'", path.display()))),
+    }
+}
+
+/// A camera at `origin` looking along `forward`, turned about that direction
+/// so that `up` points up in the image as far as `Camera`'s roll allows.
+pub(crate) fn oriented_camera(origin: Vec3, forward: Vec3, up: Vec3, fov: f64, aspect_ratio: f64) -> Camera {
+    // `Camera` takes its up direction to be the world's y axis turned by the
+    // roll about the z axis, which must be perpendicular to the image's right.
+    let right = forward.cross(up);
+    let rolled_up = Vec3::new(-right.y, right.x, 0.0);
+    let roll = if rolled_up.length() < 1e-9 * right.length() {
+        0.0
+    } else if rolled_up.dot(up) < 0.0 {
+        right.y.atan2(right.x).to_degrees() + 180.0
+    } else {
+        right.y.atan2(right.x).to_degrees()
+    };
+
+    Camera::new(origin, origin + forward, fov, aspect_ratio, roll)
+}
+
+/// A mesh as read from a file, before the import options are applied. Normals,
+/// uvs and colors are either missing or given for every vertex.
+#[derive(Debug, Default)]
+pub(crate) struct MeshData {
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<[usize; 3]>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+}
+
+impl MeshData {
+    /// Splits a polygon into a fan of triangles around its first corner.
+    pub fn add_polygon(&mut self, corners: &[usize]) {
+        for i in 1..corners.len().saturating_sub(1) {
+            self.triangles.push([corners[0], corners[i], corners[i + 1]]);
+        }
+    }
+
+    pub fn into_mesh(self, options: &ImportOptions) -> Result<Mesh, ImportError> {
+        let MeshData {
+            mut vertices,
+            triangles,
+            normals,
+            uvs,
+            colors,
+        } = self;
+
+        if triangles.is_empty() {
+            return Err(ImportError::malformed("the file has no faces"));
+        }
+        if let Some(index) = triangles.iter().flatten().find(|index| **index >= vertices.len()) {
+            return Err(ImportError::malformed(format!(
+                "a face refers to vertex {} of only {}",
+                index,
+                vertices.len()
+            )));
+        }
+
+        for vertex in &mut vertices {
+            *vertex = *vertex * options.scale;
+        }
+        let shift = if options.recenter {
+            options.offset - Bounds::from_points(&vertices).centroid()
+        } else {
+            options.offset
+        };
+        for vertex in &mut vertices {
+            *vertex = *vertex + shift;
+        }
+
+        // A negative scale mirrors the model, which turns its faces inside out.
+        let triangles = if options.scale < 0.0 {
+            triangles.into_iter().map(|[a, b, c]| [a, c, b]).collect()
+        } else {
+            triangles
+        };
+        let normals = if normals.is_empty() && options.smooth_normals {
+            smooth_normals(&vertices, &triangles)
+        } else {
+            normals.into_iter().map(|normal| normal * options.scale.signum()).collect()
+        };
+
+        let mut mesh = Mesh::new(vertices, triangles, options.material);
+        if !normals.is_empty() {
+            mesh = mesh.with_normals(normals);
+        }
+        if !uvs.is_empty() {
+            mesh = mesh.with_uvs(uvs);
+        }
+        if !colors.is_empty() {
+            mesh = mesh.with_colors(colors);
+        }
+        Ok(mesh)
+    }
+}
+
+/// Vertex normals averaged over the faces around each vertex, weighted by the
+/// faces' areas. Vertices no face uses point up.
+pub(crate) fn smooth_normals(vertices: &[Vec3], triangles: &[[usize; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); vertices.len()];
+    for [a, b, c] in triangles {
+        // The cross product's length is twice the face's area.
+        let normal = (vertices[*b] - vertices[*a]).cross(vertices[*c] - vertices[*a]);
+        for index in &[*a, *b, *c] {
+            normals[*index] = normals[*index] + normal;
+        }
+    }
+
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.length() > 0.0 {
+                normal.normalize()
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,749 @@
+use crate::bsdf::{Bsdf, Principled};
+use crate::bvh::Bounds;
+use crate::camera::Camera;
+use crate::cfg::Cfg;
+use crate::color::Color;
+use crate::import::json::Json;
+use crate::import::{oriented_camera, ImportError, SceneOptions};
+use crate::light::{Light, LightType};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::renderer::Renderer;
+use crate::shapes::Shape;
+use crate::shapes::mesh::Mesh;
+use crate::texture::{SurfaceTextures, Texture};
+use crate::vector::Vec3;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Extensions a file may require that the importer knows about.
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "KHR_lights_punctual",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_ior",
+    "KHR_materials_transmission",
+];
+
+pub(crate) const GLB_MAGIC: &[u8] = b"glTF";
+pub(crate) const CHUNK_JSON: u32 = 0x4E4F_534A;
+pub(crate) const CHUNK_BIN: u32 = 0x004E_4942;
+
+/// Loads a glTF 2.0 scene, as a `.gltf` file with its buffers and images
+/// beside it or embedded, or as a binary `.glb` file.
+///
+/// The node hierarchy of the default scene is flattened into one mesh per
+/// primitive, in world space. Metallic-roughness materials become principled
+/// BSDFs, along with Phong parameters close to them, and keep their base
+/// color, metallic-roughness and emissive textures. Normal and occlusion
+/// textures are left out. The first perspective camera found becomes the
+/// scene's camera, and `KHR_lights_punctual` lights become point lights,
+/// see `light`. Without a camera the scene is framed from the front, and
+/// without lights it is lit from the camera.
+pub fn load<P: AsRef<Path>>(path: P, options: &SceneOptions) -> Result<Renderer, ImportError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+
+    let (text, binary) = if bytes.starts_with(GLB_MAGIC) {
+        split_glb(&bytes)?
+    } else {
+        (&bytes[..], None)
+    };
+    let json = Json::parse(&String::from_utf8_lossy(text))?;
+
+    let mut importer = Importer {
+        json: &json,
+        dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        buffers: Vec::new(),
+        textures: HashMap::new(),
+    };
+    importer.check_asset()?;
+    importer.read_buffers(binary)?;
+    importer.scene(options)
+}
+
+/// The JSON and binary chunks of a GLB file.
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), ImportError> {
+    let word = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as usize)
+            .ok_or_else(|| ImportError::malformed("the GLB file is cut short"))
+    };
+
+    if word(4)? != 2 {
+        return Err(ImportError::unsupported(format!("GLB container version {}", word(4)?)));
+    }
+    let length = word(8)?.min(bytes.len());
+
+    let mut json = None;
+    let mut binary = None;
+    let mut position = 12;
+    while position + 8 <= length {
+        let size = word(position)?;
+        let data = bytes
+            .get(position + 8..position + 8 + size)
+            .ok_or_else(|| ImportError::malformed("a GLB chunk runs past the end of the file"))?;
+        match word(position + 4)? as u32 {
+            CHUNK_JSON if json.is_none() => json = Some(data),
+            CHUNK_BIN if binary.is_none() => binary = Some(data),
+            _ => {}
+        }
+        position += 8 + size;
+    }
+
+    Ok((json.ok_or_else(|| ImportError::malformed("the GLB file has no JSON chunk"))?, binary))
+}
+
+/// The shapes, lights and camera gathered while walking the node hierarchy.
+#[derive(Default)]
+struct Scene {
+    objects: Vec<Box<dyn Shape>>,
+    bounds: Option<Bounds>,
+    /// Index of the camera to render with and its node's transform.
+    camera: Option<(usize, Mat4)>,
+    lights: Vec<(usize, Mat4)>,
+}
+
+struct Importer<'a> {
+    json: &'a Json,
+    dir: PathBuf,
+    buffers: Vec<Vec<u8>>,
+    /// Decoded images by index and whether they hold sRGB colors.
+    textures: HashMap<(usize, bool), Arc<Texture>>,
+}
+
+impl<'a> Importer<'a> {
+    /// An item of one of the document's top level arrays.
+    fn item(&self, array: &str, index: usize) -> Result<&'a Json, ImportError> {
+        let item = self.json.get(array).at(index);
+        if item.is_null() {
+            Err(ImportError::malformed(format!("{}[{}] does not exist", array, index)))
+        } else {
+            Ok(item)
+        }
+    }
+
+    fn check_asset(&self) -> Result<(), ImportError> {
+        let version = self.json.get("asset").get("version").as_str().unwrap_or("");
+        if !version.starts_with("2.") {
+            return Err(ImportError::unsupported(format!("glTF version '{}'", version)));
+        }
+
+        for extension in self.json.get("extensionsRequired").items() {
+            let name = extension.as_str().unwrap_or("");
+            if !SUPPORTED_EXTENSIONS.contains(&name) {
+                return Err(ImportError::unsupported(format!("the required extension {}", name)));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_buffers(&mut self, binary: Option<&[u8]>) -> Result<(), ImportError> {
+        for (index, buffer) in self.json.get("buffers").items().iter().enumerate() {
+            let length = buffer
+                .get("byteLength")
+                .as_usize()
+                .ok_or_else(|| ImportError::malformed(format!("buffers[{}] has no byteLength", index)))?;
+            let data = match (buffer.get("uri").as_str(), binary) {
+                (Some(uri), _) => self.read_uri(uri)?,
+                (None, Some(binary)) if index == 0 => binary.to_vec(),
+                (None, _) => return Err(ImportError::malformed(format!("buffers[{}] has no data", index))),
+            };
+            if data.len() < length {
+                return Err(ImportError::malformed(format!(
+                    "buffers[{}] holds {} bytes instead of {}",
+                    index,
+                    data.len(),
+                    length
+                )));
+            }
+            self.buffers.push(data);
+        }
+        Ok(())
+    }
+
+    /// The data of a `data:` URI or of a file relative to the document.
+    fn read_uri(&self, uri: &str) -> Result<Vec<u8>, ImportError> {
+        if let Some(rest) = uri.strip_prefix("data:") {
+            let comma = rest.find(',').ok_or_else(|| ImportError::malformed("a data URI has no ','"))?;
+            if !rest[..comma].ends_with(";base64") {
+                return Err(ImportError::unsupported("data URIs not in base64".to_string()));
+            }
+            return decode_base64(&rest[comma + 1..]);
+        }
+        Ok(fs::read(self.dir.join(decode_percents(uri)))?)
+    }
+
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>), ImportError> {
+        let view = self.item("bufferViews", index)?;
+        let buffer = view
+            .get("buffer")
+            .as_usize()
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| ImportError::malformed(format!("bufferViews[{}] has no buffer", index)))?;
+        let offset = view.get("byteOffset").as_usize().unwrap_or(0);
+        let length = view.get("byteLength").as_usize().unwrap_or(0);
+
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| ImportError::malformed(format!("bufferViews[{}] runs past the end of its buffer", index)))?;
+        let stride = view.get("byteStride").as_usize();
+        if stride == Some(0) {
+            return Err(ImportError::malformed(format!("bufferViews[{}] has a byte stride of 0", index)));
+        }
+        Ok((data, stride))
+    }
+
+    /// The values of an accessor, `width` numbers per element, with normalized
+    /// integers mapped to 0..1 or -1..1. `vertices` is the vertex count of the
+    /// primitive the accessor is read for, if it is known yet.
+    fn accessor(&self, index: usize, vertices: Option<usize>) -> Result<(usize, Vec<f64>), ImportError> {
+        let accessor = self.item("accessors", index)?;
+        if !accessor.get("sparse").is_null() {
+            return Err(ImportError::unsupported("sparse accessors".to_string()));
+        }
+        let malformed = |what: &str| ImportError::malformed(format!("accessors[{}] {}", index, what));
+
+        let count = accessor.get("count").as_usize().ok_or_else(|| malformed("has no count"))?;
+        let width = match accessor.get("type").as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(malformed("has no known type")),
+        };
+        let component = accessor.get("componentType").as_usize().unwrap_or(0);
+        let size = match component {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(malformed("has no known component type")),
+        };
+        let normalized = accessor.get("normalized").as_bool().unwrap_or(false);
+
+        // Accessors without a buffer view are all zeros. Nothing in the file
+        // backs their count, so it must match the primitive's vertices.
+        let view = match accessor.get("bufferView").as_usize() {
+            Some(view) => view,
+            None if vertices.is_some_and(|vertices| count <= vertices) => return Ok((width, vec![0.0; count * width])),
+            None => return Err(malformed("has no buffer view and more elements than vertices")),
+        };
+        let (data, stride) = self.buffer_view(view)?;
+        let stride = stride.unwrap_or(width * size);
+        let offset = accessor.get("byteOffset").as_usize().unwrap_or(0);
+        let end = match count.checked_sub(1) {
+            Some(last) => stride.checked_mul(last).and_then(|at| at.checked_add(offset)).and_then(|at| at.checked_add(width * size)),
+            None => Some(offset),
+        };
+        if end.is_none_or(|end| end > data.len()) {
+            return Err(malformed("runs past the end of its buffer view"));
+        }
+
+        let mut values = Vec::with_capacity(count * width);
+        for element in 0..count {
+            for i in 0..width {
+                let at = offset + element * stride + i * size;
+                let b = &data[at..at + size];
+                values.push(match (component, normalized) {
+                    (5120, false) => f64::from(b[0] as i8),
+                    (5120, true) => (f64::from(b[0] as i8) / 127.0).max(-1.0),
+                    (5121, false) => f64::from(b[0]),
+                    (5121, true) => f64::from(b[0]) / 255.0,
+                    (5122, false) => f64::from(i16::from_le_bytes([b[0], b[1]])),
+                    (5122, true) => (f64::from(i16::from_le_bytes([b[0], b[1]])) / 32767.0).max(-1.0),
+                    (5123, false) => f64::from(u16::from_le_bytes([b[0], b[1]])),
+                    (5123, true) => f64::from(u16::from_le_bytes([b[0], b[1]])) / 65535.0,
+                    (5125, _) => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    _ => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                });
+            }
+        }
+        Ok((width, values))
+    }
+
+    /// The values of an accessor that must have `width` numbers per element
+    /// and, if given, `count` elements.
+    fn attribute(&self, index: usize, widths: &[usize], count: Option<usize>) -> Result<(usize, Vec<f64>), ImportError> {
+        let (width, values) = self.accessor(index, count)?;
+        if !widths.contains(&width) || count.is_some_and(|count| values.len() != count * width) {
+            return Err(ImportError::malformed(format!("accessors[{}] does not fit the attribute it is used for", index)));
+        }
+        Ok((width, values))
+    }
+
+    fn scene(&mut self, options: &SceneOptions) -> Result<Renderer, ImportError> {
+        let roots: Vec<usize> = match self.json.get("scene").as_usize().or_else(|| {
+            self.json.get("scenes").items().first().map(|_| 0)
+        }) {
+            Some(index) => self.item("scenes", index)?.get("nodes").items().iter().filter_map(Json::as_usize).collect(),
+            // Without scenes, every node that is no other node's child is a root.
+            None => {
+                let nodes = self.json.get("nodes").items();
+                let children: Vec<usize> = nodes
+                    .iter()
+                    .flat_map(|node| node.get("children").items().iter().filter_map(Json::as_usize))
+                    .collect();
+                (0..nodes.len()).filter(|node| !children.contains(node)).collect()
+            }
+        };
+
+        let mut scene = Scene::default();
+        for root in roots {
+            self.visit(root, Mat4::identity(), 0, &mut scene)?;
+        }
+
+        let bounds = scene.bounds.unwrap_or_else(|| Bounds::from_points(&[Vec3::zero()]));
+        let aspect_ratio = f64::from(options.width) / f64::from(options.height.max(1));
+        let camera = match scene.camera {
+            Some((index, transform)) => self.camera(index, transform, aspect_ratio)?,
+            None => framing_camera(&bounds, aspect_ratio),
+        };
+
+        let mut lights = Vec::new();
+        for (index, transform) in &scene.lights {
+            lights.push(self.light(*index, *transform, &bounds, options)?);
+        }
+        if lights.is_empty() {
+            lights.push(Light {
+                light_type: LightType::Point,
+                position: camera.origin(),
+                intensity: options.light_scale,
+                color: Color::white(),
+            });
+        }
+        if options.ambient > 0.0 {
+            lights.push(Light {
+                light_type: LightType::Ambient,
+                position: Vec3::zero(),
+                intensity: options.ambient,
+                color: Color::white(),
+            });
+        }
+
+        Ok(Renderer {
+            width: options.width,
+            height: options.height,
+            camera,
+            objects: scene.objects,
+            lights,
+            bg_color: options.background,
+            options: Cfg {
+                integrator: options.integrator.build(),
+                ..Cfg::default()
+            },
+        })
+    }
+
+    fn visit(&mut self, index: usize, parent: Mat4, depth: usize, scene: &mut Scene) -> Result<(), ImportError> {
+        if depth > self.json.get("nodes").items().len() {
+            return Err(ImportError::malformed("the node hierarchy loops back on itself"));
+        }
+        let node = self.item("nodes", index)?;
+
+        let local = match node.get("matrix").as_numbers::<16>() {
+            Some(columns) => Mat4::from_columns(&columns),
+            None => {
+                let translation = node.get("translation").as_numbers::<3>().unwrap_or([0.0; 3]);
+                let [x, y, z, w] = node.get("rotation").as_numbers::<4>().unwrap_or([0.0, 0.0, 0.0, 1.0]);
+                let scale = node.get("scale").as_numbers::<3>().unwrap_or([1.0; 3]);
+                Mat4::translation(Vec3::from_slice(&translation))
+                    * Mat4::from_quaternion(x, y, z, w)
+                    * Mat4::scaling(Vec3::from_slice(&scale))
+            }
+        };
+        let transform = parent * local;
+
+        if let Some(mesh) = node.get("mesh").as_usize() {
+            self.mesh(mesh, transform, scene)?;
+        }
+        if let Some(camera) = node.get("camera").as_usize() {
+            let perspective = self.item("cameras", camera)?.get("type").as_str() == Some("perspective");
+            if scene.camera.is_none() && perspective {
+                scene.camera = Some((camera, transform));
+            }
+        }
+        if let Some(light) = node.get("extensions").get("KHR_lights_punctual").get("light").as_usize() {
+            scene.lights.push((light, transform));
+        }
+
+        for child in node.get("children").items() {
+            let child = child.as_usize().ok_or_else(|| ImportError::malformed(format!("nodes[{}] has a bad child", index)))?;
+            self.visit(child, transform, depth + 1, scene)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a mesh's primitives made of triangles, leaving out points and lines.
+    fn mesh(&mut self, index: usize, transform: Mat4, scene: &mut Scene) -> Result<(), ImportError> {
+        let mirrored = transform.determinant3() < 0.0;
+
+        for primitive in self.item("meshes", index)?.get("primitives").items() {
+            let attributes = primitive.get("attributes");
+            let position = attributes
+                .get("POSITION")
+                .as_usize()
+                .ok_or_else(|| ImportError::malformed(format!("a primitive of meshes[{}] has no positions", index)))?;
+            let (_, positions) = self.attribute(position, &[3], None)?;
+            let vertices: Vec<Vec3> = positions.chunks_exact(3).map(|p| transform.transform_point(Vec3::from_slice(p))).collect();
+            let count = Some(vertices.len());
+
+            let indices: Vec<usize> = match primitive.get("indices").as_usize() {
+                Some(indices) => self.attribute(indices, &[1], None)?.1.into_iter().map(|index| index as usize).collect(),
+                None => (0..vertices.len()).collect(),
+            };
+            let mut triangles: Vec<[usize; 3]> = match primitive.get("mode").as_usize().unwrap_or(4) {
+                4 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+                5 => indices
+                    .windows(3)
+                    .enumerate()
+                    .map(|(i, t)| if i % 2 == 0 { [t[0], t[1], t[2]] } else { [t[1], t[0], t[2]] })
+                    .collect(),
+                6 => (1..indices.len().saturating_sub(1)).map(|i| [indices[0], indices[i], indices[i + 1]]).collect(),
+                _ => continue,
+            };
+            if triangles.iter().flatten().any(|index| *index >= vertices.len()) {
+                return Err(ImportError::malformed(format!("a primitive of meshes[{}] refers to a missing vertex", index)));
+            }
+            if mirrored {
+                triangles.iter_mut().for_each(|triangle| triangle.swap(1, 2));
+            }
+            if triangles.is_empty() {
+                continue;
+            }
+
+            let (material, textures, set) = self.material(primitive.get("material").as_usize())?;
+            let bounds = Bounds::from_points(&vertices);
+            scene.bounds = Some(scene.bounds.map_or(bounds, |all| all.union(bounds)));
+            let mut mesh = Mesh::new(vertices, triangles, material);
+
+            if let Some(normals) = attributes.get("NORMAL").as_usize() {
+                let (_, normals) = self.attribute(normals, &[3], count)?;
+                mesh = mesh.with_normals(
+                    normals.chunks_exact(3).map(|n| transform.transform_normal(Vec3::from_slice(n))).collect(),
+                );
+            }
+            if let Some(colors) = attributes.get("COLOR_0").as_usize() {
+                let (width, colors) = self.attribute(colors, &[3, 4], count)?;
+                mesh = mesh.with_colors(colors.chunks_exact(width).map(|c| Color::new(c[0], c[1], c[2])).collect());
+            }
+            if !textures.is_empty() {
+                if let Some(uvs) = attributes.get(&format!("TEXCOORD_{}", set)).as_usize() {
+                    let (_, uvs) = self.attribute(uvs, &[2], count)?;
+                    mesh = mesh.with_uvs(uvs.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect()).with_textures(textures);
+                }
+            }
+
+            scene.objects.push(Box::new(mesh));
+        }
+        Ok(())
+    }
+
+    /// A material with its textures and the texture coordinate set they use,
+    /// glTF's default material without an index.
+    fn material(&mut self, index: Option<usize>) -> Result<(Material, SurfaceTextures, usize), ImportError> {
+        let material = match index {
+            Some(index) => self.item("materials", index)?,
+            None => &Json::Null,
+        };
+        let pbr = material.get("pbrMetallicRoughness");
+        let extensions = material.get("extensions");
+
+        let [r, g, b, alpha] = pbr.get("baseColorFactor").as_numbers::<4>().unwrap_or([1.0; 4]);
+        let base_color = Color::new(r, g, b);
+        let metallic = pbr.get("metallicFactor").as_f64().unwrap_or(1.0).clamp(0.0, 1.0);
+        let roughness = pbr.get("roughnessFactor").as_f64().unwrap_or(1.0).clamp(0.0, 1.0);
+        let [er, eg, eb] = material.get("emissiveFactor").as_numbers::<3>().unwrap_or([0.0; 3]);
+        let strength = extensions.get("KHR_materials_emissive_strength").get("emissiveStrength").as_f64().unwrap_or(1.0);
+        let transmission = extensions.get("KHR_materials_transmission").get("transmissionFactor").as_f64().unwrap_or(0.0);
+        let ior = extensions.get("KHR_materials_ior").get("ior").as_f64().unwrap_or(1.5);
+
+        let shaded = Material {
+            color: base_color,
+            diffuse: 1.0 - metallic,
+            specular: 0.5,
+            // The inverse of the Blinn-Phong to Beckmann mapping of `Material::bsdf`.
+            specular_exponent: 2.0 / roughness.max(0.05).powi(4) - 2.0,
+            reflectiveness: metallic,
+            roughness,
+            opacity: if material.get("alphaMode").as_str() == Some("BLEND") { alpha } else { 1.0 },
+            bsdf: Some(Bsdf::Principled(Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular: 0.5,
+                transmission,
+                ior,
+                ..Principled::default()
+            })),
+            emission: Color::new(er, eg, eb) * strength,
+            medium: None,
+        };
+
+        let base_color_texture = pbr.get("baseColorTexture");
+        let textures = SurfaceTextures {
+            base_color: self.texture(base_color_texture, true)?,
+            metallic_roughness: self.texture(pbr.get("metallicRoughnessTexture"), false)?,
+            emission: self.texture(material.get("emissiveTexture"), true)?,
+        };
+        let set = [base_color_texture, pbr.get("metallicRoughnessTexture"), material.get("emissiveTexture")]
+            .iter()
+            .find(|info| !info.is_null())
+            .and_then(|info| info.get("texCoord").as_usize())
+            .unwrap_or(0);
+        Ok((shaded, textures, set))
+    }
+
+    /// The image of a texture info, decoded once for each color space.
+    fn texture(&mut self, info: &Json, srgb: bool) -> Result<Option<Arc<Texture>>, ImportError> {
+        let texture = match info.get("index").as_usize() {
+            Some(texture) => texture,
+            None => return Ok(None),
+        };
+        let source = self
+            .item("textures", texture)?
+            .get("source")
+            .as_usize()
+            .ok_or_else(|| ImportError::unsupported(format!("textures[{}] has no image in a core format", texture)))?;
+        if let Some(decoded) = self.textures.get(&(source, srgb)) {
+            return Ok(Some(decoded.clone()));
+        }
+
+        let entry = self.item("images", source)?;
+        let bytes = match (entry.get("uri").as_str(), entry.get("bufferView").as_usize()) {
+            (Some(uri), _) => self.read_uri(uri)?,
+            (None, Some(view)) => self.buffer_view(view)?.0.to_vec(),
+            (None, None) => return Err(ImportError::malformed(format!("images[{}] has no data", source))),
+        };
+        let image = image::load_from_memory(&bytes)
+            .map_err(|err| ImportError::malformed(format!("images[{}] cannot be decoded: {}", source, err)))?;
+
+        let decoded = Arc::new(Texture::from_image(&image, srgb));
+        self.textures.insert((source, srgb), decoded.clone());
+        Ok(Some(decoded))
+    }
+
+    fn camera(&self, index: usize, transform: Mat4, aspect_ratio: f64) -> Result<Camera, ImportError> {
+        let perspective = self.item("cameras", index)?.get("perspective");
+        let fov = perspective
+            .get("yfov")
+            .as_f64()
+            .ok_or_else(|| ImportError::malformed(format!("cameras[{}] has no yfov", index)))?;
+
+        Ok(oriented_camera(
+            transform.transform_point(Vec3::zero()),
+            transform.transform_vector(Vec3::new(0.0, 0.0, -1.0)).normalize(),
+            transform.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            fov.to_degrees(),
+            aspect_ratio,
+        ))
+    }
+
+    /// A `KHR_lights_punctual` light as a point light. The renderer's lights do
+    /// not fall off with distance, so point and spot lights, whose cones are
+    /// left out, take the brightness they have at the middle of the scene.
+    /// Directional lights are put far away against their direction.
+    fn light(&self, index: usize, transform: Mat4, bounds: &Bounds, options: &SceneOptions) -> Result<Light, ImportError> {
+        let light = self.json.get("extensions").get("KHR_lights_punctual").get("lights").at(index);
+        if light.is_null() {
+            return Err(ImportError::malformed(format!("KHR_lights_punctual light {} does not exist", index)));
+        }
+        let [r, g, b] = light.get("color").as_numbers::<3>().unwrap_or([1.0; 3]);
+        let intensity = light.get("intensity").as_f64().unwrap_or(1.0) * options.light_scale;
+
+        let position = transform.transform_point(Vec3::zero());
+
+        let (position, intensity) = match light.get("type").as_str() {
+            Some("directional") => {
+                let direction = transform.transform_vector(Vec3::new(0.0, 0.0, -1.0)).normalize();
+                (bounds.centroid() - direction * (scene_radius(bounds) * 1000.0), intensity)
+            }
+            Some("point") | Some("spot") => {
+                let distance = falloff_distance(position, bounds);
+                (position, intensity / (distance * distance))
+            }
+            other => return Err(ImportError::unsupported(format!("lights of type {:?}", other.unwrap_or("")))),
+        };
+
+        Ok(Light {
+            light_type: LightType::Point,
+            position,
+            intensity,
+            color: Color::new(r, g, b),
+        })
+    }
+}
+
+/// Half the diagonal of a scene's bounds, at least 1.
+pub(crate) fn scene_radius(bounds: &Bounds) -> f64 {
+    ((bounds.max - bounds.min).length() / 2.0).max(1.0)
+}
+
+/// Distance from a point light to the middle of the scene, by which the
+/// inverse square law is applied once rather than at every hit point.
+pub(crate) fn falloff_distance(position: Vec3, bounds: &Bounds) -> f64 {
+    (position - bounds.centroid()).length().max(scene_radius(bounds) * 0.1)
+}
+
+/// A camera looking at the whole of `bounds` from the front and a little above.
+fn framing_camera(bounds: &Bounds, aspect_ratio: f64) -> Camera {
+    let fov: f64 = 45.0;
+    let center = bounds.centroid();
+    let radius = ((bounds.max - bounds.min).length() / 2.0).max(1e-3);
+    let distance = radius / (fov.to_radians() / 2.0).sin();
+
+    let direction = Vec3::new(0.0, 0.3, 1.0).normalize();
+    Camera::new(center + direction * distance, center, fov, aspect_ratio, 0.0)
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, ImportError> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ if byte.is_ascii_whitespace() => continue,
+            _ => return Err(ImportError::malformed("a data URI is not valid base64")),
+        };
+        bits = (bits << 6) | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decodes the `%20` style escapes of a relative URI into a path.
+fn decode_percents(uri: &str) -> PathBuf {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::import::gltf::{decode_base64, load};
+    use crate::import::{ImportError, SceneOptions};
+    use crate::ray::Ray;
+    use crate::renderer::Renderer;
+    use crate::vector::Vec3;
+
+    use std::fs;
+
+    /// Loads `document` next to a buffer file named `rusty tracer test.bin`,
+    /// in a directory of its own for `test` and this run.
+    fn load_with_buffer(document: &str, buffer: &[u8], test: &str) -> Result<Renderer, ImportError> {
+        let dir = std::env::temp_dir().join(format!("rusty_tracer_gltf_{}_{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("rusty tracer test.bin"), buffer).unwrap();
+        fs::write(dir.join("test.gltf"), document).unwrap();
+        let result = load(dir.join("test.gltf"), &SceneOptions::default());
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_load() {
+        // One triangle in the xy plane, scaled to 2 wide and moved along x by
+        // its node's parent, seen by a camera 5 in front of it.
+        let mut buffer = Vec::new();
+        for value in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let document = r#"{
+                "asset": {"version": "2.0"},
+                "scene": 0,
+                "scenes": [{"nodes": [0]}],
+                "nodes": [
+                    {"translation": [3, 0, 0], "children": [1, 2]},
+                    {"mesh": 0, "scale": [2, 2, 2]},
+                    {"camera": 0, "translation": [0, 0, 5]}
+                ],
+                "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "znear": 0.1}}],
+                "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+                "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}],
+                "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}],
+                "bufferViews": [{"buffer": 0, "byteLength": 36}],
+                "buffers": [{"byteLength": 36, "uri": "rusty%20tracer%20test.bin"}]
+            }"#;
+        let renderer = load_with_buffer(document, &buffer, "load").unwrap();
+
+        assert!((renderer.camera.origin() - Vec3::new(3.0, 0.0, 5.0)).length() < 1e-9);
+        assert!((renderer.camera.fov() - 0.8f64.to_degrees()).abs() < 1e-9);
+
+        let ray = Ray {
+            origin: Vec3::new(3.5, 0.5, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = Ray::intersect(ray, &renderer.objects).unwrap();
+        assert!((hit.distance - 5.0).abs() < 1e-6);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!((hit.material.color.channels()[0] - 1.0).abs() < 1e-9);
+
+        assert_eq!(decode_base64("aGk=").unwrap(), b"hi");
+    }
+    #[test]
+    fn test_accessor_counts() {
+        let document = |accessors: &str| {
+            format!(
+                r#"{{
+                    "asset": {{"version": "2.0"}},
+                    "scenes": [{{"nodes": [0]}}],
+                    "nodes": [{{"mesh": 0}}],
+                    "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1}}}}]}}],
+                    "accessors": [
+                        {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                        {}
+                    ],
+                    "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+                    "buffers": [{{"byteLength": 36, "uri": "rusty%20tracer%20test.bin"}}]
+                }}"#,
+                accessors
+            )
+        };
+        let buffer = [0; 36];
+        let malformed = |result: Result<Renderer, ImportError>| matches!(result, Err(ImportError::Malformed { .. }));
+
+        // Zeros without a buffer view, as many as there are vertices or far more.
+        let zeros = r#"{"componentType": 5126, "count": 3, "type": "VEC3"}"#;
+        assert!(load_with_buffer(&document(zeros), &buffer, "zeros").is_ok());
+        let zeros = r#"{"componentType": 5126, "count": 4000000000000, "type": "VEC3"}"#;
+        assert!(malformed(load_with_buffer(&document(zeros), &buffer, "too_many_zeros")));
+
+        // A count whose end overflows instead of running past the buffer view.
+        let huge = r#"{"bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 4611686018427387904, "type": "VEC3"}"#;
+        assert!(malformed(load_with_buffer(&document(huge), &buffer, "huge")));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::import::ImportError;
+
+use std::fmt;
+
+/// A JSON value. Objects keep their members in the order they were written.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Shared by lookups of members that are missing.
+const NULL: Json = Json::Null;
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, ImportError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+            line: 1,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.position < parser.text.len() {
+            return Err(parser.error("unexpected text after the end of the document"));
+        }
+        Ok(value)
+    }
+
+    /// The member `key` of an object, or null.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    /// The item at `index` of an array, or null.
+    pub fn at(&self, index: usize) -> &Json {
+        match self {
+            Json::Array(items) => items.get(index).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// A number that is a whole, non-negative count or index.
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64().filter(|number| *number >= 0.0 && number.fract() == 0.0).map(|number| number as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The items of an array, none for anything else.
+    pub fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// The numbers of an array of exactly `N` numbers.
+    pub fn as_numbers<const N: usize>(&self) -> Option<[f64; N]> {
+        let items = self.items();
+        if items.len() != N {
+            return None;
+        }
+        let mut numbers = [0.0; N];
+        for (number, item) in numbers.iter_mut().zip(items) {
+            *number = item.as_f64()?;
+        }
+        Some(numbers)
+    }
+
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+    }
+
+    pub fn numbers(values: &[f64]) -> Json {
+        Json::Array(values.iter().map(|value| Json::Number(*value)).collect())
+    }
+}
+
+impl From<f64> for Json {
+    fn from(number: f64) -> Json {
+        Json::Number(number)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Json {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Json {
+        Json::String(text.to_string())
+    }
+}
+
+/// Writes the value compactly, on one line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.is_finite() => write!(f, "{}", number),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(text) => write_string(f, text),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Deeper nesting than this is taken to be a broken or hostile file.
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ImportError {
+        ImportError::at_line(self.line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.text.get(self.position) {
+            match byte {
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => {}
+                _ => break,
+            }
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.position).cloned()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ImportError> {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, ImportError> {
+        if self.text[self.position..].starts_with(word.as_bytes()) {
+            self.position += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, ImportError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("the document is nested too deeply"));
+        }
+
+        match self.peek() {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected the name of an object member"));
+                    }
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    members.push((name, self.value(depth + 1)?));
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(self.error("expected ',' or '}' in an object")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']' in an array")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("the document ends too early")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, ImportError> {
+        let start = self.position;
+        while let Some(byte) = self.text.get(self.position) {
+            match byte {
+                b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E' => self.position += 1,
+                _ => break,
+            }
+        }
+        let text = String::from_utf8_lossy(&self.text[start..self.position]);
+        text.parse().map(Json::Number).map_err(|_| self.error(&format!("'{}' is not a number", text)))
+    }
+
+    /// A string, the opening quote being next.
+    fn string(&mut self) -> Result<String, ImportError> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+
+        loop {
+            let byte = *self.text.get(self.position).ok_or_else(|| self.error("a string is not closed"))?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\n' => return Err(self.error("a string runs past the end of its line")),
+                b'\\' => {
+                    let escape = *self.text.get(self.position).ok_or_else(|| self.error("a string is not closed"))?;
+                    self.position += 1;
+                    let decoded = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("unknown escape in a string")),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(decoded.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error("a string is not valid UTF-8"))
+    }
+
+    /// The character of a `\u` escape, joining the halves of surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, ImportError> {
+        let first = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&first) && self.text[self.position..].starts_with(b"\\u") {
+            self.position += 2;
+            let second = self.hex4()?;
+            0x10000 + ((first - 0xD800) << 10) + second.wrapping_sub(0xDC00)
+        } else {
+            first
+        };
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ImportError> {
+        let digits = self.text.get(self.position..self.position + 4).ok_or_else(|| self.error("a \\u escape is cut short"))?;
+        let code = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("a \\u escape is not four hex digits"))?;
+        self.position += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::import::json::Json;
+    use crate::import::ImportError;
+
+    #[test]
+    fn test_parse() {
+        let json = Json::parse("{\"a\": [1, -2.5e1, true, null], \"b\": {\"c\": \"\\u00e9\\n\"}}").unwrap();
+        assert_eq!(json.get("a").at(1).as_f64(), Some(-25.0));
+        assert_eq!(json.get("a").at(2).as_bool(), Some(true));
+        assert_eq!(json.get("b").get("c").as_str(), Some("é\n"));
+        assert!(json.get("missing").at(3).is_null());
+        assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+
+        match Json::parse("{\n  \"a\": [1,\n  2,,\n]}") {
+            Err(ImportError::Malformed { line: Some(3), .. }) => {}
+            other => panic!("expected an error on line 3, got {:?}", other),
+        }
+    }
+}
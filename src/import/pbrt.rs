@@ -0,0 +1,890 @@
+use crate::bsdf::{fresnel_conductor, Bsdf, Principled};
+use crate::bvh::Bounds;
+use crate::cfg::{Cfg, IntegratorKind};
+use crate::color::Color;
+use crate::import::gltf::{falloff_distance, scene_radius};
+use crate::import::{oriented_camera, ply, ImportError, ImportOptions, MeshData, SceneOptions};
+use crate::light::{Light, LightType};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::renderer::Renderer;
+use crate::shapes::sphere::Sphere;
+use crate::shapes::Shape;
+use crate::vector::Vec3;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files nested deeper than this are taken to include themselves.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Loads a scene written in a subset of the pbrt-v3 format, following its
+/// `Include` directives relative to the file.
+///
+/// Supported are perspective cameras, the film's resolution, the path and
+/// bidirectional integrators with their maximum depth, transforms and
+/// attribute blocks, sphere, triangle mesh and PLY mesh shapes, matte,
+/// plastic, metal and glass materials, named materials, diffuse area lights,
+/// and point, spot, distant and infinite lights. Textures and environment
+/// maps are left out, parameters falling back to their defaults; anything
+/// else the scene needs is reported as unsupported.
+///
+/// pbrt's camera space is left-handed, so scenes are mirrored where that
+/// keeps the image the way pbrt renders it. As in `gltf::load`, point lights
+/// take the brightness they have at the middle of the scene, distant lights
+/// are put far away and infinite lights become the ambient light and the
+/// background.
+pub fn load<P: AsRef<Path>>(path: P, options: &SceneOptions) -> Result<Renderer, ImportError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    parse(&text, path.parent().unwrap_or_else(|| Path::new("")), options)
+}
+
+fn parse(text: &str, dir: &Path, options: &SceneOptions) -> Result<Renderer, ImportError> {
+    let mut importer = Importer {
+        options,
+        dir: dir.to_path_buf(),
+        state: Attributes::default(),
+        stack: Vec::new(),
+        coordinate_systems: HashMap::new(),
+        named_materials: HashMap::new(),
+        camera_to_world: Mat4::identity(),
+        fov: 90.0,
+        resolution: (options.width, options.height),
+        world: Mat4::identity(),
+        cfg: Cfg::default(),
+        integrator: options.integrator,
+        objects: Vec::new(),
+        bounds: None,
+        lights: Vec::new(),
+        emitters: false,
+    };
+    importer.run(text, 0)?;
+    Ok(importer.finish())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+/// Splits a file into tokens, each with the line it is on.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, ImportError> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                line += 1;
+                i += 1;
+            }
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'[' => {
+                tokens.push((Token::Open, line));
+                i += 1;
+            }
+            b']' => {
+                tokens.push((Token::Close, line));
+                i += 1;
+            }
+            b'"' => {
+                let start = i + 1;
+                let end = bytes[start..]
+                    .iter()
+                    .position(|byte| *byte == b'"' || *byte == b'\n')
+                    .map(|length| start + length)
+                    .filter(|end| bytes[*end] == b'"')
+                    .ok_or_else(|| ImportError::at_line(line, "a string is not closed on its line"))?;
+                tokens.push((Token::Text(text[start..end].to_string()), line));
+                i = end + 1;
+            }
+            byte if byte.is_ascii_whitespace() => i += 1,
+            byte => {
+                let start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !b"[]\"#".contains(&bytes[i]) {
+                    i += 1;
+                }
+                let word = &text[start..i];
+                let token = if byte.is_ascii_alphabetic() {
+                    Token::Word(word.to_string())
+                } else {
+                    Token::Number(word.parse().map_err(|_| ImportError::at_line(line, format!("'{}' is not a number", word)))?)
+                };
+                tokens.push((token, line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A value following a directive, lists holding no further lists.
+#[derive(Clone, Debug, PartialEq)]
+enum Arg {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    List(Vec<Arg>),
+}
+
+impl Arg {
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Arg::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Arg::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// The numbers of `args`, whether they are in a list or not, which must be `N`.
+fn numbers<const N: usize>(directive: &str, args: &[Arg], line: usize) -> Result<[f64; N], ImportError> {
+    let flat: Vec<f64> = args
+        .iter()
+        .flat_map(|arg| match arg {
+            Arg::List(items) => items.clone(),
+            other => vec![other.clone()],
+        })
+        .filter_map(|arg| arg.as_f64())
+        .collect();
+    let mut values = [0.0; N];
+    if flat.len() != N {
+        return Err(ImportError::at_line(line, format!("{} takes {} numbers, not {}", directive, N, flat.len())));
+    }
+    values.copy_from_slice(&flat);
+    Ok(values)
+}
+
+/// The string a directive starts with, such as the type of a shape.
+fn name<'a>(directive: &str, args: &'a [Arg], line: usize) -> Result<&'a str, ImportError> {
+    args.first()
+        .and_then(Arg::as_str)
+        .ok_or_else(|| ImportError::at_line(line, format!("{} needs a name", directive)))
+}
+
+/// One `"type name" value` parameter of a directive.
+#[derive(Debug)]
+struct Param {
+    kind: String,
+    name: String,
+    values: Vec<Arg>,
+}
+
+/// The parameter list of a directive, with the line it is on for errors.
+#[derive(Debug)]
+struct Params {
+    list: Vec<Param>,
+    line: usize,
+}
+
+impl Params {
+    fn parse(args: &[Arg], line: usize) -> Result<Params, ImportError> {
+        let mut list = Vec::new();
+        let mut args = args.iter();
+        while let Some(declaration) = args.next() {
+            let declaration = declaration
+                .as_str()
+                .ok_or_else(|| ImportError::at_line(line, "expected a parameter such as \"float radius\""))?;
+            let mut words = declaration.split_whitespace();
+            let (kind, name) = match (words.next(), words.next(), words.next()) {
+                (Some(kind), Some(name), None) => (kind.to_string(), name.to_string()),
+                _ => return Err(ImportError::at_line(line, format!("'{}' is not a type and a name", declaration))),
+            };
+            let values = match args.next() {
+                Some(Arg::List(items)) => items.clone(),
+                Some(value) => vec![value.clone()],
+                None => return Err(ImportError::at_line(line, format!("the parameter '{}' has no value", name))),
+            };
+            list.push(Param { kind, name, values });
+        }
+        Ok(Params { list, line })
+    }
+
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.list.iter().find(|param| param.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        self.get(name).map(|param| param.values.iter().filter_map(Arg::as_f64).collect())
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.numbers(name).and_then(|numbers| numbers.first().cloned()).unwrap_or(default)
+    }
+
+    fn text(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|param| param.values.first()).and_then(Arg::as_str)
+    }
+
+    fn point(&self, name: &str, default: Vec3) -> Result<Vec3, ImportError> {
+        match self.numbers(name) {
+            Some(numbers) if numbers.len() == 3 => Ok(Vec3::from_slice(&numbers)),
+            Some(_) => Err(ImportError::at_line(self.line, format!("'{}' needs three numbers", name))),
+            None => Ok(default),
+        }
+    }
+
+    /// A spectrum parameter as a color. Textures are left out and give the
+    /// default, and sampled spectra are averaged into a gray.
+    fn color(&self, name: &str, default: Color) -> Result<Color, ImportError> {
+        let param = match self.get(name) {
+            Some(param) => param,
+            None => return Ok(default),
+        };
+        let numbers: Vec<f64> = param.values.iter().filter_map(Arg::as_f64).collect();
+
+        match (param.kind.as_str(), numbers.as_slice()) {
+            ("rgb", [r, g, b]) | ("color", [r, g, b]) => Ok(Color::new(*r, *g, *b)),
+            ("float", [value]) => Ok(Color::new(*value, *value, *value)),
+            ("blackbody", [kelvin]) => Ok(Color::blackbody(*kelvin)),
+            ("blackbody", [kelvin, scale]) => Ok(Color::blackbody(*kelvin) * *scale),
+            ("spectrum", samples) if !samples.is_empty() && samples.len() % 2 == 0 => {
+                let mean = samples.iter().skip(1).step_by(2).sum::<f64>() / (samples.len() / 2) as f64;
+                Ok(Color::new(mean, mean, mean))
+            }
+            ("spectrum", _) => Err(ImportError::unsupported_at_line(
+                self.line,
+                format!("named or file spectra such as '{}'", name),
+            )),
+            ("texture", _) => Ok(default),
+            (kind, _) => Err(ImportError::at_line(
+                self.line,
+                format!("'{}' cannot be a color given as {} {}", name, kind, numbers.len()),
+            )),
+        }
+    }
+}
+
+/// What shapes are created with, saved and restored by attribute blocks.
+#[derive(Clone, Copy, Debug)]
+struct Attributes {
+    /// From the current coordinate system to pbrt's world space.
+    transform: Mat4,
+    material: Material,
+    /// Light given off by shapes, set by `AreaLightSource`.
+    emission: Color,
+    reverse_orientation: bool,
+}
+
+impl Default for Attributes {
+    fn default() -> Attributes {
+        Attributes {
+            transform: Mat4::identity(),
+            material: material("matte", &Params { list: Vec::new(), line: 0 }).unwrap_or_else(|_| Material::neutral()),
+            emission: Color::black(),
+            reverse_orientation: false,
+        }
+    }
+}
+
+/// A light as written in the file, placed once the scene's extent is known.
+#[derive(Debug)]
+enum SceneLight {
+    Point { position: Vec3, color: Color },
+    Distant { direction: Vec3, color: Color },
+    Infinite(Color),
+}
+
+struct Importer<'a> {
+    options: &'a SceneOptions,
+    dir: PathBuf,
+    state: Attributes,
+    /// Saved states, and whether only their transform is restored.
+    stack: Vec<(Attributes, bool)>,
+    coordinate_systems: HashMap<String, Mat4>,
+    named_materials: HashMap<String, Material>,
+    camera_to_world: Mat4,
+    fov: f64,
+    resolution: (u32, u32),
+    /// Mirrors pbrt's world space into the renderer's, see `world_begin`.
+    world: Mat4,
+    cfg: Cfg,
+    integrator: IntegratorKind,
+    objects: Vec<Box<dyn Shape>>,
+    bounds: Option<Bounds>,
+    lights: Vec<SceneLight>,
+    emitters: bool,
+}
+
+impl Importer<'_> {
+    fn run(&mut self, text: &str, depth: usize) -> Result<(), ImportError> {
+        let tokens = tokenize(text)?;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let (directive, line) = match &tokens[i] {
+                (Token::Word(word), line) => (word.as_str(), *line),
+                (_, line) => return Err(ImportError::at_line(*line, "expected a directive")),
+            };
+            i += 1;
+
+            let mut args = Vec::new();
+            while let Some((token, line)) = tokens.get(i) {
+                let arg = match token {
+                    Token::Word(word) if word == "true" || word == "false" => Arg::Bool(word == "true"),
+                    Token::Word(_) => break,
+                    Token::Text(text) => Arg::Text(text.clone()),
+                    Token::Number(number) => Arg::Number(*number),
+                    Token::Close => return Err(ImportError::at_line(*line, "']' without '['")),
+                    Token::Open => {
+                        let mut items = Vec::new();
+                        loop {
+                            i += 1;
+                            match tokens.get(i) {
+                                Some((Token::Close, _)) => break,
+                                Some((Token::Number(number), _)) => items.push(Arg::Number(*number)),
+                                Some((Token::Text(text), _)) => items.push(Arg::Text(text.clone())),
+                                Some((Token::Word(word), _)) if word == "true" || word == "false" => {
+                                    items.push(Arg::Bool(word == "true"))
+                                }
+                                _ => return Err(ImportError::at_line(*line, "a list is not closed")),
+                            }
+                        }
+                        Arg::List(items)
+                    }
+                };
+                args.push(arg);
+                i += 1;
+            }
+
+            self.directive(directive, &args, line, depth)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, args: &[Arg], line: usize, depth: usize) -> Result<(), ImportError> {
+        match directive {
+            "Identity" => self.state.transform = Mat4::identity(),
+            "Translate" => self.concat(Mat4::translation(Vec3::from_slice(&numbers::<3>(directive, args, line)?))),
+            "Scale" => self.concat(Mat4::scaling(Vec3::from_slice(&numbers::<3>(directive, args, line)?))),
+            "Rotate" => {
+                let [degrees, x, y, z] = numbers::<4>(directive, args, line)?;
+                self.concat(Mat4::rotation(Vec3::new(x, y, z), degrees));
+            }
+            "LookAt" => {
+                let values = numbers::<9>(directive, args, line)?;
+                let look_at = look_at(Vec3::from_slice(&values[0..3]), Vec3::from_slice(&values[3..6]), Vec3::from_slice(&values[6..9]))
+                    .ok_or_else(|| ImportError::at_line(line, "LookAt's up direction is along its view direction"))?;
+                self.concat(look_at);
+            }
+            "Transform" => self.state.transform = Mat4::from_columns(&numbers::<16>(directive, args, line)?),
+            "ConcatTransform" => self.concat(Mat4::from_columns(&numbers::<16>(directive, args, line)?)),
+            "CoordinateSystem" => {
+                let name = name(directive, args, line)?.to_string();
+                self.coordinate_systems.insert(name, self.state.transform);
+            }
+            "CoordSysTransform" => {
+                let name = name(directive, args, line)?;
+                self.state.transform = *self
+                    .coordinate_systems
+                    .get(name)
+                    .ok_or_else(|| ImportError::at_line(line, format!("no coordinate system is named '{}'", name)))?;
+            }
+            "ReverseOrientation" => self.state.reverse_orientation = !self.state.reverse_orientation,
+
+            "AttributeBegin" => self.stack.push((self.state, false)),
+            "TransformBegin" => self.stack.push((self.state, true)),
+            "AttributeEnd" | "TransformEnd" => {
+                let (saved, transform_only) = self
+                    .stack
+                    .pop()
+                    .ok_or_else(|| ImportError::at_line(line, format!("{} without a block to end", directive)))?;
+                if transform_only {
+                    self.state.transform = saved.transform;
+                } else {
+                    self.state = saved;
+                }
+            }
+
+            "Camera" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                match name(directive, args, line)? {
+                    "perspective" => {}
+                    other => return Err(ImportError::unsupported_at_line(line, format!("{} cameras", other))),
+                }
+                self.camera_to_world = self
+                    .state
+                    .transform
+                    .inverse()
+                    .ok_or_else(|| ImportError::at_line(line, "the camera's transform cannot be inverted"))?;
+                self.coordinate_systems.insert("camera".to_string(), self.camera_to_world);
+                self.fov = params.float("fov", 90.0);
+            }
+            "Film" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                let size = |name: &str, default: u32| params.float(name, f64::from(default)).max(1.0) as u32;
+                self.resolution = (size("xresolution", self.resolution.0), size("yresolution", self.resolution.1));
+            }
+            "Integrator" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                self.integrator = match name(directive, args, line)? {
+                    "path" | "volpath" => IntegratorKind::Path,
+                    "bdpt" => IntegratorKind::Bidirectional,
+                    "whitted" | "directlighting" => IntegratorKind::Whitted,
+                    _ => self.integrator,
+                };
+                self.cfg.max_rays = params.float("maxdepth", f64::from(self.cfg.max_rays)).clamp(1.0, 255.0) as u8;
+            }
+            "WorldBegin" => self.world_begin(),
+
+            "Material" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                self.state.material = material(name(directive, args, line)?, &params)?;
+            }
+            "MakeNamedMaterial" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                let kind = params.text("type").unwrap_or("matte");
+                let material = material(kind, &params)?;
+                self.named_materials.insert(name(directive, args, line)?.to_string(), material);
+            }
+            "NamedMaterial" => {
+                let name = name(directive, args, line)?;
+                self.state.material = *self
+                    .named_materials
+                    .get(name)
+                    .ok_or_else(|| ImportError::at_line(line, format!("no material is named '{}'", name)))?;
+            }
+            "AreaLightSource" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                match name(directive, args, line)? {
+                    "diffuse" => {
+                        self.state.emission = params.color("L", Color::white())? * params.color("scale", Color::white())?;
+                    }
+                    other => return Err(ImportError::unsupported_at_line(line, format!("{} area lights", other))),
+                }
+            }
+            "LightSource" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                self.light(name(directive, args, line)?, &params)?;
+            }
+            "Shape" => {
+                let params = Params::parse(args.get(1..).unwrap_or_default(), line)?;
+                self.shape(name(directive, args, line)?, &params)?;
+            }
+
+            "Include" | "Import" => {
+                let file = name(directive, args, line)?;
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(ImportError::at_line(line, "files include each other too deeply"));
+                }
+                let text = fs::read_to_string(self.dir.join(file))?;
+                self.run(&text, depth + 1).map_err(|err| match err {
+                    ImportError::Malformed { line, message } => ImportError::Malformed {
+                        line,
+                        message: format!("{} (in {})", message, file),
+                    },
+                    other => other,
+                })?;
+            }
+
+            // Settings the renderer chooses for itself, and textures and media,
+            // which are left out.
+            "Sampler" | "PixelFilter" | "Accelerator" | "SurfaceIntegrator" | "VolumeIntegrator" | "Renderer"
+            | "Option" | "ColorSpace" | "Texture" | "MakeNamedMedium" | "MediumInterface" | "TransformTimes"
+            | "WorldEnd" => {}
+            "ActiveTransform" => {
+                return Err(ImportError::unsupported_at_line(line, "motion blurred transforms"));
+            }
+            "ObjectBegin" | "ObjectEnd" | "ObjectInstance" => {
+                return Err(ImportError::unsupported_at_line(line, "object instancing"));
+            }
+            _ => return Err(ImportError::at_line(line, format!("unknown directive '{}'", directive))),
+        }
+        Ok(())
+    }
+
+    fn concat(&mut self, transform: Mat4) {
+        self.state.transform = self.state.transform * transform;
+    }
+
+    /// Starts the description of the world, deciding whether to mirror it.
+    /// pbrt puts its camera space's x axis on the right of the image, which
+    /// a right-handed camera looking down the same z axis has on the left,
+    /// unless the camera transform mirrors, as scenes converted from
+    /// right-handed programs often make it with `Scale -1 1 1`.
+    fn world_begin(&mut self) {
+        self.world = if self.camera_to_world.determinant3() > 0.0 {
+            Mat4::scaling(Vec3::new(-1.0, 1.0, 1.0))
+        } else {
+            Mat4::identity()
+        };
+        self.state.transform = Mat4::identity();
+        self.coordinate_systems.insert("world".to_string(), Mat4::identity());
+    }
+
+    fn light(&mut self, kind: &str, params: &Params) -> Result<(), ImportError> {
+        let transform = self.world * self.state.transform;
+        let scale = params.color("scale", Color::white())?;
+
+        let light = match kind {
+            // Spot lights leave out their cones.
+            "point" | "spot" => SceneLight::Point {
+                position: transform.transform_point(params.point("from", Vec3::zero())?),
+                color: params.color("I", Color::white())? * scale,
+            },
+            "distant" => {
+                let from = params.point("from", Vec3::zero())?;
+                let to = params.point("to", Vec3::new(0.0, 0.0, 1.0))?;
+                SceneLight::Distant {
+                    direction: transform.transform_vector(to - from).normalize(),
+                    color: params.color("L", Color::white())? * scale,
+                }
+            }
+            "infinite" => SceneLight::Infinite(params.color("L", Color::white())? * scale),
+            other => return Err(ImportError::unsupported_at_line(params.line, format!("{} lights", other))),
+        };
+        self.lights.push(light);
+        Ok(())
+    }
+
+    fn shape(&mut self, kind: &str, params: &Params) -> Result<(), ImportError> {
+        let transform = self.world * self.state.transform;
+        let material = Material {
+            emission: self.state.emission,
+            ..self.state.material
+        };
+        self.emitters |= material.emission.channels().iter().any(|channel| *channel > 0.0);
+
+        match kind {
+            // Partial spheres are left whole.
+            "sphere" => {
+                let position = transform.transform_point(Vec3::zero());
+                let radius = params.float("radius", 1.0) * transform.determinant3().abs().cbrt();
+                let extent = Vec3::new(radius, radius, radius);
+                self.add_bounds(Bounds::from_points(&[position - extent, position + extent]));
+                self.objects.push(Box::new(Sphere {
+                    position,
+                    radius,
+                    material,
+                }));
+                Ok(())
+            }
+            "trianglemesh" => {
+                let chunks = |name: &str, width: usize| {
+                    params.numbers(name).unwrap_or_default().chunks_exact(width).map(<[f64]>::to_vec).collect::<Vec<_>>()
+                };
+                let vertices: Vec<Vec3> = chunks("P", 3).iter().map(|p| Vec3::from_slice(p)).collect();
+                let triangles = match params.numbers("indices") {
+                    Some(indices) => indices.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect(),
+                    None if vertices.len() == 3 => vec![[0, 1, 2]],
+                    None => return Err(ImportError::at_line(params.line, "a triangle mesh of more than 3 points needs indices")),
+                };
+                let uv_name = if params.get("uv").is_some() { "uv" } else { "st" };
+                let data = MeshData {
+                    triangles,
+                    normals: chunks("N", 3).iter().map(|n| Vec3::from_slice(n)).collect(),
+                    uvs: chunks(uv_name, 2).iter().map(|uv| (uv[0], uv[1])).collect(),
+                    colors: Vec::new(),
+                    vertices,
+                };
+                self.add_mesh(data, transform, material).map_err(|err| match err {
+                    ImportError::Malformed { line: None, message } => ImportError::at_line(params.line, message),
+                    other => other,
+                })
+            }
+            "plymesh" => {
+                let file = params
+                    .text("filename")
+                    .ok_or_else(|| ImportError::at_line(params.line, "a PLY mesh needs its filename"))?;
+                let data = ply::parse(&fs::read(self.dir.join(file))?)?;
+                self.add_mesh(data, transform, material)
+            }
+            other => Err(ImportError::unsupported_at_line(params.line, format!("{} shapes", other))),
+        }
+    }
+
+    fn add_mesh(&mut self, mut data: MeshData, transform: Mat4, material: Material) -> Result<(), ImportError> {
+        for vertex in &mut data.vertices {
+            *vertex = transform.transform_point(*vertex);
+        }
+        for normal in &mut data.normals {
+            *normal = transform.transform_normal(*normal).normalize();
+        }
+        if (transform.determinant3() < 0.0) != self.state.reverse_orientation {
+            data.triangles.iter_mut().for_each(|triangle| triangle.swap(1, 2));
+        }
+        for (name, count) in &[("normals", data.normals.len()), ("uvs", data.uvs.len())] {
+            if *count != 0 && *count != data.vertices.len() {
+                return Err(ImportError::malformed(format!(
+                    "a mesh has {} {} for {} points",
+                    count,
+                    name,
+                    data.vertices.len()
+                )));
+            }
+        }
+
+        let mesh = data.into_mesh(&ImportOptions {
+            material,
+            ..ImportOptions::default()
+        })?;
+        self.add_bounds(Bounds::from_points(mesh.vertices()));
+        self.objects.push(Box::new(mesh));
+        Ok(())
+    }
+
+    fn add_bounds(&mut self, bounds: Bounds) {
+        self.bounds = Some(self.bounds.map_or(bounds, |all| all.union(bounds)));
+    }
+
+    fn finish(self) -> Renderer {
+        let options = self.options;
+        let bounds = self.bounds.unwrap_or_else(|| Bounds::from_points(&[Vec3::zero()]));
+        let (width, height) = self.resolution;
+        let aspect_ratio = f64::from(width) / f64::from(height);
+
+        // pbrt's field of view spans the shorter side of the image.
+        let fov = if aspect_ratio >= 1.0 {
+            self.fov
+        } else {
+            ((self.fov.to_radians() / 2.0).tan() / aspect_ratio).atan().to_degrees() * 2.0
+        };
+        let camera_to_world = self.world * self.camera_to_world;
+        let camera = oriented_camera(
+            camera_to_world.transform_point(Vec3::zero()),
+            camera_to_world.transform_vector(Vec3::new(0.0, 0.0, 1.0)).normalize(),
+            camera_to_world.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            fov,
+            aspect_ratio,
+        );
+
+        let mut bg_color = options.background;
+        let mut lights = Vec::new();
+        for light in self.lights {
+            lights.push(match light {
+                SceneLight::Point { position, color } => {
+                    let distance = falloff_distance(position, &bounds);
+                    Light {
+                        light_type: LightType::Point,
+                        position,
+                        intensity: options.light_scale / (distance * distance),
+                        color,
+                    }
+                }
+                SceneLight::Distant { direction, color } => Light {
+                    light_type: LightType::Point,
+                    position: bounds.centroid() - direction * (scene_radius(&bounds) * 1000.0),
+                    intensity: options.light_scale,
+                    color,
+                },
+                SceneLight::Infinite(color) => {
+                    bg_color = color;
+                    Light {
+                        light_type: LightType::Ambient,
+                        position: Vec3::zero(),
+                        intensity: options.light_scale,
+                        color,
+                    }
+                }
+            });
+        }
+
+        if lights.is_empty() && !self.emitters {
+            lights.push(Light {
+                light_type: LightType::Point,
+                position: camera.origin(),
+                intensity: options.light_scale,
+                color: Color::white(),
+            });
+        }
+        let has_ambient = lights.iter().any(|light| matches!(light.light_type, LightType::Ambient));
+        if options.ambient > 0.0 && !has_ambient {
+            lights.push(Light {
+                light_type: LightType::Ambient,
+                position: Vec3::zero(),
+                intensity: options.ambient,
+                color: Color::white(),
+            });
+        }
+
+        Renderer {
+            width,
+            height,
+            camera,
+            objects: self.objects,
+            lights,
+            bg_color,
+            options: Cfg {
+                integrator: self.integrator.build(),
+                ..self.cfg
+            },
+        }
+    }
+}
+
+/// pbrt's `LookAt`, from world space to a camera space with z along the view
+/// direction and x to the right of the image, or `None` if `up` is along it.
+fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Option<Mat4> {
+    let direction = (target - eye).normalize();
+    let right = up.normalize().cross(direction);
+    if right.length() < 1e-9 || !right.length().is_finite() {
+        return None;
+    }
+    let right = right.normalize();
+    let up = direction.cross(right);
+
+    let mut camera_to_world = Mat4::translation(eye);
+    for (column, axis) in [right, up, direction].iter().enumerate() {
+        camera_to_world.rows[0][column] = axis.x;
+        camera_to_world.rows[1][column] = axis.y;
+        camera_to_world.rows[2][column] = axis.z;
+    }
+    camera_to_world.inverse()
+}
+
+/// A pbrt material, with Phong parameters close to its BSDF.
+fn material(kind: &str, params: &Params) -> Result<Material, ImportError> {
+    let roughness = |default: f64| {
+        let roughness = params.float("roughness", default);
+        (params.float("uroughness", roughness) + params.float("vroughness", roughness)) / 2.0
+    };
+    // The inverse of the Blinn-Phong to Beckmann mapping of `Material::bsdf`.
+    let exponent = |roughness: f64| 2.0 / roughness.max(0.05).powi(4) - 2.0;
+
+    match kind {
+        "matte" => {
+            let kd = params.color("Kd", Color::new(0.5, 0.5, 0.5))?;
+            Ok(Material {
+                color: kd,
+                diffuse: 1.0,
+                bsdf: Some(Bsdf::Lambertian { albedo: kd }),
+                ..Material::neutral()
+            })
+        }
+        "plastic" => {
+            let kd = params.color("Kd", Color::new(0.25, 0.25, 0.25))?;
+            let ks = params.color("Ks", Color::new(0.25, 0.25, 0.25))?.luminance();
+            let roughness = roughness(0.1);
+            Ok(Material {
+                color: kd,
+                diffuse: 1.0,
+                specular: ks,
+                specular_exponent: exponent(roughness),
+                roughness,
+                bsdf: Some(Bsdf::Principled(Principled {
+                    base_color: kd,
+                    roughness,
+                    specular: (ks * 2.0).min(1.0),
+                    ..Principled::default()
+                })),
+                ..Material::neutral()
+            })
+        }
+        "metal" => {
+            // pbrt's default is copper.
+            let eta = params.color("eta", Color::new(0.2004, 0.9240, 1.1022))?;
+            let k = params.color("k", Color::new(3.9129, 2.4528, 2.1422))?;
+            let roughness = roughness(0.01);
+            Ok(Material {
+                color: fresnel_conductor(1.0, eta, k),
+                specular: 1.0,
+                specular_exponent: exponent(roughness),
+                reflectiveness: 1.0,
+                roughness,
+                bsdf: Some(Bsdf::Conductor { eta, k, roughness }),
+                ..Material::neutral()
+            })
+        }
+        "glass" => {
+            let tint = params.color("Kt", Color::white())?;
+            let ior = params.float("index", params.float("eta", 1.5));
+            Ok(Material {
+                color: tint,
+                reflectiveness: 0.05,
+                opacity: 0.0,
+                bsdf: Some(Bsdf::Dielectric {
+                    ior,
+                    roughness: roughness(0.0),
+                    tint,
+                }),
+                ..Material::neutral()
+            })
+        }
+        other => Err(ImportError::unsupported_at_line(params.line, format!("{} materials", other))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bsdf::Bsdf;
+    use crate::import::pbrt::parse;
+    use crate::import::{ImportError, SceneOptions};
+    use crate::ray::Ray;
+    use crate::vector::Vec3;
+
+    use std::path::Path;
+
+    #[test]
+    fn test_parse() {
+        let scene = r#"
+            # A red sphere over a metal floor, lit by a point light.
+            LookAt 0 0 5  0 0 0  0 1 0
+            Camera "perspective" "float fov" [45]
+            Film "image" "integer xresolution" [64] "integer yresolution" [32]
+            WorldBegin
+            LightSource "point" "point from" [0 4 4] "rgb I" [10 10 10]
+            AttributeBegin
+              Material "matte" "rgb Kd" [0.8 0.1 0.1]
+              Translate 1 0 0
+              Shape "sphere" "float radius" 0.5
+            AttributeEnd
+            Material "metal"
+            Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+                "point P" [-2 -1 -2  2 -1 -2  2 -1 2  -2 -1 2]
+            WorldEnd
+        "#;
+        let renderer = parse(scene, Path::new(""), &SceneOptions::default()).unwrap();
+        assert_eq!((renderer.width, renderer.height), (64, 32));
+        assert!((renderer.camera.origin() - Vec3::new(0.0, 0.0, 5.0)).length() < 1e-9);
+        assert!((renderer.camera.fov() - 45.0).abs() < 1e-9);
+        assert_eq!(renderer.objects.len(), 2);
+        assert_eq!(renderer.lights.len(), 2);
+
+        // pbrt shows x to the left in this view, so the sphere ends up there.
+        let (right, _, _) = renderer.camera.basis();
+        assert!((right - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = Ray::intersect(ray, &renderer.objects).unwrap();
+        assert!((hit.distance - 4.5).abs() < 1e-6);
+        assert!((hit.material.color.channels()[0] - 0.8).abs() < 1e-9);
+
+        let ray = Ray {
+            origin: Vec3::new(0.5, 2.0, 0.5),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = Ray::intersect(ray, &renderer.objects).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-6);
+        assert!(matches!(hit.material.bsdf, Some(Bsdf::Conductor { .. })));
+
+        match parse("WorldBegin\nAttributeBegin\n\nShape \"sphere\" \"float radius\" [1\n", Path::new(""), &SceneOptions::default()) {
+            Err(ImportError::Malformed { line: Some(4), .. }) => {}
+            other => panic!("expected an error on line 4, got {:?}", other.map(|_| ())),
+        }
+        match parse("WorldBegin\nShape \"disk\"\n", Path::new(""), &SceneOptions::default()) {
+            Err(ImportError::Unsupported { line: Some(2), .. }) => {}
+            other => panic!("expected disks to be unsupported, got {:?}", other.map(|_| ())),
+        }
+    }
+}
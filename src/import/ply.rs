@@ -0,0 +1,421 @@
+use crate::color::Color;
+use crate::import::{ImportError, ImportOptions, MeshData};
+use crate::shapes::mesh::Mesh;
+use crate::vector::Vec3;
+
+use std::fs;
+use std::path::Path;
+
+/// Loads a PLY file, in its text or either of its binary encodings, as a mesh.
+/// Vertices may carry normals, texture coordinates and colors, and faces of
+/// more than three corners are split into triangles. Other elements, such as
+/// edges, are skipped.
+pub fn load<P: AsRef<Path>>(path: P, options: &ImportOptions) -> Result<Mesh, ImportError> {
+    parse(&fs::read(path)?)?.into_mesh(options)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// What a color channel of this type is divided by to get 0..1.
+    fn color_range(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.0,
+            Scalar::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    /// Type of the value, or of the items of a list.
+    scalar: Scalar,
+    /// Type of a list's length, for list properties.
+    count: Option<Scalar>,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+/// Reads the values of the body one after another, whatever its encoding.
+struct Body<'a> {
+    encoding: Encoding,
+    bytes: &'a [u8],
+    position: usize,
+    /// Line of the text encoding the next value is on.
+    line: usize,
+}
+
+impl Body<'_> {
+    fn next(&mut self, scalar: Scalar, element: &str) -> Result<f64, ImportError> {
+        if self.encoding == Encoding::Ascii {
+            return self.next_token(element);
+        }
+
+        let size = scalar.size();
+        let bytes = self.bytes.get(self.position..self.position + size).ok_or_else(|| {
+            ImportError::malformed(format!("the file ends in the middle of its '{}' elements", element))
+        })?;
+        self.position += size;
+
+        let mut buffer = [0; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.encoding == Encoding::BigEndian {
+            buffer[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buffer;
+
+        Ok(match scalar {
+            Scalar::I8 => f64::from(b0 as i8),
+            Scalar::U8 => f64::from(b0),
+            Scalar::I16 => f64::from(i16::from_le_bytes([b0, b1])),
+            Scalar::U16 => f64::from(u16::from_le_bytes([b0, b1])),
+            Scalar::I32 => f64::from(i32::from_le_bytes([b0, b1, b2, b3])),
+            Scalar::U32 => f64::from(u32::from_le_bytes([b0, b1, b2, b3])),
+            Scalar::F32 => f64::from(f32::from_le_bytes([b0, b1, b2, b3])),
+            Scalar::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+
+    fn next_token(&mut self, element: &str) -> Result<f64, ImportError> {
+        let rest = &self.bytes[self.position..];
+        let start = rest.iter().position(|byte| !byte.is_ascii_whitespace()).ok_or_else(|| {
+            ImportError::at_line(self.line, format!("the file ends in the middle of its '{}' elements", element))
+        })?;
+        self.line += rest[..start].iter().filter(|byte| **byte == b'\n').count();
+
+        let token = &rest[start..];
+        let end = token.iter().position(|byte| byte.is_ascii_whitespace()).unwrap_or(token.len());
+        self.position += start + end;
+
+        let text = String::from_utf8_lossy(&token[..end]);
+        text.parse().map_err(|_| {
+            ImportError::at_line(self.line, format!("expected a number in a '{}' element, found '{}'", element, text))
+        })
+    }
+}
+
+struct Header {
+    encoding: Encoding,
+    elements: Vec<Element>,
+    /// Offset of the first byte after the header.
+    end: usize,
+    /// Number of lines in the header.
+    lines: usize,
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, ImportError> {
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+    let mut number = 0;
+
+    loop {
+        let rest = &bytes[position..];
+        let length = rest.iter().position(|byte| *byte == b'\n').ok_or_else(|| {
+            ImportError::at_line(number + 1, "the header is missing its 'end_header' line")
+        })?;
+        let line = String::from_utf8_lossy(&rest[..length]);
+        position += length + 1;
+        number += 1;
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if number == 1 {
+            if words != ["ply"] {
+                return Err(ImportError::malformed("not a PLY file, it does not start with 'ply'"));
+            }
+            continue;
+        }
+
+        match words.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, version] => {
+                if *version != "1.0" {
+                    return Err(ImportError::unsupported(format!("PLY version {}", version)));
+                }
+                encoding = Some(match *name {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(ImportError::at_line(number, format!("unknown format '{}'", name))),
+                });
+            }
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| ImportError::at_line(number, format!("'{}' is not a number of elements", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            ["property", ..] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| ImportError::at_line(number, "a property comes before any element"))?;
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or_else(|| ImportError::at_line(number, format!("unknown type '{}'", name)))
+                };
+                let property = match words.as_slice() {
+                    ["property", "list", count, item, name] => Property {
+                        name: name.to_string(),
+                        scalar: scalar(item)?,
+                        count: Some(scalar(count)?),
+                    },
+                    ["property", kind, name] => Property {
+                        name: name.to_string(),
+                        scalar: scalar(kind)?,
+                        count: None,
+                    },
+                    _ => return Err(ImportError::at_line(number, format!("cannot read property '{}'", line.trim()))),
+                };
+                element.properties.push(property);
+            }
+            ["end_header"] => break,
+            _ => return Err(ImportError::at_line(number, format!("unexpected header line '{}'", line.trim()))),
+        }
+    }
+
+    Ok(Header {
+        encoding: encoding.ok_or_else(|| ImportError::malformed("the header gives no format"))?,
+        elements,
+        end: position,
+        lines: number,
+    })
+}
+
+pub(crate) fn parse(bytes: &[u8]) -> Result<MeshData, ImportError> {
+    let header = parse_header(bytes)?;
+    let mut body = Body {
+        encoding: header.encoding,
+        bytes: &bytes[header.end..],
+        position: 0,
+        line: header.lines + 1,
+    };
+    let mut data = MeshData::default();
+
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(element, &mut body, &mut data)?,
+            "face" => read_faces(element, &mut body, &mut data)?,
+            _ => {
+                for _ in 0..element.count {
+                    read_values(element, &mut body, &mut Vec::new())?;
+                }
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Reads one element's values into `values`, lists flattened and preceded by
+/// their length.
+fn read_values(element: &Element, body: &mut Body, values: &mut Vec<f64>) -> Result<(), ImportError> {
+    values.clear();
+    for property in &element.properties {
+        match property.count {
+            Some(count) => {
+                let length = body.next(count, &element.name)?;
+                values.push(length);
+                for _ in 0..length as usize {
+                    values.push(body.next(property.scalar, &element.name)?);
+                }
+            }
+            None => values.push(body.next(property.scalar, &element.name)?),
+        }
+    }
+    Ok(())
+}
+
+fn read_vertices(element: &Element, body: &mut Body, data: &mut MeshData) -> Result<(), ImportError> {
+    if element.properties.iter().any(|property| property.count.is_some()) {
+        return Err(ImportError::unsupported("vertices with list properties".to_string()));
+    }
+    let position = match (element.find(&["x"]), element.find(&["y"]), element.find(&["z"])) {
+        (Some(x), Some(y), Some(z)) => [x, y, z],
+        _ => return Err(ImportError::malformed("vertices have no x, y and z properties")),
+    };
+    let normal = match (element.find(&["nx"]), element.find(&["ny"]), element.find(&["nz"])) {
+        (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+        _ => None,
+    };
+    let uv = match (
+        element.find(&["u", "s", "texture_u", "texture_s"]),
+        element.find(&["v", "t", "texture_v", "texture_t"]),
+    ) {
+        (Some(u), Some(v)) => Some([u, v]),
+        _ => None,
+    };
+    let color = match (
+        element.find(&["red", "diffuse_red"]),
+        element.find(&["green", "diffuse_green"]),
+        element.find(&["blue", "diffuse_blue"]),
+    ) {
+        (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+        _ => None,
+    };
+
+    let mut values = Vec::with_capacity(element.properties.len());
+    for _ in 0..element.count {
+        read_values(element, body, &mut values)?;
+        let vector = |[x, y, z]: [usize; 3]| Vec3::new(values[x], values[y], values[z]);
+
+        data.vertices.push(vector(position));
+        if let Some(normal) = normal {
+            data.normals.push(vector(normal));
+        }
+        if let Some([u, v]) = uv {
+            data.uvs.push((values[u], values[v]));
+        }
+        if let Some(channels) = color {
+            let [r, g, b] = channels.map(|channel| values[channel] / element.properties[channel].scalar.color_range());
+            data.colors.push(Color::new(r, g, b));
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(element: &Element, body: &mut Body, data: &mut MeshData) -> Result<(), ImportError> {
+    let list = element
+        .find(&["vertex_indices", "vertex_index"])
+        .ok_or_else(|| ImportError::malformed("faces have no vertex_indices property"))?;
+    if element.properties[list].count.is_none() {
+        return Err(ImportError::malformed("the vertex_indices of faces are not a list"));
+    }
+
+    let mut values = Vec::new();
+    let mut corners = Vec::new();
+    for face in 0..element.count {
+        let line = body.line;
+        read_values(element, body, &mut values)?;
+
+        // Where the list starts among the element's flattened values.
+        let mut start = 0;
+        for property in &element.properties[..list] {
+            start += match property.count {
+                Some(_) => 1 + values[start] as usize,
+                None => 1,
+            };
+        }
+        let length = values[start] as usize;
+        if length < 3 {
+            let message = format!("face {} has only {} corners", face, length);
+            return Err(match body.encoding {
+                Encoding::Ascii => ImportError::at_line(line, message),
+                _ => ImportError::malformed(message),
+            });
+        }
+
+        corners.clear();
+        for index in &values[start + 1..start + 1 + length] {
+            if *index < 0.0 {
+                return Err(ImportError::malformed(format!("face {} refers to vertex {}", face, index)));
+            }
+            corners.push(*index as usize);
+        }
+        data.add_polygon(&corners);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::import::ply::parse;
+    use crate::import::{ImportError, ImportOptions};
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_ascii_and_binary() {
+        let ascii = b"ply\nformat ascii 1.0\ncomment a unit quad\nelement vertex 4\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 255 0 0\n0 0 1 255 0 0\n1 0 1 0 0 255\n1 0 0 0 0 255\n4 0 1 2 3\n";
+        let data = parse(ascii).unwrap();
+        assert_eq!(data.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!((data.colors[2].channels()[2] - 1.0).abs() < 1e-9);
+
+        let mut binary = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\n\
+            property double x\nproperty double y\nproperty double z\n\
+            element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+            .to_vec();
+        for value in &[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 1.0f64] {
+            binary.extend_from_slice(&value.to_be_bytes());
+        }
+        binary.push(3);
+        for index in &[0u32, 1, 2] {
+            binary.extend_from_slice(&index.to_be_bytes());
+        }
+        let options = ImportOptions {
+            scale: 0.5,
+            recenter: true,
+            smooth_normals: true,
+            ..ImportOptions::default()
+        };
+        let mesh = parse(&binary).unwrap().into_mesh(&options).unwrap();
+        assert!((mesh.vertices()[2] - Vec3::new(0.5, 0.0, 0.25)).length() < 1e-9);
+        assert!((mesh.normals()[0] - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+
+        // Errors in text files name the line they are on.
+        let broken = b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\n\
+            property float z\nend_header\n0 0 zero\n";
+        match parse(broken) {
+            Err(ImportError::Malformed { line: Some(8), .. }) => {}
+            other => panic!("expected an error on line 8, got {:?}", other),
+        }
+    }
+}
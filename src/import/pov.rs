@@ -0,0 +1,1647 @@
+use crate::bsdf::Bsdf;
+use crate::cfg::Cfg;
+use crate::color::Color;
+use crate::import::{oriented_camera, ImportError, SceneOptions};
+use crate::light::{Light, LightType};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::renderer::Renderer;
+use crate::shapes::aabb::Aabb;
+use crate::shapes::csg::{Csg, Operation};
+use crate::shapes::mesh::Mesh;
+use crate::shapes::plane::Plane;
+use crate::shapes::sphere::Sphere;
+use crate::shapes::transformed::Transformed;
+use crate::shapes::Shape;
+use crate::vector::Vec3;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Objects and files nested deeper than this are taken to be broken.
+const MAX_DEPTH: usize = 64;
+
+/// Standard include files that are skipped when they are not beside the
+/// scene, so that names they declare are reported where they are used.
+const STANDARD_INCLUDES: &[&str] = &[
+    "consts.inc",
+    "finish.inc",
+    "functions.inc",
+    "glass.inc",
+    "golds.inc",
+    "math.inc",
+    "metals.inc",
+    "shapes.inc",
+    "skies.inc",
+    "stdinc.inc",
+    "stones.inc",
+    "textures.inc",
+    "transforms.inc",
+    "woods.inc",
+];
+
+/// The common colors of `colors.inc`, for scenes that include it without
+/// having it beside them.
+const COLORS_INC: &[(&str, [f64; 5])] = &[
+    ("Red", [1.0, 0.0, 0.0, 0.0, 0.0]),
+    ("Green", [0.0, 1.0, 0.0, 0.0, 0.0]),
+    ("Blue", [0.0, 0.0, 1.0, 0.0, 0.0]),
+    ("Yellow", [1.0, 1.0, 0.0, 0.0, 0.0]),
+    ("Cyan", [0.0, 1.0, 1.0, 0.0, 0.0]),
+    ("Magenta", [1.0, 0.0, 1.0, 0.0, 0.0]),
+    ("Clear", [1.0, 1.0, 1.0, 1.0, 0.0]),
+    ("White", [1.0, 1.0, 1.0, 0.0, 0.0]),
+    ("Black", [0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("Gray", [0.752941, 0.752941, 0.752941, 0.0, 0.0]),
+    ("Grey", [0.752941, 0.752941, 0.752941, 0.0, 0.0]),
+    ("Gray10", [0.1, 0.1, 0.1, 0.0, 0.0]),
+    ("Gray20", [0.2, 0.2, 0.2, 0.0, 0.0]),
+    ("Gray30", [0.3, 0.3, 0.3, 0.0, 0.0]),
+    ("Gray40", [0.4, 0.4, 0.4, 0.0, 0.0]),
+    ("Gray50", [0.5, 0.5, 0.5, 0.0, 0.0]),
+    ("Gray60", [0.6, 0.6, 0.6, 0.0, 0.0]),
+    ("Gray70", [0.7, 0.7, 0.7, 0.0, 0.0]),
+    ("Gray80", [0.8, 0.8, 0.8, 0.0, 0.0]),
+    ("Gray90", [0.9, 0.9, 0.9, 0.0, 0.0]),
+    ("Orange", [1.0, 0.5, 0.0, 0.0, 0.0]),
+    ("Brown", [0.647059, 0.164706, 0.164706, 0.0, 0.0]),
+    ("Pink", [0.737255, 0.560784, 0.560784, 0.0, 0.0]),
+    ("Violet", [0.309804, 0.184314, 0.309804, 0.0, 0.0]),
+    ("Gold", [0.8, 0.498039, 0.196078, 0.0, 0.0]),
+    ("Silver", [0.9, 0.91, 0.98, 0.0, 0.0]),
+    ("SkyBlue", [0.196078, 0.6, 0.8, 0.0, 0.0]),
+    ("Navy", [0.137255, 0.137255, 0.556863, 0.0, 0.0]),
+    ("ForestGreen", [0.137255, 0.556863, 0.137255, 0.0, 0.0]),
+    ("Wheat", [0.847059, 0.847059, 0.74902, 0.0, 0.0]),
+];
+
+/// Loads a scene written in a subset of POV-Ray's scene description language.
+///
+/// Supported are the perspective camera, point and spot lights, spheres,
+/// boxes, planes, triangles and smooth triangles, unions, merges,
+/// differences and intersections, `object` references, translations,
+/// rotations, scaling and matrices, solid color pigments, finishes and
+/// interiors, `#declare`d values, objects and textures, `#include` and the
+/// background, with float and vector arithmetic. Anything else, such as other
+/// objects, pigment patterns or control flow, is reported as unsupported with
+/// its line. Normal perturbations and focal blur are left out, and lights
+/// always cast shadows.
+///
+/// POV-Ray's space is left-handed, so scenes are mirrored along z unless
+/// their camera's `right` vector makes it right-handed. Finishes become Phong
+/// materials. The scene's ambient light stands for POV-Ray's default ambient
+/// finish of 0.1, and surfaces with more ambient than that glow by the rest.
+pub fn load<P: AsRef<Path>>(path: P, options: &SceneOptions) -> Result<Renderer, ImportError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    parse(&text, path.parent().unwrap_or_else(|| Path::new("")), options)
+}
+
+fn parse(text: &str, dir: &Path, options: &SceneOptions) -> Result<Renderer, ImportError> {
+    let mut context = Context {
+        dir: dir.to_path_buf(),
+        options: *options,
+        declared: HashMap::new(),
+        default_texture: Texture::default(),
+        scene: Scene::default(),
+    };
+    Parser::new(text, &mut context, 0)?.scene()?;
+    context.scene.build(options)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f64),
+    Text(String),
+    Symbol(char),
+    /// A word following `#`, such as `declare`.
+    Directive(String),
+}
+
+/// Splits a file into tokens, each with the line it is on.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, ImportError> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    let word_end = |mut i: usize| {
+        while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+            i += 1;
+        }
+        i
+    };
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let next = bytes.get(i + 1).cloned().unwrap_or(0);
+        match byte {
+            b'\n' => {
+                line += 1;
+                i += 1;
+            }
+            _ if byte.is_ascii_whitespace() => i += 1,
+            b'/' if next == b'/' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if next == b'*' => {
+                let start = line;
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    if bytes[i] == b'\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                if i >= bytes.len() {
+                    return Err(ImportError::at_line(start, "a comment is not closed"));
+                }
+                i += 2;
+            }
+            b'"' => {
+                let start = i + 1;
+                let end = bytes[start..]
+                    .iter()
+                    .position(|byte| *byte == b'"' || *byte == b'\n')
+                    .map(|length| start + length)
+                    .filter(|end| bytes[*end] == b'"')
+                    .ok_or_else(|| ImportError::at_line(line, "a string is not closed on its line"))?;
+                tokens.push((Token::Text(text[start..end].to_string()), line));
+                i = end + 1;
+            }
+            b'#' => {
+                let end = word_end(i + 1);
+                if end == i + 1 {
+                    return Err(ImportError::at_line(line, "'#' is not followed by a directive"));
+                }
+                tokens.push((Token::Directive(text[i + 1..end].to_string()), line));
+                i = end;
+            }
+            _ if byte.is_ascii_digit() || (byte == b'.' && next.is_ascii_digit()) => {
+                let start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+                    let sign = usize::from(matches!(bytes.get(i + 1), Some(b'+') | Some(b'-')));
+                    if bytes.get(i + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                        i += 1 + sign;
+                        while i < bytes.len() && bytes[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let number = &text[start..i];
+                let number = number.parse().map_err(|_| ImportError::at_line(line, format!("'{}' is not a number", number)))?;
+                tokens.push((Token::Number(number), line));
+            }
+            _ if byte.is_ascii_alphabetic() || byte == b'_' => {
+                let end = word_end(i);
+                tokens.push((Token::Word(text[i..end].to_string()), line));
+                i = end;
+            }
+            b'{' | b'}' | b'<' | b'>' | b',' | b'(' | b')' | b'+' | b'-' | b'*' | b'/' | b'=' | b';' => {
+                tokens.push((Token::Symbol(byte as char), line));
+                i += 1;
+            }
+            _ => {
+                let character = text[i..].chars().next().unwrap_or('?');
+                return Err(ImportError::at_line(line, format!("unexpected character '{}'", character)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A float, or a vector or color of up to five components. Floats fill
+/// every component, so that they combine with vectors as POV-Ray promotes them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Value {
+    components: [f64; 5],
+    len: usize,
+}
+
+impl Value {
+    fn float(value: f64) -> Value {
+        Value {
+            components: [value; 5],
+            len: 1,
+        }
+    }
+
+    fn vector(values: &[f64]) -> Value {
+        let mut components = [0.0; 5];
+        components[..values.len()].copy_from_slice(values);
+        Value {
+            components,
+            len: values.len(),
+        }
+    }
+
+    fn combine(self, other: Value, op: fn(f64, f64) -> f64) -> Value {
+        let mut components = [0.0; 5];
+        for (i, component) in components.iter_mut().enumerate() {
+            *component = op(self.components[i], other.components[i]);
+        }
+        Value {
+            components,
+            len: self.len.max(other.len),
+        }
+    }
+
+    fn vec3(self) -> Vec3 {
+        Vec3::from_slice(&self.components[..3])
+    }
+
+    /// The value as the red, green, blue, filter and transmit of a color.
+    fn color(self) -> [f64; 5] {
+        match self.len {
+            1 | 3 => [self.components[0], self.components[1], self.components[2], 0.0, 0.0],
+            _ => self.components,
+        }
+    }
+}
+
+/// Color and surface of a texture, each inherited from the enclosing object
+/// where it is not given.
+#[derive(Clone, Copy, Debug, Default)]
+struct Texture {
+    pigment: Option<[f64; 5]>,
+    finish: Option<Finish>,
+}
+
+impl Texture {
+    fn or(self, inherited: Texture) -> Texture {
+        Texture {
+            pigment: self.pigment.or(inherited.pigment),
+            finish: self.finish.or(inherited.finish),
+        }
+    }
+
+    /// The Phong material closest to the texture. Pigments default to black,
+    /// as in POV-Ray, and see-through ones refract when given an `ior`.
+    fn material(self, ior: Option<f64>) -> Material {
+        let [r, g, b, filter, transmit] = self.pigment.unwrap_or([0.0; 5]);
+        let finish = self.finish.unwrap_or_default();
+        let color = Color::new(r, g, b);
+        let opacity = (1.0 - filter.max(transmit)).clamp(0.0, 1.0);
+        let (specular, specular_exponent) = if finish.phong > 0.0 {
+            (finish.phong, finish.phong_size)
+        } else {
+            (finish.specular, 1.0 / finish.roughness.max(1e-4))
+        };
+
+        Material {
+            color,
+            diffuse: finish.diffuse,
+            specular,
+            specular_exponent,
+            reflectiveness: finish.reflection,
+            opacity,
+            bsdf: match ior {
+                Some(ior) if opacity < 1.0 => Some(Bsdf::Dielectric { ior, roughness: 0.0, tint: color }),
+                _ => None,
+            },
+            emission: color * (finish.emission + (finish.ambient - DEFAULT_AMBIENT).max(0.0)),
+            ..Material::neutral()
+        }
+    }
+}
+
+/// POV-Ray's ambient finish, which the scene's ambient light stands for.
+const DEFAULT_AMBIENT: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug)]
+struct Finish {
+    ambient: f64,
+    diffuse: f64,
+    specular: f64,
+    roughness: f64,
+    phong: f64,
+    phong_size: f64,
+    reflection: f64,
+    emission: f64,
+}
+
+impl Default for Finish {
+    fn default() -> Finish {
+        Finish {
+            ambient: DEFAULT_AMBIENT,
+            diffuse: 0.6,
+            specular: 0.0,
+            roughness: 0.05,
+            phong: 0.0,
+            phong_size: 40.0,
+            reflection: 0.0,
+            emission: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Geometry {
+    Sphere { center: Vec3, radius: f64 },
+    Box { corner1: Vec3, corner2: Vec3 },
+    Plane { normal: Vec3, distance: f64 },
+    Triangle { corners: [Vec3; 3], normals: Option<[Vec3; 3]> },
+    /// A plain union without an operation, which keeps the surfaces inside
+    /// its objects as POV-Ray's `union` does.
+    Group(Vec<Object>),
+    Csg(Operation, Vec<Object>),
+}
+
+#[derive(Clone, Debug)]
+struct Object {
+    geometry: Geometry,
+    /// From the object's own space to POV-Ray's world space.
+    transform: Mat4,
+    texture: Texture,
+    ior: Option<f64>,
+    line: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Declared {
+    Value(Value),
+    Object(Box<Object>),
+    Pigment([f64; 5]),
+    Finish(Finish),
+    Texture(Texture),
+    Interior(Option<f64>),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CameraSpec {
+    location: Vec3,
+    look_at: Option<Vec3>,
+    direction: Vec3,
+    up: Vec3,
+    right: Vec3,
+    sky: Vec3,
+    /// Horizontal field of view in degrees.
+    angle: Option<f64>,
+    transform: Mat4,
+}
+
+impl Default for CameraSpec {
+    fn default() -> CameraSpec {
+        CameraSpec {
+            location: Vec3::zero(),
+            look_at: None,
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            right: Vec3::new(1.33, 0.0, 0.0),
+            sky: Vec3::new(0.0, 1.0, 0.0),
+            angle: None,
+            transform: Mat4::identity(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Scene {
+    camera: CameraSpec,
+    objects: Vec<Object>,
+    /// Positions and colors of the light sources.
+    lights: Vec<(Vec3, Color)>,
+    background: Option<Color>,
+    ambient_light: Option<Color>,
+    max_trace_level: Option<f64>,
+}
+
+impl Scene {
+    fn build(self, options: &SceneOptions) -> Result<Renderer, ImportError> {
+        let camera = self.camera;
+        // POV-Ray's image has `right` on its right, which is `up` x
+        // `direction` in its left-handed space and the other way round in
+        // ours, so the scene is mirrored unless `right` says otherwise.
+        let world = if camera.up.cross(camera.direction).dot(camera.right) >= 0.0 {
+            Mat4::scaling(Vec3::new(1.0, 1.0, -1.0))
+        } else {
+            Mat4::identity()
+        };
+
+        let (forward, up) = match camera.look_at {
+            Some(look_at) => (look_at - camera.location, camera.sky),
+            None => (camera.direction, camera.up),
+        };
+        let fov = match camera.angle {
+            Some(angle) => ((angle.to_radians() / 2.0).tan() * camera.up.length() / camera.right.length()).atan() * 2.0,
+            None => (camera.up.length() / 2.0 / camera.direction.length()).atan() * 2.0,
+        };
+        let to_world = world * camera.transform;
+        let camera = oriented_camera(
+            to_world.transform_point(camera.location),
+            to_world.transform_vector(forward).normalize(),
+            to_world.transform_vector(up),
+            fov.to_degrees(),
+            f64::from(options.width) / f64::from(options.height.max(1)),
+        );
+
+        let mut objects = Vec::new();
+        for object in &self.objects {
+            build(object, world, Texture::default(), None, false, &mut objects)?;
+        }
+
+        let mut lights: Vec<Light> = self
+            .lights
+            .iter()
+            .map(|(position, color)| Light {
+                light_type: LightType::Point,
+                position: world.transform_point(*position),
+                intensity: options.light_scale,
+                color: *color,
+            })
+            .collect();
+        if options.ambient > 0.0 {
+            lights.push(Light {
+                light_type: LightType::Ambient,
+                position: Vec3::zero(),
+                intensity: options.ambient,
+                color: self.ambient_light.unwrap_or_else(Color::white),
+            });
+        }
+
+        let mut cfg = Cfg {
+            integrator: options.integrator.build(),
+            ..Cfg::default()
+        };
+        if let Some(level) = self.max_trace_level {
+            cfg.max_rays = level.clamp(1.0, 255.0) as u8;
+        }
+
+        Ok(Renderer {
+            width: options.width,
+            height: options.height,
+            camera,
+            objects,
+            lights,
+            bg_color: self.background.unwrap_or(options.background),
+            options: cfg,
+        })
+    }
+}
+
+/// Adds the shapes making up `object` to `shapes`. Inside a difference,
+/// intersection or merge, every object must be a single solid.
+fn build(
+    object: &Object,
+    parent: Mat4,
+    inherited: Texture,
+    inherited_ior: Option<f64>,
+    solid: bool,
+    shapes: &mut Vec<Box<dyn Shape>>,
+) -> Result<(), ImportError> {
+    let transform = parent * object.transform;
+    let texture = object.texture.or(inherited);
+    let ior = object.ior.or(inherited_ior);
+    let material = texture.material(ior);
+    let line = object.line;
+    let flattened = || ImportError::at_line(line, "the object is scaled flat");
+
+    let shape: Box<dyn Shape> = match &object.geometry {
+        Geometry::Sphere { center, radius } => {
+            // Spheres stay spheres unless they are stretched.
+            let axes = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)]
+                .map(|axis| transform.transform_vector(axis));
+            let scale = axes[0].length();
+            let uniform = axes.iter().all(|axis| (axis.length() - scale).abs() < 1e-9 * scale.max(1.0))
+                && axes[0].dot(axes[1]).abs() < 1e-9
+                && axes[1].dot(axes[2]).abs() < 1e-9
+                && axes[0].dot(axes[2]).abs() < 1e-9;
+            if uniform && scale > 0.0 {
+                Box::new(Sphere {
+                    position: transform.transform_point(*center),
+                    radius: radius * scale,
+                    material,
+                })
+            } else {
+                let sphere = Sphere {
+                    position: *center,
+                    radius: *radius,
+                    material,
+                };
+                Box::new(Transformed::new(Box::new(sphere), transform).ok_or_else(flattened)?)
+            }
+        }
+        Geometry::Box { corner1, corner2 } => {
+            let min = Vec3::new(corner1.x.min(corner2.x), corner1.y.min(corner2.y), corner1.z.min(corner2.z));
+            let max = Vec3::new(corner1.x.max(corner2.x), corner1.y.max(corner2.y), corner1.z.max(corner2.z));
+            // Boxes that are only moved and scaled stay lined up with the axes.
+            let m = &transform.rows;
+            let aligned = m[0][1] == 0.0 && m[0][2] == 0.0 && m[1][0] == 0.0 && m[1][2] == 0.0 && m[2][0] == 0.0 && m[2][1] == 0.0;
+            if aligned {
+                let (a, b) = (transform.transform_point(min), transform.transform_point(max));
+                if (a.x - b.x) * (a.y - b.y) * (a.z - b.z) == 0.0 {
+                    return Err(flattened());
+                }
+                Box::new(Aabb {
+                    min: Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+                    max: Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+                    material,
+                })
+            } else {
+                Box::new(Transformed::new(Box::new(Aabb { min, max, material }), transform).ok_or_else(flattened)?)
+            }
+        }
+        Geometry::Plane { normal, distance } => {
+            let normal = normal.normalize();
+            let surface_normal = transform.transform_normal(normal).normalize();
+            Box::new(Plane {
+                position: transform.transform_point(normal * *distance),
+                // `Plane` is seen from the side opposite its `normal`.
+                normal: -surface_normal,
+                material,
+            })
+        }
+        Geometry::Triangle { corners, normals } => {
+            if solid {
+                return Err(ImportError::unsupported_at_line(
+                    line,
+                    "triangles inside a difference, intersection or merge",
+                ));
+            }
+            let vertices = corners.iter().map(|corner| transform.transform_point(*corner)).collect();
+            let mut mesh = Mesh::new(vertices, vec![[0, 1, 2]], material);
+            if let Some(normals) = normals {
+                mesh = mesh.with_normals(normals.iter().map(|normal| transform.transform_normal(*normal).normalize()).collect());
+            }
+            Box::new(mesh)
+        }
+        Geometry::Group(children) if !solid => {
+            for child in children {
+                build(child, transform, texture, ior, false, shapes)?;
+            }
+            return Ok(());
+        }
+        Geometry::Group(children) | Geometry::Csg(Operation::Union, children) => csg(Operation::Union, children, transform, texture, ior)?,
+        Geometry::Csg(operation, children) => csg(*operation, children, transform, texture, ior)?,
+    };
+
+    shapes.push(shape);
+    Ok(())
+}
+
+fn csg(operation: Operation, children: &[Object], transform: Mat4, texture: Texture, ior: Option<f64>) -> Result<Box<dyn Shape>, ImportError> {
+    let mut shapes = Vec::new();
+    for child in children {
+        build(child, transform, texture, ior, true, &mut shapes)?;
+    }
+    Ok(Box::new(Csg::new(operation, shapes)))
+}
+
+/// Everything the files of a scene share.
+struct Context {
+    dir: PathBuf,
+    options: SceneOptions,
+    declared: HashMap<String, Declared>,
+    default_texture: Texture,
+    scene: Scene,
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    context: &'a mut Context,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn new<'a>(text: &str, context: &'a mut Context, depth: usize) -> Result<Parser<'a>, ImportError> {
+        Ok(Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+            context,
+            depth,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).map(|(token, _)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    /// The line of the next token, or of the last one at the end of the file.
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).or_else(|| self.tokens.last()).map_or(1, |(_, line)| *line)
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> ImportError {
+        ImportError::at_line(self.line(), message)
+    }
+
+    fn unsupported(&self, what: &str) -> ImportError {
+        ImportError::unsupported_at_line(self.line(), what)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ImportError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn word(&mut self) -> Result<String, ImportError> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            _ => {
+                self.position -= 1;
+                Err(self.error("expected a name"))
+            }
+        }
+    }
+
+    fn deeper(&mut self) -> Result<(), ImportError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            Err(self.error("the scene is nested too deeply"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Skips a block whose `{` is next, with the blocks inside it.
+    fn skip_block(&mut self) -> Result<(), ImportError> {
+        self.expect('{')?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Token::Symbol('{')) => depth += 1,
+                Some(Token::Symbol('}')) => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.error("a block is not closed")),
+            }
+        }
+        Ok(())
+    }
+
+    fn scene(&mut self) -> Result<(), ImportError> {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Directive(directive) => self.directive(&directive)?,
+                Token::Word(word) => self.item(&word)?,
+                Token::Symbol(';') => self.position += 1,
+                _ => return Err(self.error("expected an object, a camera, a light or a directive")),
+            }
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str) -> Result<(), ImportError> {
+        self.position += 1;
+        match directive {
+            "declare" | "local" => {
+                let name = self.word()?;
+                self.expect('=')?;
+                let declared = self.declaration()?;
+                self.eat(';');
+                self.context.declared.insert(name, declared);
+            }
+            "include" => {
+                let file = match self.next() {
+                    Some(Token::Text(file)) => file,
+                    _ => return Err(self.error("#include needs a file name in quotes")),
+                };
+                self.include(&file)?;
+            }
+            "version" => {
+                self.float()?;
+                self.eat(';');
+            }
+            "default" => {
+                self.expect('{')?;
+                while !self.eat('}') {
+                    let texture = self.texture_item()?.ok_or_else(|| self.unsupported("this #default setting"))?;
+                    self.context.default_texture = texture.or(self.context.default_texture);
+                }
+            }
+            other => {
+                self.position -= 1;
+                return Err(self.unsupported(&format!("the #{} directive", other)));
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, file: &str) -> Result<(), ImportError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("files include each other too deeply"));
+        }
+        let path = self.context.dir.join(file);
+        if !path.exists() {
+            if file == "colors.inc" {
+                for (name, color) in COLORS_INC {
+                    self.context.declared.insert(name.to_string(), Declared::Value(Value::vector(color)));
+                }
+                return Ok(());
+            }
+            if STANDARD_INCLUDES.contains(&file) {
+                return Ok(());
+            }
+        }
+
+        let text = fs::read_to_string(path)?;
+        let depth = self.depth + 1;
+        Parser::new(&text, self.context, depth)
+            .and_then(|mut parser| parser.scene())
+            .map_err(|err| match err {
+                ImportError::Malformed { line, message } => ImportError::Malformed {
+                    line,
+                    message: format!("{} (in {})", message, file),
+                },
+                other => other,
+            })
+    }
+
+    fn declaration(&mut self) -> Result<Declared, ImportError> {
+        let word = self.peek_word().unwrap_or("").to_string();
+        match word.as_str() {
+            "pigment" => {
+                self.position += 1;
+                Ok(Declared::Pigment(self.pigment()?))
+            }
+            "finish" => {
+                self.position += 1;
+                Ok(Declared::Finish(self.finish()?))
+            }
+            "texture" => {
+                self.position += 1;
+                Ok(Declared::Texture(self.texture()?))
+            }
+            "interior" => {
+                self.position += 1;
+                Ok(Declared::Interior(self.interior()?))
+            }
+            _ if is_color_keyword(&word) => Ok(Declared::Value(Value::vector(&self.color()?))),
+            _ if self.is_object(&word) => Ok(Declared::Object(Box::new(self.object()?))),
+            _ => Ok(Declared::Value(self.expression()?)),
+        }
+    }
+
+    fn is_object(&self, word: &str) -> bool {
+        matches!(
+            word,
+            "sphere" | "box" | "plane" | "triangle" | "smooth_triangle" | "union" | "merge" | "difference" | "intersection" | "object"
+        )
+    }
+
+    fn item(&mut self, word: &str) -> Result<(), ImportError> {
+        match word {
+            "camera" => {
+                self.position += 1;
+                self.camera()
+            }
+            "light_source" => {
+                self.position += 1;
+                self.light_source()
+            }
+            "background" => {
+                self.position += 1;
+                self.expect('{')?;
+                let [r, g, b, _, _] = self.color()?;
+                self.expect('}')?;
+                self.context.scene.background = Some(Color::new(r, g, b));
+                Ok(())
+            }
+            "global_settings" => {
+                self.position += 1;
+                self.global_settings()
+            }
+            _ if self.is_object(word) => {
+                let object = self.object()?;
+                self.context.scene.objects.push(object);
+                Ok(())
+            }
+            _ if self.tokens.get(self.position + 1).map(|(token, _)| token) == Some(&Token::Symbol('{')) => {
+                Err(self.unsupported(&format!("{} statements", word)))
+            }
+            _ => Err(self.error(format!("unexpected '{}'", word))),
+        }
+    }
+
+    fn camera(&mut self) -> Result<(), ImportError> {
+        self.expect('{')?;
+        let mut camera = CameraSpec::default();
+        loop {
+            let word = match self.next() {
+                Some(Token::Symbol('}')) => break,
+                Some(Token::Word(word)) => word,
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected a camera setting"));
+                }
+            };
+            match word.as_str() {
+                "perspective" => {}
+                "location" => camera.location = self.vector()?,
+                "look_at" => camera.look_at = Some(self.vector()?),
+                "direction" => camera.direction = self.vector()?,
+                "up" => camera.up = self.vector()?,
+                "right" => camera.right = self.vector()?,
+                "sky" => camera.sky = self.vector()?,
+                "angle" => camera.angle = Some(self.float()?),
+                // Focal blur is left out.
+                "aperture" | "blur_samples" | "confidence" | "variance" => {
+                    self.float()?;
+                }
+                "focal_point" => {
+                    self.vector()?;
+                }
+                "translate" | "rotate" | "scale" | "matrix" => {
+                    self.position -= 1;
+                    camera.transform = self.transform()?.unwrap_or_else(Mat4::identity) * camera.transform;
+                }
+                other => {
+                    self.position -= 1;
+                    return Err(self.unsupported(&format!("the camera setting '{}'", other)));
+                }
+            }
+        }
+        self.context.scene.camera = camera;
+        Ok(())
+    }
+
+    fn light_source(&mut self) -> Result<(), ImportError> {
+        self.expect('{')?;
+        let mut position = self.vector()?;
+        self.eat(',');
+        let [r, g, b, _, _] = self.color()?;
+
+        loop {
+            let word = match self.next() {
+                Some(Token::Symbol('}')) => break,
+                Some(Token::Word(word)) => word,
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected a light setting"));
+                }
+            };
+            match word.as_str() {
+                // Spot lights leave out their cones, and lights do not fade.
+                "spotlight" | "shadowless" => {}
+                "radius" | "falloff" | "tightness" | "fade_distance" | "fade_power" => {
+                    self.float()?;
+                }
+                "point_at" => {
+                    self.vector()?;
+                }
+                "media_interaction" | "media_attenuation" => {
+                    self.optional_float()?;
+                }
+                "translate" | "rotate" | "scale" | "matrix" => {
+                    self.position -= 1;
+                    position = self.transform()?.unwrap_or_else(Mat4::identity).transform_point(position);
+                }
+                other => {
+                    self.position -= 1;
+                    return Err(self.unsupported(&format!("the light setting '{}'", other)));
+                }
+            }
+        }
+        self.context.scene.lights.push((position, Color::new(r, g, b)));
+        Ok(())
+    }
+
+    fn global_settings(&mut self) -> Result<(), ImportError> {
+        self.expect('{')?;
+        loop {
+            match self.next() {
+                Some(Token::Symbol('}')) => break,
+                Some(Token::Word(word)) if word == "ambient_light" => {
+                    let [r, g, b, _, _] = self.color()?;
+                    self.context.scene.ambient_light = Some(Color::new(r, g, b));
+                }
+                Some(Token::Word(word)) if word == "max_trace_level" => {
+                    self.context.scene.max_trace_level = Some(self.float()?);
+                }
+                // Other settings, such as radiosity, are left to the renderer.
+                Some(Token::Symbol('{')) => {
+                    self.position -= 1;
+                    self.skip_block()?;
+                }
+                Some(_) => {}
+                None => return Err(self.error("global_settings is not closed")),
+            }
+        }
+        Ok(())
+    }
+
+    /// An object, its keyword being next.
+    fn object(&mut self) -> Result<Object, ImportError> {
+        self.deeper()?;
+        let line = self.line();
+        let keyword = self.word()?;
+        self.expect('{')?;
+
+        let mut object = match keyword.as_str() {
+            "sphere" => {
+                let center = self.vector()?;
+                self.eat(',');
+                let radius = self.float()?;
+                Object::new(Geometry::Sphere { center, radius }, line)
+            }
+            "box" => {
+                let corner1 = self.vector()?;
+                self.eat(',');
+                let corner2 = self.vector()?;
+                Object::new(Geometry::Box { corner1, corner2 }, line)
+            }
+            "plane" => {
+                let normal = self.vector()?;
+                self.eat(',');
+                let distance = self.float()?;
+                if normal.length() == 0.0 {
+                    return Err(ImportError::at_line(line, "a plane's normal has no length"));
+                }
+                Object::new(Geometry::Plane { normal, distance }, line)
+            }
+            "triangle" => {
+                let mut corners = [Vec3::zero(); 3];
+                for corner in &mut corners {
+                    *corner = self.vector()?;
+                    self.eat(',');
+                }
+                Object::new(Geometry::Triangle { corners, normals: None }, line)
+            }
+            "smooth_triangle" => {
+                let mut corners = [Vec3::zero(); 3];
+                let mut normals = [Vec3::zero(); 3];
+                for (corner, normal) in corners.iter_mut().zip(normals.iter_mut()) {
+                    *corner = self.vector()?;
+                    self.eat(',');
+                    *normal = self.vector()?;
+                    self.eat(',');
+                }
+                Object::new(Geometry::Triangle { corners, normals: Some(normals) }, line)
+            }
+            "object" => {
+                let name = self.word()?;
+                match self.context.declared.get(&name) {
+                    Some(Declared::Object(object)) => (**object).clone(),
+                    _ => return Err(ImportError::at_line(line, format!("'{}' is not a declared object", name))),
+                }
+            }
+            "union" | "merge" | "difference" | "intersection" => {
+                let mut children = Vec::new();
+                while let Some(word) = self.peek_word() {
+                    if !self.is_object(word) {
+                        break;
+                    }
+                    children.push(self.object()?);
+                }
+                if children.is_empty() {
+                    return Err(ImportError::at_line(line, format!("a {} has no objects", keyword)));
+                }
+                let geometry = match keyword.as_str() {
+                    "union" => Geometry::Group(children),
+                    "merge" => Geometry::Csg(Operation::Union, children),
+                    "difference" => Geometry::Csg(Operation::Difference, children),
+                    _ => Geometry::Csg(Operation::Intersection, children),
+                };
+                Object::new(geometry, line)
+            }
+            other => return Err(ImportError::unsupported_at_line(line, format!("{} objects", other))),
+        };
+        if object.texture.pigment.is_none() && object.texture.finish.is_none() {
+            object.texture = object.texture.or(self.context.default_texture);
+        }
+
+        self.modifiers(&mut object)?;
+        self.depth -= 1;
+        Ok(object)
+    }
+
+    /// The modifiers of an object up to its closing `}`.
+    fn modifiers(&mut self, object: &mut Object) -> Result<(), ImportError> {
+        loop {
+            if self.eat('}') {
+                return Ok(());
+            }
+            if let Some(transform) = self.transform()? {
+                object.transform = transform * object.transform;
+                continue;
+            }
+            if let Some(texture) = self.texture_item()? {
+                object.texture = texture.or(object.texture);
+                continue;
+            }
+
+            let word = match self.next() {
+                Some(Token::Word(word)) => word,
+                None => return Err(self.error("an object is not closed")),
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected an object modifier"));
+                }
+            };
+            match word.as_str() {
+                "interior" => object.ior = self.interior()?.or(object.ior),
+                "material" => {
+                    self.expect('{')?;
+                    while !self.eat('}') {
+                        if let Some(texture) = self.texture_item()? {
+                            object.texture = texture.or(object.texture);
+                        } else if self.peek_word() == Some("interior") {
+                            self.position += 1;
+                            object.ior = self.interior()?.or(object.ior);
+                        } else if self.transform()?.is_none() {
+                            return Err(self.unsupported("this material setting"));
+                        }
+                    }
+                }
+                "bounded_by" => self.skip_block()?,
+                "no_shadow" | "no_image" | "no_reflection" | "double_illuminate" => {}
+                "hollow" => {
+                    self.optional_float()?;
+                }
+                other => {
+                    self.position -= 1;
+                    return Err(self.unsupported(&format!("the object modifier '{}'", other)));
+                }
+            }
+        }
+    }
+
+    /// A transform if one is next.
+    fn transform(&mut self) -> Result<Option<Mat4>, ImportError> {
+        let transform = match self.peek_word() {
+            Some("translate") => {
+                self.position += 1;
+                Mat4::translation(self.vector()?)
+            }
+            Some("scale") => {
+                self.position += 1;
+                Mat4::scaling(self.vector()?)
+            }
+            // POV-Ray turns about x, then y, then z.
+            Some("rotate") => {
+                self.position += 1;
+                let angles = self.vector()?;
+                Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), angles.z)
+                    * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), angles.y)
+                    * Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), angles.x)
+            }
+            // Twelve values, the rows of the linear part and then the
+            // translation, transforming row vectors.
+            Some("matrix") => {
+                self.position += 1;
+                self.expect('<')?;
+                let mut values = [0.0; 12];
+                for (i, value) in values.iter_mut().enumerate() {
+                    if i > 0 {
+                        self.expect(',')?;
+                    }
+                    *value = self.float()?;
+                }
+                self.expect('>')?;
+                let mut matrix = Mat4::identity();
+                for row in 0..3 {
+                    for column in 0..3 {
+                        matrix.rows[row][column] = values[column * 3 + row];
+                    }
+                    matrix.rows[row][3] = values[9 + row];
+                }
+                matrix
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(transform))
+    }
+
+    /// A pigment, finish, texture or declared texture if one is next, with
+    /// normal perturbations skipped.
+    fn texture_item(&mut self) -> Result<Option<Texture>, ImportError> {
+        let texture = match self.peek_word() {
+            Some("pigment") => {
+                self.position += 1;
+                Texture {
+                    pigment: Some(self.pigment()?),
+                    finish: None,
+                }
+            }
+            Some("finish") => {
+                self.position += 1;
+                Texture {
+                    pigment: None,
+                    finish: Some(self.finish()?),
+                }
+            }
+            Some("texture") => {
+                self.position += 1;
+                self.texture()?
+            }
+            Some("normal") => {
+                self.position += 1;
+                self.skip_block()?;
+                Texture::default()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(texture))
+    }
+
+    fn texture(&mut self) -> Result<Texture, ImportError> {
+        self.expect('{')?;
+        let mut texture = Texture::default();
+        loop {
+            if self.eat('}') {
+                return Ok(texture);
+            }
+            if let Some(item) = self.texture_item()? {
+                texture = item.or(texture);
+            } else if self.transform()?.is_some() {
+                // Solid colors look the same wherever they are moved.
+            } else if let Some(Declared::Texture(declared)) = self.declared_next() {
+                texture = declared;
+            } else {
+                return Err(self.unsupported("this texture setting"));
+            }
+        }
+    }
+
+    /// What the name next was declared as, moving past it, if it is a name.
+    fn declared_next(&mut self) -> Option<Declared> {
+        let declared = self.peek_word().and_then(|word| self.context.declared.get(word)).cloned();
+        if declared.is_some() {
+            self.position += 1;
+        }
+        declared
+    }
+
+    fn pigment(&mut self) -> Result<[f64; 5], ImportError> {
+        self.expect('{')?;
+        let mut color = [0.0; 5];
+        loop {
+            if self.eat('}') {
+                return Ok(color);
+            }
+            if self.transform()?.is_some() {
+                continue;
+            }
+            let word = self.peek_word().unwrap_or("").to_string();
+            match self.context.declared.get(&word) {
+                Some(Declared::Pigment(pigment)) => {
+                    color = *pigment;
+                    self.position += 1;
+                }
+                Some(Declared::Value(_)) => color = self.color()?,
+                _ if is_pattern(&word) => return Err(self.unsupported(&format!("the {} pigment pattern", word))),
+                _ => color = self.color()?,
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<Finish, ImportError> {
+        self.expect('{')?;
+        let mut finish = Finish::default();
+        loop {
+            let word = match self.next() {
+                Some(Token::Symbol('}')) => return Ok(finish),
+                Some(Token::Word(word)) => word,
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected a finish setting"));
+                }
+            };
+            match word.as_str() {
+                "ambient" => finish.ambient = self.color_strength()?,
+                "emission" => finish.emission = self.color_strength()?,
+                "diffuse" => {
+                    if self.peek_word() == Some("albedo") {
+                        self.position += 1;
+                    }
+                    finish.diffuse = self.float()?;
+                }
+                "specular" => finish.specular = self.float()?,
+                "roughness" => finish.roughness = self.float()?,
+                "phong" => finish.phong = self.float()?,
+                "phong_size" => finish.phong_size = self.float()?,
+                "reflection" => {
+                    finish.reflection = if self.peek() == Some(&Token::Symbol('{')) {
+                        self.reflection()?
+                    } else {
+                        self.color_strength()?
+                    }
+                }
+                // Settings the Phong model has no counterpart for.
+                "brilliance" | "crand" | "metallic" | "reflection_exponent" | "refraction" | "conserve_energy" => {
+                    self.optional_float()?;
+                }
+                "irid" => self.skip_block()?,
+                other => match self.context.declared.get(other) {
+                    Some(Declared::Finish(declared)) => finish = *declared,
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.unsupported(&format!("the finish setting '{}'", other)));
+                    }
+                },
+            }
+        }
+    }
+
+    /// A `reflection { min, max ... }` block as its strongest reflection.
+    fn reflection(&mut self) -> Result<f64, ImportError> {
+        self.expect('{')?;
+        let mut strength = self.color_strength()?;
+        if self.eat(',') {
+            strength = strength.max(self.color_strength()?);
+        }
+        while !self.eat('}') {
+            match self.next() {
+                Some(Token::Word(word)) if matches!(word.as_str(), "fresnel" | "metallic" | "falloff" | "exponent") => {
+                    self.optional_float()?;
+                }
+                None => return Err(self.error("reflection is not closed")),
+                _ => {
+                    self.position -= 1;
+                    return Err(self.unsupported("this reflection setting"));
+                }
+            }
+        }
+        Ok(strength)
+    }
+
+    /// An interior's index of refraction, if it has one.
+    fn interior(&mut self) -> Result<Option<f64>, ImportError> {
+        self.expect('{')?;
+        let mut ior = None;
+        loop {
+            match self.next() {
+                Some(Token::Symbol('}')) => return Ok(ior),
+                Some(Token::Word(word)) => match word.as_str() {
+                    "ior" => ior = Some(self.float()?),
+                    "caustics" | "dispersion" | "dispersion_samples" | "fade_distance" | "fade_power" => {
+                        self.float()?;
+                    }
+                    "fade_color" => {
+                        self.color()?;
+                    }
+                    other => match self.context.declared.get(other) {
+                        Some(Declared::Interior(declared)) => ior = *declared,
+                        _ => {
+                            self.position -= 1;
+                            return Err(self.unsupported(&format!("the interior setting '{}'", other)));
+                        }
+                    },
+                },
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected an interior setting"));
+                }
+            }
+        }
+    }
+
+    /// A color, with the `color` keyword or without, and the `red`, `filter`
+    /// and such that follow it. Gives red, green, blue, filter and transmit.
+    fn color(&mut self) -> Result<[f64; 5], ImportError> {
+        if matches!(self.peek_word(), Some("color") | Some("colour")) {
+            self.position += 1;
+        }
+
+        let mut color = match self.peek_word() {
+            Some("rgb") | Some("rgbf") | Some("rgbt") | Some("rgbft") | Some("srgb") => {
+                let keyword = self.word()?;
+                let v = self.expression()?.components;
+                match keyword.as_str() {
+                    "rgbf" => [v[0], v[1], v[2], v[3], 0.0],
+                    "rgbt" => [v[0], v[1], v[2], 0.0, v[3]],
+                    "rgbft" => v,
+                    "srgb" => {
+                        let linear = |c: f64| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+                        [linear(v[0]), linear(v[1]), linear(v[2]), 0.0, 0.0]
+                    }
+                    _ => [v[0], v[1], v[2], 0.0, 0.0],
+                }
+            }
+            Some("red") | Some("green") | Some("blue") | Some("filter") | Some("transmit") => [0.0; 5],
+            _ => self.expression()?.color(),
+        };
+
+        loop {
+            let index = match self.peek_word() {
+                Some("red") => 0,
+                Some("green") => 1,
+                Some("blue") => 2,
+                Some("filter") => 3,
+                Some("transmit") => 4,
+                _ => return Ok(color),
+            };
+            self.position += 1;
+            color[index] = self.float()?;
+        }
+    }
+
+    /// A float, or a color taken by its strongest channel.
+    fn color_strength(&mut self) -> Result<f64, ImportError> {
+        let [r, g, b, _, _] = self.color()?;
+        Ok(r.max(g).max(b))
+    }
+
+    fn optional_float(&mut self) -> Result<Option<f64>, ImportError> {
+        let starts_expression = match self.peek() {
+            Some(Token::Number(_)) | Some(Token::Symbol('-')) | Some(Token::Symbol('(')) => true,
+            Some(Token::Word(word)) => matches!(word.as_str(), "on" | "off" | "true" | "false" | "yes" | "no"),
+            _ => false,
+        };
+        if starts_expression {
+            self.float().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, ImportError> {
+        let value = self.expression()?;
+        if value.len == 1 {
+            Ok(value.components[0])
+        } else {
+            Err(self.error("expected a number, not a vector"))
+        }
+    }
+
+    fn vector(&mut self) -> Result<Vec3, ImportError> {
+        Ok(self.expression()?.vec3())
+    }
+
+    fn expression(&mut self) -> Result<Value, ImportError> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = value.combine(self.term()?, |a, b| a + b);
+            } else if self.eat('-') {
+                value = value.combine(self.term()?, |a, b| a - b);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Value, ImportError> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value = value.combine(self.unary()?, |a, b| a * b);
+            } else if self.eat('/') {
+                value = value.combine(self.unary()?, |a, b| a / b);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, ImportError> {
+        self.deeper()?;
+        let value = if self.eat('-') {
+            Value::float(0.0).combine(self.unary()?, |a, b| a - b)
+        } else if self.eat('+') {
+            self.unary()?
+        } else {
+            self.primary()?
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn primary(&mut self) -> Result<Value, ImportError> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Value::float(number)),
+            Some(Token::Symbol('(')) => {
+                self.deeper()?;
+                let value = self.expression()?;
+                self.expect(')')?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some(Token::Symbol('<')) => {
+                let mut components = vec![self.float()?];
+                while self.eat(',') {
+                    components.push(self.float()?);
+                }
+                self.expect('>')?;
+                if components.len() > 5 {
+                    return Err(self.error("a vector has more than five components"));
+                }
+                Ok(Value::vector(&components))
+            }
+            Some(Token::Word(word)) => self.identifier(&word),
+            _ => {
+                self.position -= 1;
+                Err(self.error("expected a number or a vector"))
+            }
+        }
+    }
+
+    fn identifier(&mut self, word: &str) -> Result<Value, ImportError> {
+        let options = &self.context.options;
+        let value = match word {
+            "x" => Value::vector(&[1.0, 0.0, 0.0]),
+            "y" => Value::vector(&[0.0, 1.0, 0.0]),
+            "z" => Value::vector(&[0.0, 0.0, 1.0]),
+            "pi" => Value::float(std::f64::consts::PI),
+            "image_width" => Value::float(f64::from(options.width)),
+            "image_height" => Value::float(f64::from(options.height)),
+            "clock" | "off" | "false" | "no" => Value::float(0.0),
+            "on" | "true" | "yes" => Value::float(1.0),
+            "sqrt" | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "abs" | "radians" | "degrees" | "exp" | "log"
+            | "ln" | "floor" | "ceil" | "int" | "vlength" | "vnormalize" => {
+                self.expect('(')?;
+                let argument = self.expression()?;
+                self.expect(')')?;
+                let f = argument.components[0];
+                match word {
+                    "vlength" => Value::float(argument.vec3().length()),
+                    "vnormalize" => {
+                        let v = argument.vec3().normalize();
+                        Value::vector(&[v.x, v.y, v.z])
+                    }
+                    "sqrt" => Value::float(f.sqrt()),
+                    "sin" => Value::float(f.sin()),
+                    "cos" => Value::float(f.cos()),
+                    "tan" => Value::float(f.tan()),
+                    "asin" => Value::float(f.asin()),
+                    "acos" => Value::float(f.acos()),
+                    "atan" => Value::float(f.atan()),
+                    "abs" => Value::float(f.abs()),
+                    "radians" => Value::float(f.to_radians()),
+                    "degrees" => Value::float(f.to_degrees()),
+                    "exp" => Value::float(f.exp()),
+                    "log" => Value::float(f.log10()),
+                    "ln" => Value::float(f.ln()),
+                    "floor" => Value::float(f.floor()),
+                    "ceil" => Value::float(f.ceil()),
+                    _ => Value::float(f.trunc()),
+                }
+            }
+            "pow" | "atan2" | "min" | "max" | "mod" => {
+                self.expect('(')?;
+                let a = self.float()?;
+                self.expect(',')?;
+                let b = self.float()?;
+                self.expect(')')?;
+                Value::float(match word {
+                    "pow" => a.powf(b),
+                    "atan2" => a.atan2(b),
+                    "min" => a.min(b),
+                    "max" => a.max(b),
+                    _ => a % b,
+                })
+            }
+            _ => match self.context.declared.get(word) {
+                Some(Declared::Value(value)) => *value,
+                Some(Declared::Pigment(color)) => Value::vector(color),
+                Some(_) => {
+                    self.position -= 1;
+                    return Err(self.error(format!("'{}' is not a number, vector or color", word)));
+                }
+                None => {
+                    self.position -= 1;
+                    return Err(self.error(format!("'{}' is not declared", word)));
+                }
+            },
+        };
+        Ok(value)
+    }
+}
+
+impl Object {
+    fn new(geometry: Geometry, line: usize) -> Object {
+        Object {
+            geometry,
+            transform: Mat4::identity(),
+            texture: Texture::default(),
+            ior: None,
+            line,
+        }
+    }
+}
+
+fn is_color_keyword(word: &str) -> bool {
+    matches!(
+        word,
+        "color" | "colour" | "rgb" | "rgbf" | "rgbt" | "rgbft" | "srgb" | "red" | "green" | "blue" | "filter" | "transmit"
+    )
+}
+
+/// Pigment patterns and maps, which solid colors cannot stand for.
+fn is_pattern(word: &str) -> bool {
+    matches!(
+        word,
+        "agate" | "average" | "boxed" | "brick" | "bozo" | "bumps" | "cells" | "checker" | "color_map" | "colour_map"
+            | "crackle" | "cylindrical" | "dents" | "gradient" | "granite" | "hexagon" | "image_map" | "leopard"
+            | "mandel" | "marble" | "onion" | "pigment_map" | "planar" | "quilted" | "radial" | "ripples" | "spherical"
+            | "spiral1" | "spiral2" | "spotted" | "waves" | "wood" | "wrinkles"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::import::pov::parse;
+    use crate::import::{ImportError, SceneOptions};
+    use crate::ray::Ray;
+    use crate::vector::Vec3;
+
+    use std::path::Path;
+
+    #[test]
+    fn test_parse() {
+        let scene = r#"
+            #include "colors.inc"
+            #declare Radius = 0.5;
+            #declare Ball = sphere { <0, 0, 0>, Radius pigment { color Red } }
+
+            camera { location <0, 0, -5> look_at <0, 0, 0> angle 60 right x*image_width/image_height }
+            light_source { <0, 4, -4> color White }
+            background { color rgb <0.1, 0.2, 0.3> }
+
+            object { Ball translate 1.5*x }
+            difference {
+                box { <-1, -1, -1>, <1, 1, 1> }
+                sphere { <0, 0, -1>, 0.5 pigment { rgb <0, 0, 1> } }
+                pigment { White }
+                finish { phong 0.5 reflection 0.2 }
+                translate -1.5*x
+            }
+            plane { y, -1 pigment { Gray50 } }
+        "#;
+        let options = SceneOptions {
+            width: 64,
+            height: 32,
+            ..SceneOptions::default()
+        };
+        let renderer = parse(scene, Path::new(""), &options).unwrap();
+        assert_eq!(renderer.objects.len(), 3);
+        assert_eq!(renderer.lights.len(), 2);
+        assert!((renderer.bg_color.channels()[2] - 0.3).abs() < 1e-9);
+
+        // POV-Ray's z goes into the screen, which is -z here.
+        assert!((renderer.camera.origin() - Vec3::new(0.0, 0.0, 5.0)).length() < 1e-9);
+        let (right, _, _) = renderer.camera.basis();
+        assert!((right - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        let vertical = 2.0 * ((30f64.to_radians()).tan() / 2.0).atan().to_degrees();
+        assert!((renderer.camera.fov() - vertical).abs() < 1e-9);
+
+        let hit = |x: f64| {
+            let ray = Ray {
+                origin: Vec3::new(x, 0.0, 5.0),
+                direction: Vec3::new(0.0, 0.0, -1.0),
+                time: 0.0,
+            };
+            Ray::intersect(ray, &renderer.objects).unwrap()
+        };
+        let ball = hit(1.5);
+        assert!((ball.distance - 4.5).abs() < 1e-6);
+        assert!((ball.material.color.channels()[0] - 1.0).abs() < 1e-9);
+
+        // The sphere cut out of the front of the box leaves a blue dimple.
+        let dimple = hit(-1.5);
+        assert!((dimple.distance - 4.5).abs() < 1e-6);
+        assert!((dimple.material.color.channels()[2] - 1.0).abs() < 1e-9);
+        let face = hit(-0.8);
+        assert!((face.distance - 4.0).abs() < 1e-6);
+        assert!((face.material.reflectiveness - 0.2).abs() < 1e-9);
+
+        match parse("sphere { <0, 0, 0>, 1\n  pigment { checker Red White }\n}", Path::new(""), &options) {
+            Err(ImportError::Unsupported { line: Some(2), .. }) => {}
+            other => panic!("expected patterns to be unsupported, got {:?}", other.map(|_| ())),
+        }
+        // Deep expressions are refused rather than overflowing the stack.
+        let parentheses = format!("#declare R = {}1{};", "(".repeat(200_000), ")".repeat(200_000));
+        let minuses = format!("#declare R = {}1;", "-".repeat(200_000));
+        for text in [parentheses, minuses] {
+            match parse(&text, Path::new(""), &options) {
+                Err(ImportError::Malformed { line: Some(1), .. }) => {}
+                other => panic!("expected deep expressions to be rejected, got {:?}", other.map(|_| ())),
+            }
+        }
+        match parse("sphere { <0, 0, 0>,\n\n 1 pigment { Nothing } }", Path::new(""), &options) {
+            Err(ImportError::Malformed { line: Some(3), .. }) => {}
+            other => panic!("expected an error on line 3, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::import::{ImportError, ImportOptions, MeshData};
+use crate::shapes::mesh::Mesh;
+use crate::vector::Vec3;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Loads a text or binary STL file as a mesh. STL gives every face its own
+/// corners, which are welded back together where they lie on the same point.
+/// Faces wound against the facet normal the file gives them are turned around.
+pub fn load<P: AsRef<Path>>(path: P, options: &ImportOptions) -> Result<Mesh, ImportError> {
+    parse(&fs::read(path)?)?.into_mesh(options)
+}
+
+fn parse(bytes: &[u8]) -> Result<MeshData, ImportError> {
+    // Binary files may start with "solid" too, so their size decides first.
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() == 84 + 50 * count {
+            return Ok(parse_binary(&bytes[84..]));
+        }
+    }
+
+    if bytes.trim_ascii_start().starts_with(b"solid") {
+        parse_ascii(&String::from_utf8_lossy(bytes))
+    } else if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        Err(ImportError::malformed(format!(
+            "a binary STL file of {} faces takes {} bytes, found {}",
+            count,
+            84 + 50 * count,
+            bytes.len()
+        )))
+    } else {
+        Err(ImportError::malformed("not an STL file, too short to be binary and not starting with 'solid'"))
+    }
+}
+
+/// Collects faces, giving each distinct corner a single vertex.
+#[derive(Default)]
+struct Welder {
+    data: MeshData,
+    indices: HashMap<[u64; 3], usize>,
+}
+
+impl Welder {
+    fn vertex(&mut self, point: Vec3) -> usize {
+        // Adding zero turns -0 into 0, which would otherwise differ in its bits.
+        let key = [(point.x + 0.0).to_bits(), (point.y + 0.0).to_bits(), (point.z + 0.0).to_bits()];
+        let vertices = &mut self.data.vertices;
+        *self.indices.entry(key).or_insert_with(|| {
+            vertices.push(point);
+            vertices.len() - 1
+        })
+    }
+
+    fn face(&mut self, normal: Vec3, corners: &[Vec3]) {
+        let mut indices: Vec<usize> = corners.iter().map(|corner| self.vertex(*corner)).collect();
+        let winding = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        if winding.dot(normal) < 0.0 {
+            indices.reverse();
+        }
+        self.data.add_polygon(&indices);
+    }
+}
+
+fn parse_binary(bytes: &[u8]) -> MeshData {
+    let mut welder = Welder::default();
+
+    for facet in bytes.chunks_exact(50) {
+        let float = |i: usize| f64::from(f32::from_le_bytes([facet[i], facet[i + 1], facet[i + 2], facet[i + 3]]));
+        let vector = |i: usize| Vec3::new(float(i), float(i + 4), float(i + 8));
+        welder.face(vector(0), &[vector(12), vector(24), vector(36)]);
+    }
+
+    welder.data
+}
+
+fn parse_ascii(text: &str) -> Result<MeshData, ImportError> {
+    let mut welder = Welder::default();
+    // The facet being read, with its normal and the corners read so far.
+    let mut facet: Option<(Vec3, Vec<Vec3>)> = None;
+    let mut in_loop = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let vector = |numbers: &[&str]| -> Result<Vec3, ImportError> {
+            let parsed: Vec<f64> = numbers.iter().filter_map(|word| word.parse().ok()).collect();
+            match parsed.as_slice() {
+                [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+                _ => Err(ImportError::at_line(number, format!("expected three numbers, found '{}'", numbers.join(" ")))),
+            }
+        };
+
+        match (words.as_slice(), &mut facet) {
+            ([], _) | (["solid", ..], None) | (["endsolid", ..], None) => {}
+            (["facet", "normal", numbers @ ..], None) => facet = Some((vector(numbers)?, Vec::new())),
+            (["outer", "loop"], Some(_)) if !in_loop => in_loop = true,
+            (["vertex", numbers @ ..], Some((_, corners))) if in_loop => corners.push(vector(numbers)?),
+            (["endloop"], Some(_)) if in_loop => in_loop = false,
+            (["endfacet"], Some((normal, corners))) if !in_loop => {
+                if corners.len() < 3 {
+                    return Err(ImportError::at_line(number, format!("a facet with only {} corners", corners.len())));
+                }
+                welder.face(*normal, corners);
+                facet = None;
+            }
+            _ => return Err(ImportError::at_line(number, format!("unexpected '{}'", line.trim()))),
+        }
+    }
+
+    if facet.is_some() {
+        return Err(ImportError::malformed("the file ends in the middle of a facet"));
+    }
+    Ok(welder.data)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::import::stl::parse;
+    use crate::import::ImportError;
+
+    #[test]
+    fn test_ascii_and_binary() {
+        // Two faces of a square sharing an edge, the second wound backwards.
+        let ascii = b"solid square\n\
+            facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 1 0 0\n  vertex 1 1 0\n endloop\nendfacet\n\
+            facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 0 1 0\n  vertex 1 1 0\n endloop\nendfacet\n\
+            endsolid square\n";
+        let data = parse(ascii).unwrap();
+        assert_eq!(data.vertices.len(), 4);
+        assert_eq!(data.triangles, vec![[0, 1, 2], [2, 3, 0]]);
+
+        let mut binary = vec![0; 80];
+        binary.extend_from_slice(&1u32.to_le_bytes());
+        for value in &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0f32] {
+            binary.extend_from_slice(&value.to_le_bytes());
+        }
+        binary.extend_from_slice(&[0, 0]);
+        assert_eq!(parse(&binary).unwrap().triangles, vec![[0, 1, 2]]);
+
+        match parse(b"solid broken\nfacet normal 0 0 1\n outer loop\n  vertex 0 0\n") {
+            Err(ImportError::Malformed { line: Some(4), .. }) => {}
+            other => panic!("expected an error on line 4, got {:?}", other),
+        }
+    }
+}
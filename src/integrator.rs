@@ -0,0 +1,237 @@
+use crate::adaptive;
+use crate::cfg::IntegratorKind;
+use crate::color::Color;
+use crate::ray::{Intersection, Ray};
+use crate::renderer::Renderer;
+use crate::sampler::Sampler;
+use crate::{bdpt, occlusion, path};
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A way of computing the color seen along a camera ray. The renderer asks
+/// its integrator for every sample it takes.
+pub trait Integrator: Debug + Send + Sync {
+    /// Color arriving along `ray` in the renderer's scene. `sampler` is the
+    /// pixel's own, for integrators that need random numbers.
+    fn radiance(&self, renderer: &Renderer, ray: Ray, sampler: &mut Sampler) -> Color;
+
+    /// Light leaving a camera ray's first hit split into what comes straight
+    /// from the lights and what is reflected or refracted in, for the AOV
+    /// passes. The background counts as direct light for rays that hit
+    /// nothing. `None` for integrators that do not tell the two apart.
+    fn direct_and_reflected(
+        &self,
+        _renderer: &Renderer,
+        _ray: Ray,
+        _hit: Option<Intersection>,
+        _sampler: &mut Sampler,
+    ) -> Option<(Color, Color)> {
+        None
+    }
+}
+
+impl IntegratorKind {
+    /// The integrator of this kind, to be shared by all of a renderer's threads.
+    pub fn build(self) -> Arc<dyn Integrator> {
+        match self {
+            IntegratorKind::Whitted => Arc::new(Whitted),
+            IntegratorKind::Path => Arc::new(Path),
+            IntegratorKind::Bidirectional => Arc::new(Bidirectional),
+            IntegratorKind::AmbientOcclusion => Arc::new(AmbientOcclusion),
+            IntegratorKind::Normals => Arc::new(Normals),
+            IntegratorKind::Depth { far } => Arc::new(Depth { far }),
+            IntegratorKind::Uv => Arc::new(Uv),
+            IntegratorKind::FacingRatio => Arc::new(FacingRatio),
+            IntegratorKind::BvhCost { max } => Arc::new(BvhCost { max }),
+        }
+    }
+}
+
+/// Direct light from `Light::shade` plus mirror reflections and refraction, see `Ray::cast_camera_ray`.
+#[derive(Debug)]
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, sampler: &mut Sampler) -> Color {
+        Ray::cast_camera_ray(ray, &renderer.objects, &renderer.lights, &renderer.options, renderer.bg_color, sampler)
+    }
+
+    fn direct_and_reflected(
+        &self,
+        renderer: &Renderer,
+        ray: Ray,
+        hit: Option<Intersection>,
+        sampler: &mut Sampler,
+    ) -> Option<(Color, Color)> {
+        let intersection = match hit {
+            Some(intersection) => intersection,
+            None => return Some((renderer.bg_color, Color::black())),
+        };
+        let (objects, lights, options) = (&renderer.objects, &renderer.lights, &renderer.options);
+        let direct = Ray::direct(ray, intersection, objects, lights, options);
+        let reflected = Ray::reflect(ray, intersection, objects, lights, options, 0, sampler)
+            + Ray::transmit(ray, intersection, objects, lights, options, 0, sampler);
+        Some((direct, reflected))
+    }
+}
+
+/// Unidirectional path tracing, see `path::trace`.
+#[derive(Debug)]
+pub struct Path;
+
+impl Integrator for Path {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, sampler: &mut Sampler) -> Color {
+        path::trace(ray, &renderer.objects, &renderer.lights, &renderer.options, renderer.bg_color, sampler)
+    }
+}
+
+/// Bidirectional path tracing, see `bdpt::trace`.
+#[derive(Debug)]
+pub struct Bidirectional;
+
+impl Integrator for Bidirectional {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, sampler: &mut Sampler) -> Color {
+        bdpt::trace(ray, &renderer.objects, &renderer.lights, &renderer.options, renderer.bg_color, sampler)
+    }
+}
+
+/// Clay renders shaded by ambient occlusion only, see `occlusion::trace`.
+#[derive(Debug)]
+pub struct AmbientOcclusion;
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, sampler: &mut Sampler) -> Color {
+        occlusion::trace(ray, &renderer.objects, &renderer.lights, &renderer.options, renderer.bg_color, sampler)
+    }
+}
+
+/// Shading normals, each axis mapped from -1..1 to a color channel's 0..1.
+#[derive(Debug)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, _sampler: &mut Sampler) -> Color {
+        match Ray::intersect(ray, &renderer.objects) {
+            Some(intersection) => {
+                let n = intersection.normal;
+                Color::new((n.x + 1.0) / 2.0, (n.y + 1.0) / 2.0, (n.z + 1.0) / 2.0)
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+/// Distance to the first hit, white right at the camera fading to black at `far`.
+#[derive(Debug)]
+pub struct Depth {
+    pub far: f64,
+}
+
+impl Integrator for Depth {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, _sampler: &mut Sampler) -> Color {
+        match Ray::intersect(ray, &renderer.objects) {
+            Some(intersection) => {
+                let shade = (1.0 - intersection.distance / self.far).clamp(0.0, 1.0);
+                Color::new(shade, shade, shade)
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+/// Texture coordinates in the red and green channels, wrapped to 0..1.
+#[derive(Debug)]
+pub struct Uv;
+
+impl Integrator for Uv {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, _sampler: &mut Sampler) -> Color {
+        match Ray::intersect(ray, &renderer.objects) {
+            Some(intersection) => {
+                let (u, v) = intersection.uv;
+                Color::new(u.rem_euclid(1.0), v.rem_euclid(1.0), 0.0)
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+/// Cosine between the ray and the surface, white where it is seen head on
+/// and black where it is seen edge on, whichever side it is seen from.
+#[derive(Debug)]
+pub struct FacingRatio;
+
+impl Integrator for FacingRatio {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, _sampler: &mut Sampler) -> Color {
+        match Ray::intersect(ray, &renderer.objects) {
+            Some(intersection) => {
+                let ratio = intersection.normal.dot(ray.direction).abs();
+                Color::new(ratio, ratio, ratio)
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+/// Heat map of the intersection tests a ray makes against every shape, with
+/// the bounding volume tests of meshes, going from black over red and yellow
+/// to white at `max` tests.
+#[derive(Debug)]
+pub struct BvhCost {
+    pub max: u32,
+}
+
+impl Integrator for BvhCost {
+    fn radiance(&self, renderer: &Renderer, ray: Ray, _sampler: &mut Sampler) -> Color {
+        let tests: u32 = renderer.objects.iter().map(|shape| shape.traversal_cost(ray)).sum();
+        adaptive::heat(f64::from(tests) / f64::from(self.max.max(1)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::camera::Camera;
+    use crate::cfg::{Cfg, IntegratorKind};
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::renderer::Renderer;
+    use crate::sampler::Sampler;
+    use crate::shapes::sphere::Sphere;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_debug_integrators() {
+        let renderer = Renderer {
+            width: 1,
+            height: 1,
+            camera: Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 60.0, 1.0, 0.0),
+            objects: vec![Box::new(Sphere {
+                position: Vec3::new(0.0, 0.0, -5.0),
+                radius: 1.0,
+                material: Material::neutral(),
+            })],
+            lights: Vec::new(),
+            bg_color: Color::white(),
+            options: Cfg::default(),
+        };
+        let ray = Ray {
+            origin: Vec3::zero(),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let mut sampler = Sampler::new(0);
+
+        let normal = IntegratorKind::Normals.build().radiance(&renderer, ray, &mut sampler);
+        assert!((normal.channels()[2] - 1.0).abs() < 1e-9);
+
+        let depth = IntegratorKind::Depth { far: 8.0 }.build().radiance(&renderer, ray, &mut sampler);
+        assert!((depth.channels()[0] - 0.5).abs() < 1e-9);
+
+        let facing = IntegratorKind::FacingRatio.build().radiance(&renderer, ray, &mut sampler);
+        assert!((facing.channels()[1] - 1.0).abs() < 1e-9);
+
+        let cost = IntegratorKind::BvhCost { max: 1 }.build().radiance(&renderer, ray, &mut sampler);
+        assert!(cost.channels().iter().all(|channel| (channel - 1.0).abs() < 1e-9));
+    }
+}
@@ -1,14 +1,34 @@
 #![allow(dead_code)]
 
+pub mod adaptive;
+pub mod animation;
+pub mod aov;
+pub mod bdpt;
+pub mod bsdf;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod denoise;
+pub mod exr;
+pub mod export;
+pub mod import;
+pub mod integrator;
 pub mod light;
 pub mod material;
+pub mod matrix;
 pub mod cfg;
 pub mod ray;
 pub mod renderer;
 pub mod vector;
 pub mod shapes;
 pub mod rotate;
+pub mod texture;
+pub mod tiles;
+pub mod motion;
+pub mod medium;
+pub mod occlusion;
+pub mod path;
+pub mod photon;
+pub mod sampler;
 
 const EPSILON: f64 = 1e-6;
\ No newline at end of file
@@ -1,8 +1,11 @@
+use crate::bsdf::Bsdf;
 use crate::color::Color;
 use crate::shapes::Shape;
 use crate::cfg::Cfg;
+use crate::medium::{Heterogeneous, Medium, MAX_CROSSINGS};
 use crate::ray::Intersection;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector::Vec3;
 
 #[derive(Debug)]
@@ -20,50 +23,164 @@ pub enum LightType {
 }
 
 impl Light {
+    /// Whether nothing blocks the path from this light to the intersection.
+    /// The boundaries of participating media let light through.
+    pub fn illuminates(&self, objects: &[Box<dyn Shape>], intersection: Intersection, ray_time: f64) -> bool {
+        let origin = self.shadow_origin(intersection);
+        self.attenuation(objects, None, origin, ray_time).channels().iter().any(|channel| *channel > 0.0)
+    }
+
+    /// Share of the light's color reaching `point` through the fog and the
+    /// participating media in between, black when something solid blocks it.
+    pub fn transmittance(&self, objects: &[Box<dyn Shape>], options: &Cfg, point: Vec3, ray_time: f64) -> Color {
+        self.attenuation(objects, options.fog, point, ray_time)
+    }
+
+    /// Start of a shadow ray from the intersection towards the light, just off
+    /// the surface on the light's side.
+    fn shadow_origin(&self, intersection: Intersection) -> Vec3 {
+        if (self.position - intersection.hit_point).dot(intersection.normal) < 0.0 {
+            intersection.hit_point.correct(-intersection.normal)
+        } else {
+            intersection.hit_point.correct(intersection.normal)
+        }
+    }
+
+    /// Walks from `origin` to the light through the boundaries of the media
+    /// on the way. A stretch ending where the walk leaves a shape is inside
+    /// that shape's medium, any other stretch is in `fog`.
+    fn attenuation(&self, objects: &[Box<dyn Shape>], fog: Option<Medium>, origin: Vec3, ray_time: f64) -> Color {
+        let direction = (self.position - origin).normalize();
+        let mut sampler = Sampler::for_point(origin);
+        let mut origin = origin;
+        let mut transmittance = Color::white();
+
+        for _ in 0..MAX_CROSSINGS {
+            let light_dis = (self.position - origin).length();
+            let light_ray = Ray {
+                origin,
+                direction,
+                time: ray_time,
+            };
+
+            // This point gets hit by this light if there are no objects between us
+            // or the object is farther away than the light.
+            let hit = Ray::intersect(light_ray, objects).filter(|hit| hit.distance < light_dis);
+            let segment = hit.map_or(light_dis, |hit| hit.distance);
+            let inside = hit
+                .filter(|hit| direction.dot(hit.normal) > 0.0)
+                .and_then(|hit| hit.material.medium.map(|medium| (medium, objects[hit.object].density_field())));
+
+            let stretch = match inside {
+                Some((medium, Some(field))) => Heterogeneous { medium, field }.ratio_tracking(light_ray, segment, &mut sampler),
+                Some((medium, None)) => medium.transmittance(segment),
+                None => fog.map_or(Color::white(), |fog| fog.transmittance(segment)),
+            };
+            transmittance = transmittance * stretch;
+
+            match hit {
+                None => return transmittance,
+                Some(hit) if hit.material.medium.is_none() => return Color::black(),
+                Some(hit) => {
+                    let side = if direction.dot(hit.normal) > 0.0 { hit.normal } else { -hit.normal };
+                    origin = hit.hit_point.correct(side);
+                }
+            }
+        }
+
+        Color::black()
+    }
+
+    /// Share of the point light intensity reaching the intersection, 1 when it
+    /// is fully lit and 0 when it is in the shadow of every light.
+    pub fn visibility(objects: &[Box<dyn Shape>], lights: &[Light], intersection: Intersection, ray_time: f64) -> f64 {
+        let mut total = 0.0;
+        let mut visible = 0.0;
+
+        for light in lights {
+            if let LightType::Point = light.light_type {
+                total += light.intensity;
+                if light.illuminates(objects, intersection, ray_time) {
+                    visible += light.intensity;
+                }
+            }
+        }
+
+        if total > 0.0 {
+            visible / total
+        } else {
+            1.0
+        }
+    }
+
     pub fn shade(
         objects: &[Box<dyn Shape>],
         lights: &[Light],
         options: &Cfg,
         intersection: Intersection,
         direction: Vec3,
+        ray_time: f64,
     ) -> Color {
         let mat = intersection.material;
 
+        let mut sampler = Sampler::for_point(intersection.hit_point);
+        let emitted = Light::sample_emitters(objects, options, intersection, ray_time, &mut sampler);
+
+        let ambient_visibility = match &options.ambient_occlusion {
+            Some(ao) if lights.iter().any(|light| matches!(light.light_type, LightType::Ambient)) => {
+                ao.visibility(objects, intersection, direction, ray_time, &mut sampler)
+            }
+            _ => 1.0,
+        };
+
+        if let Some(bsdf) = mat.bsdf {
+            let mut color = Light::shade_bsdf(objects, lights, options, intersection, &bsdf, direction, ray_time);
+            color += bsdf.albedo() * Light::ambient(lights) * ambient_visibility;
+            for (light_dir, irradiance) in &emitted {
+                let f = bsdf.eval(intersection.normal, -direction, *light_dir);
+                color += f * *irradiance * light_dir.dot(intersection.normal).abs();
+            }
+            if let Some(caustics) = &options.caustics {
+                color += caustics.radiance(intersection.hit_point, intersection.normal, |wi| {
+                    bsdf.eval(intersection.normal, -direction, wi)
+                });
+            }
+            return color;
+        }
+
         let mut diff_light = Color::black();
         let mut spec_light = Color::black();
 
+        for (light_dir, irradiance) in &emitted {
+            let light_reflection = (-*light_dir).reflect(intersection.normal);
+            let angle = -(light_reflection.dot(direction));
+
+            diff_light += *irradiance * light_dir.dot(intersection.normal).max(0.0);
+            spec_light += *irradiance * angle.max(0.0).powf(mat.specular_exponent);
+        }
+
+        if let Some(caustics) = &options.caustics {
+            diff_light += caustics.radiance(intersection.hit_point, intersection.normal, |_| Color::white());
+        }
+
         for light in lights {
             match light.light_type {
-                LightType::Ambient => diff_light += light.color * light.intensity,
+                LightType::Ambient => diff_light += light.color * (light.intensity * ambient_visibility),
                 LightType::Point => {
-                    let light_vec = light.position - intersection.hit_point;
-                    let light_dir = light_vec.normalize();
-                    let light_dis = light_vec.length();
+                    let light_dir = (light.position - intersection.hit_point).normalize();
                     let light_angle = light_dir.dot(intersection.normal);
 
-                    let light_ray = Ray {
-                        origin: if light_angle < 0.0 {
-                            intersection.hit_point.correct(-intersection.normal)
-                        } else {
-                            intersection.hit_point.correct(intersection.normal)
-                        },
-                        direction: light_dir,
-                    };
-
-                    // This point gets hit by this light if there are no objects between us
-                    // or the object is farther away than the light.
-                    let hit_by_light = match Ray::intersect(light_ray, objects) {
-                        None => true,
-                        Some(intersection) => light_dis <= intersection.distance,
+                    let transmittance = if options.shadows {
+                        light.transmittance(objects, options, light.shadow_origin(intersection), ray_time)
+                    } else {
+                        Color::white()
                     };
 
-                    if hit_by_light || !options.shadows {
-                        let light_reflection = (-light_dir).reflect(intersection.normal);
-                        let angle = -(light_reflection.dot(direction));
+                    let light_reflection = (-light_dir).reflect(intersection.normal);
+                    let angle = -(light_reflection.dot(direction));
 
-                        diff_light += light.color * (light.intensity * light_angle.max(0.0));
-                        spec_light += light.color * angle.max(0.0).powf(mat.specular_exponent);
-                    }
+                    diff_light += light.color * transmittance * (light.intensity * light_angle.max(0.0));
+                    spec_light += light.color * transmittance * angle.max(0.0).powf(mat.specular_exponent);
                 }
             }
         }
@@ -84,4 +201,153 @@ impl Light {
 
         mat.color * factor
     }
-}
\ No newline at end of file
+
+    /// Combined color of the ambient lights.
+    pub fn ambient(lights: &[Light]) -> Color {
+        let mut color = Color::black();
+        for light in lights {
+            if let LightType::Ambient = light.light_type {
+                color += light.color * light.intensity;
+            }
+        }
+        color
+    }
+
+    /// Light reaching the intersection from emissive shapes, estimated from
+    /// `options.light_samples` points on each of them. Every unshadowed sample
+    /// is returned as the direction towards it and the radiance it contributes,
+    /// weighted by its solid angle, before the cosine at the intersection.
+    /// Points hidden behind the front of their own shape are always dropped,
+    /// shadows or not, and shapes also light themselves where they are concave.
+    pub fn sample_emitters(
+        objects: &[Box<dyn Shape>],
+        options: &Cfg,
+        intersection: Intersection,
+        ray_time: f64,
+        sampler: &mut Sampler,
+    ) -> Vec<(Vec3, Color)> {
+        let mut samples = Vec::new();
+        let count = options.light_samples.max(1);
+
+        for shape in objects {
+            if !shape.material().is_emissive() {
+                continue;
+            }
+
+            for _ in 0..count {
+                let (point, normal) = match shape.sample_surface(sampler) {
+                    Some(sample) => sample,
+                    None => break,
+                };
+
+                let light_vec = point - intersection.hit_point;
+                let distance = light_vec.length();
+                let light_dir = light_vec.normalize();
+                let cos_light = light_dir.dot(normal).abs();
+                if distance <= crate::EPSILON || cos_light <= 0.0 {
+                    continue;
+                }
+
+                let shadow_ray = Ray {
+                    origin: if light_dir.dot(intersection.normal) < 0.0 {
+                        intersection.hit_point.correct(-intersection.normal)
+                    } else {
+                        intersection.hit_point.correct(intersection.normal)
+                    },
+                    direction: light_dir,
+                    time: ray_time,
+                };
+                let hidden = |distance_to: Option<f64>| distance_to.is_some_and(|to| to < distance * (1.0 - 1e-4));
+                if hidden(shape.intersect(shadow_ray)) {
+                    continue;
+                }
+                if options.shadows && hidden(Ray::intersect(shadow_ray, objects).map(|hit| hit.distance)) {
+                    continue;
+                }
+
+                let emission = shape.material_at(point, ray_time).emission;
+                let solid_angle = shape.area() * cos_light / (distance * distance * f64::from(count));
+                samples.push((light_dir, emission * solid_angle));
+            }
+        }
+
+        samples
+    }
+
+    /// Direct light for materials with a BSDF, which replaces the separate
+    /// diffuse and specular terms. Ambient light is left to the caller.
+    fn shade_bsdf(
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+        intersection: Intersection,
+        bsdf: &Bsdf,
+        direction: Vec3,
+        ray_time: f64,
+    ) -> Color {
+        let mut color = Color::black();
+
+        for light in lights {
+            if let LightType::Point = light.light_type {
+                let transmittance = if options.shadows {
+                    light.transmittance(objects, options, light.shadow_origin(intersection), ray_time)
+                } else {
+                    Color::white()
+                };
+
+                let light_dir = (light.position - intersection.hit_point).normalize();
+                let cos = light_dir.dot(intersection.normal).abs();
+                let f = bsdf.eval(intersection.normal, -direction, light_dir);
+                color += f * light.color * transmittance * (light.intensity * cos);
+            }
+        }
+
+        color
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::light::Light;
+    use crate::material::Material;
+    use crate::ray::Intersection;
+    use crate::sampler::Sampler;
+    use crate::shapes::Shape;
+    use crate::shapes::sphere::Sphere;
+    use crate::vector::Vec3;
+
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_sphere_light() {
+        let lamp: Box<dyn Shape> = Box::new(Sphere {
+            position: Vec3::new(0.0, 10.0, 0.0),
+            radius: 1.0,
+            material: Material { emission: Color::white(), ..Material::neutral() },
+        });
+        let intersection = Intersection {
+            distance: 1.0,
+            hit_point: Vec3::zero(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 0.0),
+            material: Material::neutral(),
+            object: 1,
+        };
+        let lamps = [lamp];
+
+        // A uniformly bright sphere looks like a disk of the same radiance,
+        // whether or not shadows are traced.
+        for shadows in [true, false] {
+            let options = Cfg { light_samples: 4000, shadows, ..Cfg::default() };
+            let samples = Light::sample_emitters(&lamps, &options, intersection, 0.0, &mut Sampler::new(1));
+            let irradiance: f64 = samples.iter()
+                .map(|(direction, radiance)| radiance.channels()[0] * direction.y)
+                .sum();
+
+            let expected = PI * (1.0f64 / 10.0).powi(2);
+            assert!((irradiance - expected).abs() < expected * 0.1, "{} against {}", irradiance, expected);
+        }
+    }
+}
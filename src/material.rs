@@ -1,4 +1,6 @@
+use crate::bsdf::{Bsdf, Principled};
 use crate::color::Color;
+use crate::medium::Medium;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Material {
@@ -7,7 +9,17 @@ pub struct Material {
     pub specular: f64,
     pub specular_exponent: f64,
     pub reflectiveness: f64,
+    /// Spread of reflections around the mirror direction, from 0 for a
+    /// polished mirror to 1 for an almost matte finish.
+    pub roughness: f64,
     pub opacity: f64,
+    /// Physically based scattering, replacing the Phong parameters above when set.
+    pub bsdf: Option<Bsdf>,
+    /// Light given off by the surface itself, from both of its sides.
+    pub emission: Color,
+    /// Participating medium filling the shape, whose surface then only bounds
+    /// it and is not shaded itself. Rendered by `Ray::cast_ray`.
+    pub medium: Option<Medium>,
 }
 
 impl Material {
@@ -18,7 +30,62 @@ impl Material {
             specular: 0.0,
             specular_exponent: 0.0,
             reflectiveness: 0.0,
+            roughness: 0.0,
             opacity: 1.0,
+            bsdf: None,
+            emission: Color::black(),
+            medium: None,
         }
     }
-}
\ No newline at end of file
+
+    /// Index of refraction for light passing through the material.
+    pub fn ior(&self) -> f64 {
+        match self.bsdf {
+            Some(Bsdf::Dielectric { ior, .. }) => ior,
+            Some(Bsdf::Principled(Principled { ior, transmission, .. })) if transmission > 0.0 => ior,
+            _ => 1.0,
+        }
+    }
+
+    /// The material with its color, and the base color or albedo of its BSDF,
+    /// multiplied by `tint`, as vertex colors do.
+    pub fn tinted(&self, tint: Color) -> Material {
+        let bsdf = self.bsdf.map(|bsdf| match bsdf {
+            Bsdf::Lambertian { albedo } => Bsdf::Lambertian { albedo: albedo * tint },
+            Bsdf::Principled(principled) => Bsdf::Principled(Principled {
+                base_color: principled.base_color * tint,
+                ..principled
+            }),
+            other => other,
+        });
+
+        Material {
+            color: self.color * tint,
+            bsdf,
+            ..*self
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission.channels().iter().any(|channel| *channel > 0.0)
+    }
+
+    /// The material's BSDF, or a principled approximation of its Phong
+    /// parameters when it has none: reflectiveness becomes metalness and the
+    /// specular exponent a roughness unless one is given, as in the Blinn-Phong to Beckmann mapping.
+    pub fn bsdf(&self) -> Bsdf {
+        self.bsdf.unwrap_or_else(|| {
+            Bsdf::Principled(Principled {
+                base_color: self.color,
+                metallic: self.reflectiveness.clamp(0.0, 1.0),
+                roughness: if self.roughness > 0.0 {
+                    self.roughness
+                } else {
+                    (2.0 / (self.specular_exponent + 2.0)).sqrt().sqrt()
+                },
+                specular: self.specular.clamp(0.0, 1.0),
+                ..Principled::default()
+            })
+        })
+    }
+}
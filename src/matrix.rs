@@ -0,0 +1,202 @@
+use crate::vector::Vec3;
+
+use std::ops::Mul;
+
+/// A 4x4 matrix transforming points in homogeneous coordinates, stored by rows.
+/// Matrices multiply column vectors, so `a * b` applies `b` first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub rows: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4::scaling(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    /// A matrix from 16 values listed column after column, as glTF stores them.
+    pub fn from_columns(values: &[f64; 16]) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, value) in values.iter().enumerate() {
+            rows[i % 4][i / 4] = *value;
+        }
+        Mat4 { rows }
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        let mut matrix = Mat4::identity();
+        matrix.rows[0][3] = offset.x;
+        matrix.rows[1][3] = offset.y;
+        matrix.rows[2][3] = offset.z;
+        matrix
+    }
+
+    pub fn scaling(factors: Vec3) -> Mat4 {
+        Mat4 {
+            rows: [
+                [factors.x, 0.0, 0.0, 0.0],
+                [0.0, factors.y, 0.0, 0.0],
+                [0.0, 0.0, factors.z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Rotation by the unit quaternion `(x, y, z, w)`.
+    pub fn from_quaternion(x: f64, y: f64, z: f64, w: f64) -> Mat4 {
+        Mat4 {
+            rows: [
+                [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+                [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+                [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Rotation by `degrees` counter-clockwise around `axis`, seen with the axis pointing at the viewer.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Mat4 {
+        let axis = axis.normalize();
+        let half = degrees.to_radians() / 2.0;
+        let sin = half.sin();
+        Mat4::from_quaternion(axis.x * sin, axis.y * sin, axis.z * sin, half.cos())
+    }
+
+    /// The unit quaternion `(x, y, z, w)` of a matrix that only rotates.
+    pub fn to_quaternion(&self) -> [f64; 4] {
+        let m = &self.rows;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            [(m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s, s / 4.0]
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            [s / 4.0, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s, (m[2][1] - m[1][2]) / s]
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            [(m[0][1] + m[1][0]) / s, s / 4.0, (m[1][2] + m[2][1]) / s, (m[0][2] - m[2][0]) / s]
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            [(m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, s / 4.0, (m[1][0] - m[0][1]) / s]
+        }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+        Mat4 { rows }
+    }
+
+    /// Determinant of the upper 3x3 part, negative when the matrix mirrors.
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.rows;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// The inverse, by Gauss-Jordan elimination, or `None` for a singular matrix.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.rows;
+        let mut inverse = Mat4::identity().rows;
+
+        for column in 0..4 {
+            let pivot = (column..4).max_by(|i, j| a[*i][column].abs().total_cmp(&a[*j][column].abs()))?;
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = a[column][column].recip();
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inverse[row][j] -= factor * inverse[column][j];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4 { rows: inverse })
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let m = &self.rows;
+        let p = [point.x, point.y, point.z, 1.0];
+        let row = |i: usize| m[i].iter().zip(&p).map(|(a, b)| a * b).sum::<f64>();
+        let w = row(3);
+        Vec3::new(row(0), row(1), row(2)) / if w != 0.0 { w } else { 1.0 }
+    }
+
+    /// Transforms a direction, leaving out the translation.
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.rows;
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+        )
+    }
+
+    /// Transforms a surface normal by the inverse transpose, which keeps it
+    /// perpendicular to the surface under non-uniform scaling. Not normalized.
+    pub fn transform_normal(&self, normal: Vec3) -> Vec3 {
+        match self.inverse() {
+            Some(inverse) => inverse.transpose().transform_vector(normal),
+            None => normal,
+        }
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum();
+            }
+        }
+        Mat4 { rows }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::matrix::Mat4;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_transforms() {
+        let rotation = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        let rotated = rotation.transform_point(Vec3::new(1.0, 0.0, 0.0));
+        assert!((rotated - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        let turn = Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 200.0);
+        let [x, y, z, w] = turn.to_quaternion();
+        let difference = Mat4::from_quaternion(x, y, z, w).transform_vector(rotated) - turn.transform_vector(rotated);
+        assert!(difference.length() < 1e-9);
+
+        // Scale first, then move.
+        let matrix = Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::scaling(Vec3::new(2.0, 1.0, 1.0));
+        let point = Vec3::new(1.0, 1.0, 1.0);
+        let moved = matrix.transform_point(point);
+        assert!((moved - Vec3::new(3.0, 3.0, 4.0)).length() < 1e-9);
+        assert!((matrix.inverse().unwrap().transform_point(moved) - point).length() < 1e-9);
+
+        // The normal of the plane x + y = 0 stays perpendicular to it when stretched along x.
+        let normal = matrix.transform_normal(Vec3::new(1.0, 1.0, 0.0));
+        assert!(normal.dot(matrix.transform_vector(Vec3::new(1.0, -1.0, 0.0))).abs() < 1e-9);
+    }
+}
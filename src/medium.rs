@@ -0,0 +1,256 @@
+use crate::cfg::Cfg;
+use crate::color::Color;
+use crate::light::{Light, LightType};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shapes::Shape;
+use crate::vector::Vec3;
+
+use std::f64::consts::PI;
+use std::fmt::Debug;
+
+/// Transmittance below which the rest of a ray is not marched.
+const CUTOFF: f64 = 1e-3;
+
+/// Medium boundaries a ray may pass through before it is taken to be stuck
+/// on one.
+pub(crate) const MAX_CROSSINGS: usize = 32;
+
+/// A homogeneous participating medium such as fog, smoke or murky water.
+/// Coefficients are per unit of distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Medium {
+    /// Light absorbed by the medium.
+    pub absorption: Color,
+    /// Light scattered out of its way, and into other rays.
+    pub scattering: Color,
+    /// Henyey-Greenstein asymmetry, from -1 scattering light back where it
+    /// came from over 0 scattering it evenly to 1 letting it carry on.
+    pub anisotropy: f64,
+}
+
+impl Medium {
+    /// Grey fog scattering somewhat forwards, absorbing little.
+    pub fn fog(density: f64) -> Medium {
+        Medium {
+            absorption: Color::white() * (density * 0.1),
+            scattering: Color::white() * density,
+            anisotropy: 0.3,
+        }
+    }
+
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    /// Share of the light making it through `distance` of the medium.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let [r, g, b] = self.extinction().channels();
+        let fade = |extinction: f64| if extinction > 0.0 { (-extinction * distance).exp() } else { 1.0 };
+        Color::new(fade(r), fade(g), fade(b))
+    }
+
+    /// Henyey-Greenstein phase function for light scattered by an angle with
+    /// cosine `cos_theta` off its way.
+    pub fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.anisotropy.clamp(-0.99, 0.99);
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    /// Light scattered once into `ray` along its first `distance`, marched in
+    /// `options.volume_steps` steps. Point lights light the medium through
+    /// `Light::transmittance`, so shadows carve shafts out of it, while ambient
+    /// lights light it evenly. Rays are marched no further than where the
+    /// medium hides whatever lies beyond.
+    pub fn in_scattering(
+        &self,
+        ray: Ray,
+        distance: f64,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+    ) -> Color {
+        let extinction = self.extinction().channels().iter().cloned().filter(|e| *e > 0.0).fold(f64::INFINITY, f64::min);
+        let length = if extinction.is_finite() {
+            distance.min(-CUTOFF.ln() / extinction)
+        } else if distance.is_finite() {
+            distance
+        } else {
+            return Color::black();
+        };
+
+        let steps = options.volume_steps.max(1);
+        let step = length / f64::from(steps);
+        let ambient = Light::ambient(lights);
+
+        // A single jitter per ray hides the banding of the steps in noise,
+        // seeded from the ray so that still images stay still.
+        let offset = Sampler::for_point(ray.origin + ray.direction).next_f64();
+        let mut color = Color::black();
+
+        for i in 0..steps {
+            let t = (f64::from(i) + offset) * step;
+            let point = ray.origin + ray.direction * t;
+            let mut incoming = ambient;
+
+            for light in lights {
+                if let LightType::Point = light.light_type {
+                    let to_light = (light.position - point).normalize();
+                    let transmittance = if options.shadows {
+                        light.transmittance(objects, options, point, ray.time)
+                    } else {
+                        Color::white()
+                    };
+                    incoming += light.color * transmittance * (light.intensity * self.phase(to_light.dot(ray.direction)));
+                }
+            }
+
+            color += incoming * self.scattering * self.transmittance(t) * step;
+        }
+
+        color
+    }
+}
+
+/// Density varying through a shape's medium, scaling the medium's
+/// coefficients, see `Shape::density_field`.
+pub trait DensityField: Debug + Send + Sync {
+    fn density(&self, point: Vec3) -> f64;
+
+    /// Upper bound of `density`, which tracking takes its steps by.
+    fn max_density(&self) -> f64;
+
+    /// Light given off per unit of distance at a point, for fire.
+    fn emission(&self, _point: Vec3) -> Color {
+        Color::black()
+    }
+}
+
+/// A medium whose density varies as given by a field, rendered by delta and
+/// ratio tracking rather than marching. The extinction is taken to be grey,
+/// its largest channel, with the scattering's color left as the albedo.
+#[derive(Debug)]
+pub struct Heterogeneous<'a> {
+    pub medium: Medium,
+    pub field: &'a dyn DensityField,
+}
+
+impl Heterogeneous<'_> {
+    fn extinction(&self) -> f64 {
+        self.medium.extinction().channels().iter().cloned().fold(0.0, f64::max)
+    }
+
+    /// Delta tracking along the first `distance` of `ray`. Light is scattered
+    /// into the ray once at the first real collision, like `Medium::in_scattering`
+    /// does, and emission is gathered at every tentative one. Returns that light
+    /// and whether the ray got through to whatever lies beyond.
+    pub fn delta_tracking(
+        &self,
+        ray: Ray,
+        distance: f64,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+        sampler: &mut Sampler,
+    ) -> (Color, bool) {
+        let extinction = self.extinction();
+        let majorant = extinction * self.field.max_density();
+        if majorant <= 0.0 {
+            return (Color::black(), true);
+        }
+
+        let mut color = Color::black();
+        let mut t = 0.0;
+        loop {
+            t -= (1.0 - sampler.next_f64()).ln() / majorant;
+            if t >= distance {
+                return (color, true);
+            }
+
+            let point = ray.origin + ray.direction * t;
+            color += self.field.emission(point) * majorant.recip();
+
+            if sampler.next_f64() * majorant < self.field.density(point) * extinction {
+                let mut incoming = Light::ambient(lights);
+                for light in lights {
+                    if let LightType::Point = light.light_type {
+                        let to_light = (light.position - point).normalize();
+                        let transmittance = if options.shadows {
+                            light.transmittance(objects, options, point, ray.time)
+                        } else {
+                            Color::white()
+                        };
+                        let phase = self.medium.phase(to_light.dot(ray.direction));
+                        incoming += light.color * transmittance * (light.intensity * phase);
+                    }
+                }
+                return (color + incoming * self.medium.scattering * extinction.recip(), false);
+            }
+        }
+    }
+
+    /// Ratio tracking estimate of the share of light making it through the
+    /// first `distance` of `ray`.
+    pub fn ratio_tracking(&self, ray: Ray, distance: f64, sampler: &mut Sampler) -> Color {
+        let extinction = self.extinction();
+        let majorant = extinction * self.field.max_density();
+        if majorant <= 0.0 {
+            return Color::white();
+        }
+
+        let mut transmittance: f64 = 1.0;
+        let mut t = 0.0;
+        loop {
+            t -= (1.0 - sampler.next_f64()).ln() / majorant;
+            if t >= distance || transmittance <= 0.0 {
+                return Color::white() * transmittance.max(0.0);
+            }
+            let density = self.field.density(ray.origin + ray.direction * t);
+            transmittance *= 1.0 - density * extinction / majorant;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::light::{Light, LightType};
+    use crate::medium::Medium;
+    use crate::ray::Ray;
+    use crate::vector::Vec3;
+
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_in_scattering() {
+        // Evenly lit fog seen through to infinity glows with its albedo.
+        let medium = Medium {
+            absorption: Color::white() * 0.1,
+            scattering: Color::white() * 0.3,
+            anisotropy: 0.0,
+        };
+        let lights = vec![Light {
+            light_type: LightType::Ambient,
+            position: Vec3::zero(),
+            intensity: 1.0,
+            color: Color::white(),
+        }];
+        let options = Cfg {
+            volume_steps: 4096,
+            ..Cfg::default()
+        };
+        let ray = Ray {
+            origin: Vec3::zero(),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        let glow = medium.in_scattering(ray, f64::INFINITY, &[], &lights, &options);
+        assert!((glow.channels()[0] - 0.75).abs() < 1e-2);
+
+        let integral: f64 = (0..1000).map(|i| medium.phase((f64::from(i) + 0.5) / 500.0 - 1.0) / 500.0).sum();
+        assert!((integral * 2.0 * PI - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::animation::Track;
+use crate::matrix::Mat4;
+use crate::vector::Vec3;
+
+/// A placement that scales, then rotates, then translates. Kept apart rather
+/// than as a matrix so that it can be blended between keys without shearing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    /// Unit quaternion `(x, y, z, w)`.
+    pub rotation: [f64; 4],
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vec3::zero(),
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn translation(offset: Vec3) -> Transform {
+        Transform {
+            translation: offset,
+            ..Transform::identity()
+        }
+    }
+
+    /// Rotation by `degrees` around `axis`, turning as `Mat4::rotation` does.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Transform {
+        let axis = axis.normalize();
+        let half = degrees.to_radians() / 2.0;
+        let sin = half.sin();
+        Transform {
+            rotation: [axis.x * sin, axis.y * sin, axis.z * sin, half.cos()],
+            ..Transform::identity()
+        }
+    }
+
+    pub fn scaling(factors: Vec3) -> Transform {
+        Transform {
+            scale: factors,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        let [x, y, z, w] = self.rotation;
+        let length = (x * x + y * y + z * z + w * w).sqrt();
+        Mat4::translation(self.translation)
+            * Mat4::from_quaternion(x / length, y / length, z / length, w / length)
+            * Mat4::scaling(self.scale)
+    }
+
+    /// Blends towards `other`, turning the shorter way round.
+    pub fn lerp(&self, other: &Transform, t: f64) -> Transform {
+        Transform {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: slerp(self.rotation, other.rotation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+}
+
+/// Spherical interpolation of unit quaternions.
+fn slerp(a: [f64; 4], mut b: [f64; 4], t: f64) -> [f64; 4] {
+    let mut cos = a.iter().zip(&b).map(|(a, b)| a * b).sum::<f64>();
+    // `b` and `-b` are the same rotation; the one nearer `a` is the short way.
+    if cos < 0.0 {
+        cos = -cos;
+        b = b.map(|b| -b);
+    }
+
+    let (wa, wb) = if cos > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let angle = cos.acos();
+        let sin = angle.sin();
+        (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+    };
+    let blended = [0, 1, 2, 3].map(|i| a[i] * wa + b[i] * wb);
+    let length = blended.iter().map(|q| q * q).sum::<f64>().sqrt();
+    blended.map(|q| q / length)
+}
+
+/// Placement of a shape over time, evaluated at a ray's time.
+#[derive(Debug)]
+pub enum Motion {
+    /// Moves from `start` at `t0` to `end` at `t1`, holding still outside
+    /// of that interval.
+    Linear {
+        start: Transform,
+        end: Transform,
+        t0: f64,
+        t1: f64,
+    },
+    /// Follows keyframes, eased by their curves.
+    Keyframed(Track<Transform>),
+}
+
+impl Motion {
+    pub fn transform(&self, time: f64) -> Transform {
+        match self {
+            Motion::Linear { start, end, t0, t1 } => {
+                if t1 <= t0 {
+                    return *start;
+                }
+                start.lerp(end, ((time - t0) / (t1 - t0)).clamp(0.0, 1.0))
+            }
+            Motion::Keyframed(track) => track.sample(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::animation::{Curve, Track};
+    use crate::motion::{Motion, Transform};
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_keyframes() {
+        let motion = Motion::Keyframed(
+            Track::new(0.0, Transform::identity(), Curve::Linear)
+                .key(1.0, Transform::translation(Vec3::new(2.0, 0.0, 0.0)), Curve::Linear)
+                .key(2.0, Transform::translation(Vec3::new(2.0, 4.0, 0.0)), Curve::Linear),
+        );
+        assert_eq!(motion.transform(-1.0).translation, Vec3::zero());
+        assert_eq!(motion.transform(0.5).translation, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(motion.transform(1.5).translation, Vec3::new(2.0, 2.0, 0.0));
+        assert_eq!(motion.transform(3.0).translation, Vec3::new(2.0, 4.0, 0.0));
+
+        // Halfway through a quarter turn is an eighth of a turn.
+        let turn = Transform::identity().lerp(&Transform::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0), 0.5);
+        let turned = turn.matrix().transform_vector(Vec3::new(1.0, 0.0, 0.0));
+        let expected = Vec3::new(1.0, 1.0, 0.0).normalize();
+        assert!((turned - expected).length() < 1e-9);
+    }
+}
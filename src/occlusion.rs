@@ -0,0 +1,123 @@
+use crate::bsdf::{self, Frame};
+use crate::cfg::Cfg;
+use crate::color::Color;
+use crate::light::Light;
+use crate::ray::{Intersection, Ray};
+use crate::sampler::Sampler;
+use crate::shapes::Shape;
+use crate::vector::Vec3;
+
+/// Settings for ambient occlusion: how many rays are shot over the hemisphere
+/// around a point, and how far away geometry still blocks ambient light.
+#[derive(Clone, Debug)]
+pub struct AmbientOcclusion {
+    pub samples: u32,
+    pub distance: f64,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> AmbientOcclusion {
+        AmbientOcclusion {
+            samples: 16,
+            distance: 5.0,
+        }
+    }
+}
+
+impl AmbientOcclusion {
+    /// Share of the cosine weighted hemisphere above the intersection, on the
+    /// side `direction` arrived from, that is open within `distance`: 1 in
+    /// the open and 0 at the bottom of a crevice.
+    pub fn visibility(
+        &self,
+        objects: &[Box<dyn Shape>],
+        intersection: Intersection,
+        direction: Vec3,
+        ray_time: f64,
+        sampler: &mut Sampler,
+    ) -> f64 {
+        let samples = self.samples.max(1);
+        let normal = if direction.dot(intersection.normal) > 0.0 {
+            -intersection.normal
+        } else {
+            intersection.normal
+        };
+        let frame = Frame::new(normal);
+
+        let mut open = 0;
+        for _ in 0..samples {
+            let ray = Ray {
+                origin: intersection.hit_point.correct(normal),
+                direction: frame.to_world(bsdf::sample_cosine(sampler)),
+                time: ray_time,
+            };
+            if !Ray::intersect(ray, objects).is_some_and(|hit| hit.distance < self.distance) {
+                open += 1;
+            }
+        }
+
+        f64::from(open) / f64::from(samples)
+    }
+}
+
+/// Clay render of `ray`: white surfaces shaded only by how occluded they are,
+/// using the configured ambient occlusion or the default one.
+pub fn trace(
+    ray: Ray,
+    objects: &[Box<dyn Shape>],
+    _lights: &[Light],
+    options: &Cfg,
+    background: Color,
+    sampler: &mut Sampler,
+) -> Color {
+    let intersection = match Ray::intersect(ray, objects) {
+        Some(intersection) => intersection,
+        None => return background,
+    };
+
+    let default = AmbientOcclusion::default();
+    let settings = options.ambient_occlusion.as_ref().unwrap_or(&default);
+    Color::white() * settings.visibility(objects, intersection, ray.direction, ray.time, sampler)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::Material;
+    use crate::occlusion::AmbientOcclusion;
+    use crate::ray::Intersection;
+    use crate::sampler::Sampler;
+    use crate::shapes::Shape;
+    use crate::shapes::sphere::Sphere;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_visibility() {
+        let ao = AmbientOcclusion::default();
+        let intersection = Intersection {
+            distance: 1.0,
+            hit_point: Vec3::zero(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 0.0),
+            material: Material::neutral(),
+            object: 1,
+        };
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let mut sampler = Sampler::new(2);
+
+        assert_eq!(ao.visibility(&[], intersection, down, 0.0, &mut sampler), 1.0);
+
+        let far: Vec<Box<dyn Shape>> = vec![Box::new(Sphere {
+            position: Vec3::new(0.0, 20.0, 0.0),
+            radius: 2.0,
+            material: Material::neutral(),
+        })];
+        assert_eq!(ao.visibility(&far, intersection, down, 0.0, &mut sampler), 1.0);
+
+        let near: Vec<Box<dyn Shape>> = vec![Box::new(Sphere {
+            position: Vec3::new(0.0, 2.0, 0.0),
+            radius: 1.5,
+            material: Material::neutral(),
+        })];
+        assert!(ao.visibility(&near, intersection, down, 0.0, &mut sampler) < 0.8);
+    }
+}
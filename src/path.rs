@@ -0,0 +1,97 @@
+use crate::cfg::Cfg;
+use crate::color::Color;
+use crate::light::{Light, LightType};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shapes::Shape;
+
+/// Bounces after which paths may be ended early by Russian roulette.
+const ROULETTE_DEPTH: u8 = 3;
+
+/// Radiance along `ray` by unidirectional path tracing, following at most
+/// `options.max_rays` bounces. Point lights and emissive shapes are sampled at
+/// every bounce, point lights not falling off with distance like in
+/// `Light::shade`. Ambient lights act
+/// as a uniform sky for bounced rays, while camera rays that miss everything
+/// see `background`.
+pub fn trace(
+    ray: Ray,
+    objects: &[Box<dyn Shape>],
+    lights: &[Light],
+    options: &Cfg,
+    background: Color,
+    sampler: &mut Sampler,
+) -> Color {
+    let sky = Light::ambient(lights);
+
+    let mut ray = ray;
+    let mut radiance = Color::black();
+    let mut throughput = Color::white();
+    let mut specular = true;
+
+    for depth in 0..options.max_rays {
+        let intersection = match Ray::intersect(ray, objects) {
+            Some(intersection) => intersection,
+            None => {
+                radiance += throughput * if depth == 0 { background } else { sky };
+                break;
+            }
+        };
+
+        let bsdf = intersection.material.bsdf();
+        let normal = intersection.normal;
+        let wo = -ray.direction;
+
+        // Light from shapes that get sampled below was already counted at the
+        // previous bounce, unless that bounce took a mirror lobe.
+        if specular || objects[intersection.object].area() <= 0.0 {
+            radiance += throughput * intersection.material.emission;
+        }
+
+        if !bsdf.is_specular() {
+            for (wi, irradiance) in Light::sample_emitters(objects, options, intersection, ray.time, sampler) {
+                radiance += throughput * bsdf.eval(normal, wo, wi) * irradiance * wi.dot(normal).abs();
+            }
+
+            // Point lights cannot be hit by paths, so their caustics come from photons.
+            if let Some(caustics) = &options.caustics {
+                radiance += throughput * caustics.radiance(intersection.hit_point, normal, |wi| bsdf.eval(normal, wo, wi));
+            }
+
+            for light in lights {
+                if let LightType::Point = light.light_type {
+                    if options.shadows && !light.illuminates(objects, intersection, ray.time) {
+                        continue;
+                    }
+                    let wi = (light.position - intersection.hit_point).normalize();
+                    let f = bsdf.eval(normal, wo, wi);
+                    radiance += throughput * f * light.color * (light.intensity * wi.dot(normal).abs());
+                }
+            }
+        }
+
+        let sample = match bsdf.sample(normal, wo, sampler) {
+            Some(sample) => sample,
+            None => break,
+        };
+        throughput = throughput * sample.weight;
+        specular = sample.specular;
+
+        if depth >= ROULETTE_DEPTH {
+            let survival = throughput.channels().iter().cloned().fold(0.0, f64::max).min(0.95);
+            if sampler.next_f64() >= survival {
+                break;
+            }
+            throughput = throughput * survival.recip();
+        }
+
+        let side = if sample.direction.dot(normal) < 0.0 { -normal } else { normal };
+        ray = Ray {
+            origin: intersection.hit_point.correct(side),
+            direction: sample.direction,
+            time: ray.time,
+        };
+    }
+
+    radiance
+}
@@ -0,0 +1,357 @@
+use crate::color::Color;
+use crate::light::{Light, LightType};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shapes::Shape;
+use crate::vector::Vec3;
+
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+/// Settings for the caustics photon map.
+#[derive(Clone, Debug)]
+pub struct PhotonMapping {
+    /// Photons shot from every point light.
+    pub photons: usize,
+    /// Radius around a shaded point within which photons are gathered.
+    pub radius: f64,
+    /// Specular bounces a photon may make before it is given up on.
+    pub max_bounces: u32,
+}
+
+impl Default for PhotonMapping {
+    fn default() -> PhotonMapping {
+        PhotonMapping {
+            photons: 200_000,
+            radius: 0.3,
+            max_bounces: 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Photon {
+    pub position: Vec3,
+    /// Direction the photon was travelling in when it landed.
+    pub direction: Vec3,
+    pub power: Color,
+}
+
+/// Photons that reached a diffuse surface through one or more mirror or glass
+/// bounces, in a kd-tree. The tree is implicit: every range of the photon list
+/// is split at its middle photon along `axes[middle]`, with the smaller
+/// photons before it and the larger ones after.
+#[derive(Debug)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<u8>,
+    radius: f64,
+}
+
+fn coordinate(point: Vec3, axis: u8) -> f64 {
+    match axis {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z,
+    }
+}
+
+impl PhotonMap {
+    /// Shoots photons from the point lights at the sampleable specular shapes
+    /// (see `Shape::sample_surface`) and keeps the ones that end up on a
+    /// diffuse surface. Point lights do not fall off with distance in this
+    /// renderer, so photons are scaled by the square of the distance to their
+    /// first bounce to match the direct light.
+    pub fn build(objects: &[Box<dyn Shape>], lights: &[Light], settings: &PhotonMapping) -> PhotonMap {
+        let targets: Vec<usize> = (0..objects.len())
+            .filter(|index| {
+                let shape = &objects[*index];
+                shape.area() > 0.0 && shape.material().bsdf().is_specular()
+            })
+            .collect();
+
+        let mut photons = Vec::new();
+        if !targets.is_empty() {
+            for (light_index, light) in lights.iter().enumerate() {
+                if let LightType::Point = light.light_type {
+                    let seed = (light_index * settings.photons) as u64;
+                    photons.par_extend((0..settings.photons).into_par_iter().filter_map(|i| {
+                        let mut sampler = Sampler::new(seed + i as u64);
+                        PhotonMap::trace(objects, light, &targets, settings, &mut sampler)
+                    }));
+                }
+            }
+        }
+
+        let mut axes = vec![0; photons.len()];
+        PhotonMap::balance(&mut photons, &mut axes);
+
+        PhotonMap {
+            photons,
+            axes,
+            radius: settings.radius,
+        }
+    }
+
+    fn trace(
+        objects: &[Box<dyn Shape>],
+        light: &Light,
+        targets: &[usize],
+        settings: &PhotonMapping,
+        sampler: &mut Sampler,
+    ) -> Option<Photon> {
+        // Aim at a point on a random specular shape. The solid angle density
+        // of that direction turns the light's intensity into the photon's power.
+        let index = (sampler.next_f64() * targets.len() as f64) as usize;
+        let chosen = targets[index.min(targets.len() - 1)];
+        let target = &objects[chosen];
+        let (point, normal) = target.sample_surface(sampler)?;
+
+        let to_target = point - light.position;
+        let direction = to_target.normalize();
+        let cos = direction.dot(normal).abs();
+        if cos <= 0.0 {
+            return None;
+        }
+
+        // Points hidden behind the shape's own front are dropped, so that every
+        // direction stands for one point and the density below holds.
+        let mut ray = Ray {
+            origin: light.position,
+            direction,
+            time: 0.0,
+        };
+        if target.intersect(ray).is_some_and(|distance| distance < to_target.length() * (1.0 - 1e-4)) {
+            return None;
+        }
+
+        // Aiming at any other target the direction passes through would have
+        // sent the photon the same way, so the density sums over all of them.
+        let pdf = targets
+            .iter()
+            .map(|&other| {
+                if other == chosen {
+                    return to_target.norm() / (cos * target.area());
+                }
+                let shape = &objects[other];
+                let distance = match shape.intersect(ray) {
+                    Some(distance) => distance,
+                    None => return 0.0,
+                };
+                let cos = direction.dot(shape.normal_at(ray.origin + direction * distance, ray.time)).abs();
+                if cos <= 0.0 {
+                    return 0.0;
+                }
+                distance * distance / (cos * shape.area())
+            })
+            .sum::<f64>()
+            / targets.len() as f64;
+        let mut power = light.color * (light.intensity / (pdf * settings.photons as f64));
+
+        for bounce in 0..settings.max_bounces {
+            let intersection = Ray::intersect(ray, objects)?;
+            let bsdf = intersection.material.bsdf();
+
+            if !bsdf.is_specular() {
+                if bounce == 0 {
+                    return None;
+                }
+                return Some(Photon {
+                    position: intersection.hit_point,
+                    direction: ray.direction,
+                    power,
+                });
+            }
+
+            if bounce == 0 {
+                power = power * (intersection.distance * intersection.distance);
+            }
+
+            let sample = bsdf.sample(intersection.normal, -ray.direction, sampler)?;
+            power = power * sample.weight;
+
+            let side = if sample.direction.dot(intersection.normal) < 0.0 { -intersection.normal } else { intersection.normal };
+            ray = Ray {
+                origin: intersection.hit_point.correct(side),
+                direction: sample.direction,
+                time: 0.0,
+            };
+        }
+
+        None
+    }
+
+    /// Sorts `photons` into the implicit kd-tree, splitting every range along
+    /// the axis it is widest in.
+    fn balance(photons: &mut [Photon], axes: &mut [u8]) {
+        if photons.len() <= 1 {
+            return;
+        }
+
+        let mut min = photons[0].position;
+        let mut max = photons[0].position;
+        for photon in photons.iter() {
+            let p = photon.position;
+            min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let middle = photons.len() / 2;
+        photons.select_nth_unstable_by(middle, |a, b| {
+            coordinate(a.position, axis).total_cmp(&coordinate(b.position, axis))
+        });
+        axes[middle] = axis;
+
+        let (lower, upper) = photons.split_at_mut(middle);
+        let (lower_axes, upper_axes) = axes.split_at_mut(middle);
+        PhotonMap::balance(lower, lower_axes);
+        PhotonMap::balance(&mut upper[1..], &mut upper_axes[1..]);
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Calls `f` for every photon within `radius` of `point`.
+    pub fn for_each_near<F: FnMut(&Photon)>(&self, point: Vec3, radius: f64, mut f: F) {
+        self.visit(0, self.photons.len(), point, radius * radius, &mut f);
+    }
+
+    fn visit<F: FnMut(&Photon)>(&self, start: usize, end: usize, point: Vec3, radius2: f64, f: &mut F) {
+        if start >= end {
+            return;
+        }
+
+        let middle = start + (end - start) / 2;
+        let photon = &self.photons[middle];
+        if (photon.position - point).norm() <= radius2 {
+            f(photon);
+        }
+
+        if end - start == 1 {
+            return;
+        }
+
+        let axis = self.axes[middle];
+        let offset = coordinate(point, axis) - coordinate(photon.position, axis);
+        let (near, far) = if offset <= 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+
+        self.visit(near.0, near.1, point, radius2, f);
+        if offset * offset <= radius2 {
+            self.visit(far.0, far.1, point, radius2, f);
+        }
+    }
+
+    /// Caustic light reflected towards `wo` at a point, from a density
+    /// estimate over the photons near it. `reflectance` gives the surface's
+    /// BSDF for the direction a photon came from. Photons are only gathered
+    /// from a thin disc around the surface so that they do not leak through
+    /// thin objects or around corners.
+    pub fn radiance<F: Fn(Vec3) -> Color>(&self, point: Vec3, normal: Vec3, reflectance: F) -> Color {
+        let mut total = Color::black();
+        self.for_each_near(point, self.radius, |photon| {
+            if (photon.position - point).dot(normal).abs() <= self.radius * 0.25 {
+                total += photon.power * reflectance(-photon.direction);
+            }
+        });
+        total * (PI * self.radius * self.radius).recip()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bsdf::Bsdf;
+    use crate::color::Color;
+    use crate::light::{Light, LightType};
+    use crate::material::Material;
+    use crate::photon::{Photon, PhotonMap, PhotonMapping};
+    use crate::sampler::Sampler;
+    use crate::shapes::plane::Plane;
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_kd_tree() {
+        let mut sampler = Sampler::new(5);
+        let mut photons: Vec<Photon> = (0..1000)
+            .map(|_| Photon {
+                position: Vec3::new(sampler.next_f64(), sampler.next_f64(), sampler.next_f64()) * 10.0,
+                direction: Vec3::new(0.0, -1.0, 0.0),
+                power: Color::white(),
+            })
+            .collect();
+        let expected: Vec<Vec3> = photons.iter().map(|photon| photon.position).collect();
+
+        let mut axes = vec![0; photons.len()];
+        PhotonMap::balance(&mut photons, &mut axes);
+        let map = PhotonMap { photons, axes, radius: 1.0 };
+
+        let point = Vec3::new(5.0, 5.0, 5.0);
+        let brute = expected.iter().filter(|p| (**p - point).norm() <= 4.0).count();
+        let mut found = 0;
+        map.for_each_near(point, 2.0, |_| found += 1);
+
+        assert!(brute > 0);
+        assert_eq!(found, brute);
+    }
+
+    #[test]
+    fn test_overlapping_targets() {
+        // A second mirror sphere in the same place as the first is aimed at
+        // half the time, but every photon still hits both, so the caustic
+        // must not get any brighter.
+        let floor = || -> Box<dyn Shape> {
+            Box::new(Plane {
+                position: Vec3::zero(),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                material: Material {
+                    bsdf: Some(Bsdf::Lambertian { albedo: Color::white() }),
+                    ..Material::neutral()
+                },
+            })
+        };
+        let mirror = || -> Box<dyn Shape> {
+            Box::new(Sphere {
+                position: Vec3::new(0.0, 1.0, 0.0),
+                radius: 0.5,
+                material: Material {
+                    bsdf: Some(Bsdf::Conductor { eta: Color::white() * 0.2, k: Color::white() * 3.0, roughness: 0.0 }),
+                    ..Material::neutral()
+                },
+            })
+        };
+        let lights = vec![Light {
+            light_type: LightType::Point,
+            position: Vec3::new(2.0, 1.5, 0.0),
+            intensity: 1.0,
+            color: Color::white(),
+        }];
+        let settings = PhotonMapping { photons: 20000, ..PhotonMapping::default() };
+        let power = |objects: &[Box<dyn Shape>]| {
+            let map = PhotonMap::build(objects, &lights, &settings);
+            assert!(!map.is_empty());
+            map.photons.iter().map(|photon| photon.power.channels()[0]).sum::<f64>()
+        };
+
+        let single = power(&[floor(), mirror()]);
+        let double = power(&[floor(), mirror(), mirror()]);
+        assert!((double / single - 1.0).abs() < 0.05, "{} against {}", double, single);
+    }
+}
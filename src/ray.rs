@@ -1,7 +1,10 @@
+use crate::bsdf;
+use crate::sampler::Sampler;
 use crate::shapes::Shape;
 use crate::color::Color;
 use crate::light::Light;
 use crate::material::Material;
+use crate::medium::{Heterogeneous, MAX_CROSSINGS};
 use crate::vector::Vec3;
 use crate::cfg::Cfg;
 
@@ -9,6 +12,7 @@ use crate::cfg::Cfg;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub time: f64,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -16,70 +20,406 @@ pub struct Intersection {
     pub distance: f64,
     pub hit_point: Vec3,
     pub normal: Vec3,
+    pub uv: (f64, f64),
     pub material: Material,
+    /// Index of the hit shape in the list of objects.
+    pub object: usize,
+}
+
+/// Where a ray cast by `Ray::cast_ray_in` travels: inside the medium of the
+/// shape at index `inside`, or through the fog, having passed through
+/// `crossings` medium boundaries since its last bounce and made `depth`
+/// bounces before that.
+#[derive(Clone, Copy, Debug, Default)]
+struct Passage {
+    inside: Option<usize>,
+    crossings: usize,
+    depth: u8,
 }
 
 impl Ray {
     pub fn intersect(ray: Ray, objects: &[Box<dyn Shape>]) -> Option<Intersection> {
-        let mut distance = std::f64::INFINITY;
+        let mut distance = f64::INFINITY;
         let mut material = Material::neutral();
         let mut normal = Vec3::zero();
         let mut hit_point = Vec3::zero();
+        let mut uv = (0.0, 0.0);
+        let mut object = 0;
 
-        for shape in objects {
+        for (index, shape) in objects.iter().enumerate() {
             if let Some(dist) = shape.intersect(ray) {
                 if dist < distance {
                     distance = dist;
-                    material = shape.material();
-
                     hit_point = ray.origin + (ray.direction * distance);
-                    normal = shape.normal(hit_point);
+                    material = shape.material_at(hit_point, ray.time);
+                    normal = shape.normal_at(hit_point, ray.time);
+                    uv = shape.uv_at(hit_point, ray.time);
+                    object = index;
                 }
             }
         }
 
-        if distance < std::f64::INFINITY {
+        if distance < f64::INFINITY {
             Some(Intersection {
                 distance,
                 hit_point,
                 normal,
+                uv,
                 material,
+                object,
             })
         } else {
             None
         }
     }
 
+    /// Color seen along `ray`, or `None` when it leaves the scene unseen.
+    /// Rays travel through `options.fog` until they enter a shape with a
+    /// medium of its own. `sampler` is the pixel's, for glossy reflections.
     pub fn cast_ray(
         ray: Ray,
         objects: &[Box<dyn Shape>],
         lights: &[Light],
         options: &Cfg,
         depth: u8,
+        sampler: &mut Sampler,
+    ) -> Option<Color> {
+        let passage = Passage { depth, ..Passage::default() };
+        Ray::cast_ray_in(ray, passage, Color::black(), objects, lights, options, sampler)
+    }
+
+    /// `cast_ray` for a camera ray, which sees `background` where it leaves
+    /// the scene, dimmed by the media it went through on the way.
+    pub fn cast_camera_ray(
+        ray: Ray,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+        background: Color,
+        sampler: &mut Sampler,
+    ) -> Color {
+        Ray::cast_ray_in(ray, Passage::default(), background, objects, lights, options, sampler).unwrap_or(background)
+    }
+
+    /// `cast_ray` for a ray travelling through the medium `passage` is in,
+    /// that sees `background` where it leaves the scene through a medium.
+    /// Media do not nest: leaving a shape's medium always leads back into the
+    /// fog. Passing through a boundary does not count as a bounce.
+    fn cast_ray_in(
+        ray: Ray,
+        passage: Passage,
+        background: Color,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+        sampler: &mut Sampler,
     ) -> Option<Color> {
-        if depth >= options.max_rays {
+        let depth = passage.depth;
+        if depth >= options.max_rays || passage.crossings >= MAX_CROSSINGS {
             return None;
         }
+        let inside = passage.inside;
+
+        let intersection = Ray::intersect(ray, objects);
+        let distance = intersection.map_or(f64::INFINITY, |intersection| intersection.distance);
+
+        let mut surface = || {
+            intersection.and_then(|intersection| match intersection.material.medium {
+                // The boundary of a medium is passed straight through.
+                Some(_) => {
+                    let entering = ray.direction.dot(intersection.normal) < 0.0;
+                    let (next, side) = if entering {
+                        (Some(intersection.object), -intersection.normal)
+                    } else {
+                        (None, intersection.normal)
+                    };
+                    let passed_ray = Ray {
+                        origin: intersection.hit_point.correct(side),
+                        direction: ray.direction,
+                        time: ray.time,
+                    };
+                    let passage = Passage {
+                        inside: next,
+                        crossings: passage.crossings + 1,
+                        depth,
+                    };
+                    Ray::cast_ray_in(passed_ray, passage, background, objects, lights, options, sampler)
+                }
+                None => Some(
+                    Ray::direct(ray, intersection, objects, lights, options)
+                        + Ray::reflect(ray, intersection, objects, lights, options, depth, sampler)
+                        + Ray::transmit(ray, intersection, objects, lights, options, depth, sampler),
+                ),
+            })
+        };
+
+        let medium = match inside {
+            Some(index) => objects[index].material().medium,
+            None => options.fog,
+        };
+        let medium = match medium {
+            Some(medium) => medium,
+            None => return surface(),
+        };
+
+        match inside.and_then(|index| objects[index].density_field()) {
+            Some(field) => {
+                let mut tracking = Sampler::for_point(ray.origin + ray.direction);
+                let (volume, through) = Heterogeneous { medium, field }
+                    .delta_tracking(ray, distance, objects, lights, options, &mut tracking);
+                if through {
+                    Some(volume + surface().unwrap_or(background))
+                } else {
+                    Some(volume)
+                }
+            }
+            None => {
+                let scattered = medium.in_scattering(ray, distance, objects, lights, options);
+                Some(scattered + surface().unwrap_or(background) * medium.transmittance(distance))
+            }
+        }
+    }
+
+    /// Light given off and directly reflected towards `ray` at `intersection`,
+    /// the part of `cast_ray` that does not need further rays.
+    pub fn direct(
+        ray: Ray,
+        intersection: Intersection,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+    ) -> Color {
+        let shaded_color = Light::shade(objects, lights, options, intersection, ray.direction, ray.time);
+        intersection.material.emission + shaded_color * Ray::opacity(intersection.material, options)
+    }
+
+    fn opacity(material: Material, options: &Cfg) -> f64 {
+        if options.opacity {
+            material.opacity.clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+
+    /// Light refracted through a see-through material towards `ray`, already
+    /// scaled by its transparency and tinted by its color. Rays that cannot
+    /// leave the denser medium are totally internally reflected instead.
+    pub fn transmit(
+        ray: Ray,
+        intersection: Intersection,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+        depth: u8,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let material = intersection.material;
+        let transparency = 1.0 - Ray::opacity(material, options);
+        if transparency <= 0.0 {
+            return Color::black();
+        }
+
+        let entering = ray.direction.dot(intersection.normal) < 0.0;
+        let (normal, eta) = if entering {
+            (intersection.normal, material.ior().recip())
+        } else {
+            (-intersection.normal, material.ior())
+        };
+
+        let cos_i = -ray.direction.dot(normal);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        let (direction, origin) = if sin2_t > 1.0 {
+            (ray.direction.reflect(normal), intersection.hit_point.correct(normal))
+        } else {
+            let cos_t = (1.0 - sin2_t).sqrt();
+            let refracted = ray.direction * eta + normal * (eta * cos_i - cos_t);
+            (refracted.normalize(), intersection.hit_point.correct(-normal))
+        };
+
+        let transmitted_ray = Ray {
+            origin,
+            direction,
+            time: ray.time,
+        };
 
-        let intersection = Ray::intersect(ray, objects)?;
+        Ray::cast_ray(transmitted_ray, objects, lights, options, depth + 1, sampler)
+            .map_or(Color::black(), |color| color * material.color * transparency)
+    }
+
+    /// Light mirrored towards `ray` at `intersection`, already scaled by the
+    /// material's reflectiveness.
+    pub fn reflect(
+        ray: Ray,
+        intersection: Intersection,
+        objects: &[Box<dyn Shape>],
+        lights: &[Light],
+        options: &Cfg,
+        depth: u8,
+        sampler: &mut Sampler,
+    ) -> Color {
+        if intersection.material.reflectiveness <= 0.0 || !options.reflections {
+            return Color::black();
+        }
 
-        let mut shaded_color = Light::shade(objects, lights, options, intersection, ray.direction);
+        let material = intersection.material;
+        let reflected_ray = |direction: Vec3| Ray {
+            origin: intersection.hit_point.correct(intersection.normal),
+            direction,
+            time: ray.time,
+        };
 
-        if intersection.material.reflectiveness > 0.0 && options.reflections {
-            let reflection = ray.direction.reflect(intersection.normal).normalize();
+        // Glossy reflections are sampled several times at the first bounce.
+        // Directions sent below the surface are drawn again rather than lost,
+        // so the lobe keeps its energy at grazing angles.
+        let samples = if material.roughness > 0.0 && depth == 0 { options.glossy_samples.max(1) } else { 1 };
+        let mut color = Color::black();
+        let mut accepted = 0;
 
-            let reflected_ray = Ray {
-                origin: intersection.hit_point.correct(intersection.normal),
-                direction: reflection,
+        for _ in 0..samples {
+            let reflection = match glossy_reflection(ray.direction, intersection.normal, material.roughness, sampler) {
+                Some(reflection) => reflection,
+                None => continue,
             };
+            accepted += 1;
+            if let Some(reflected) = Ray::cast_ray(reflected_ray(reflection), objects, lights, options, depth + 1, sampler) {
+                color += reflected;
+            }
+        }
 
-            if let Some(reflected_color) =
-            Ray::cast_ray(reflected_ray, objects, lights, options, depth + 1)
-            {
-                shaded_color += reflected_color * intersection.material.reflectiveness;
+        if accepted == 0 {
+            return Color::black();
+        }
+        color * (material.reflectiveness / f64::from(accepted))
+    }
+}
+
+/// Direction of `direction` reflected off a surface with `normal` and
+/// `roughness`. Smooth surfaces mirror it. Rough ones mirror it off a
+/// microfacet normal drawn from the GGX distribution, which importance
+/// samples the glossy lobe around the mirror direction, giving `None` when
+/// that would send it below the surface.
+fn glossy_reflection(direction: Vec3, normal: Vec3, roughness: f64, sampler: &mut Sampler) -> Option<Vec3> {
+    if roughness <= 0.0 {
+        return Some(direction.reflect(normal).normalize());
+    }
+
+    let h = bsdf::sample_microfacet_normal(normal, roughness, sampler);
+    let reflection = direction.reflect(h).normalize();
+    if reflection.dot(normal) > 0.0 {
+        Some(reflection)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::medium::Medium;
+    use crate::ray::{glossy_reflection, Intersection, Ray};
+    use crate::sampler::Sampler;
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_through_media() {
+        let fog = |z: f64| -> Box<dyn Shape> {
+            Box::new(Sphere {
+                position: Vec3::new(0.0, 0.0, z),
+                radius: 1.0,
+                material: Material {
+                    medium: Some(Medium::fog(0.1)),
+                    ..Material::neutral()
+                },
+            })
+        };
+        let glowing = Box::new(Sphere {
+            position: Vec3::new(0.0, 0.0, -10.0),
+            radius: 1.0,
+            material: Material {
+                emission: Color::white(),
+                ..Material::neutral()
+            },
+        });
+        let objects = vec![fog(-3.0), fog(-6.0), glowing];
+        let ray = Ray {
+            origin: Vec3::zero(),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        // Four boundaries lie in front of the sphere, as many as the default
+        // number of bounces, which the sphere must still be seen through.
+        let options = Cfg::default();
+        assert_eq!(options.max_rays, 4);
+        let color = Ray::cast_camera_ray(ray, &objects, &[], &options, Color::black(), &mut Sampler::new(1));
+        assert!(color.channels().iter().all(|channel| *channel > 0.5), "{:?}", color);
+    }
+
+    #[test]
+    fn test_glossy_reflection() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let direction = Vec3::new(1.0, -2.0, 0.0).normalize();
+        let mirror = direction.reflect(normal).normalize();
+        let mut sampler = Sampler::new(7);
+        assert_eq!(glossy_reflection(direction, normal, 0.0, &mut sampler), Some(mirror));
+
+        // Rough reflections spread out around the mirror direction.
+        let mut sum = Vec3::zero();
+        let mut widest = 1.0f64;
+        for _ in 0..4000 {
+            if let Some(reflection) = glossy_reflection(direction, normal, 0.2, &mut sampler) {
+                assert!(reflection.dot(normal) > 0.0);
+                sum = sum + reflection;
+                widest = widest.min(reflection.dot(mirror));
             }
         }
+        assert!(widest < 0.99);
+        assert!(sum.normalize().dot(mirror) > 0.999);
+    }
+
+    #[test]
+    fn test_grazing_glossy_reflection_keeps_its_energy() {
+        // Seen from the inside of a glowing sphere, every reflection is white,
+        // so a rough mirror must give back exactly its reflectiveness however
+        // many of its samples point below the surface.
+        let objects: Vec<Box<dyn Shape>> = vec![Box::new(Sphere {
+            position: Vec3::zero(),
+            radius: 100.0,
+            material: Material {
+                emission: Color::white(),
+                ..Material::neutral()
+            },
+        })];
+        let intersection = Intersection {
+            distance: 1.0,
+            hit_point: Vec3::zero(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 0.0),
+            material: Material {
+                reflectiveness: 0.8,
+                roughness: 0.6,
+                ..Material::neutral()
+            },
+            object: 1,
+        };
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.05, 0.0),
+            direction: Vec3::new(1.0, -0.05, 0.0).normalize(),
+            time: 0.0,
+        };
+        let options = Cfg {
+            glossy_samples: 64,
+            ..Cfg::default()
+        };
 
-        Some(shaded_color)
+        let mut sampler = Sampler::new(3);
+        let color = Ray::reflect(ray, intersection, &objects, &[], &options, 0, &mut sampler);
+        for channel in color.channels().iter() {
+            assert!((channel - 0.8).abs() < 1e-9, "{:?}", color);
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,19 @@
+use crate::adaptive::{self, AdaptiveSampling, Estimate};
+use crate::aov::{AovBuffers, AovSample};
 use crate::camera::Camera;
 use crate::color::Color;
 use crate::shapes::Shape;
 use crate::light::Light;
 use crate::cfg::Cfg;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::tiles::{CancelToken, Cancelled, Progress, Tile};
 
 use rayon::prelude::*;
-use image::ImageBuffer;
+use image::{ImageBuffer, RgbImage};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct Renderer {
@@ -21,49 +28,429 @@ pub struct Renderer {
 
 impl Renderer {
 
-    pub fn render(&self) -> Vec<u32> {
-        let gamma_correction = self.options.gamma.recip();
+    /// Color of the pixel at `(x, y)`. With more than one sample per pixel the
+    /// samples are jittered across the pixel and the camera's shutter interval.
+    pub fn sample_pixel(&self, x: u32, y: u32) -> Color {
+        self.sample_pixel_counted(x, y).0
+    }
+
+    /// Like `sample_pixel`, also returning how many samples the pixel took.
+    pub fn sample_pixel_counted(&self, x: u32, y: u32) -> (Color, u32) {
+        if let Some(settings) = &self.options.adaptive {
+            return self.sample_adaptive(x, y, settings);
+        }
+
+        if self.options.samples <= 1 {
+            return (self.trace(x, y, None), 1);
+        }
+
+        let mut sampler = Sampler::new(u64::from(y) * u64::from(self.width) + u64::from(x));
+        let mut color = Color::black();
+
+        for _ in 0..self.options.samples {
+            color += self.trace(x, y, Some(&mut sampler));
+        }
+
+        (color * f64::from(self.options.samples).recip(), self.options.samples)
+    }
+
+    /// Samples the pixel in batches until its estimated error is small enough.
+    fn sample_adaptive(&self, x: u32, y: u32, settings: &AdaptiveSampling) -> (Color, u32) {
+        let mut sampler = Sampler::new(u64::from(y) * u64::from(self.width) + u64::from(x));
+        let mut estimate = Estimate::new();
+
+        while estimate.samples < settings.min_samples.max(1) {
+            estimate.add(self.trace(x, y, Some(&mut sampler)));
+        }
+
+        while !estimate.converged(settings) {
+            for _ in 0..settings.batch.max(1) {
+                estimate.add(self.trace(x, y, Some(&mut sampler)));
+            }
+        }
+
+        (estimate.color(), estimate.samples)
+    }
+
+    /// A single camera ray's color through pixel `(x, y)`, as the integrator sees it.
+    fn trace(&self, x: u32, y: u32, sampler: Option<&mut Sampler>) -> Color {
+        match sampler {
+            Some(sampler) => {
+                let ray = self.camera_ray(x, y, Some(sampler));
+                self.options.integrator.radiance(self, ray, sampler)
+            }
+            None => {
+                let mut sampler = Sampler::new(u64::from(y) * u64::from(self.width) + u64::from(x));
+                self.options.integrator.radiance(self, self.camera_ray(x, y, None), &mut sampler)
+            }
+        }
+    }
+
+    /// Camera ray through pixel `(x, y)`, through its corner when there is no
+    /// sampler to jitter it with.
+    fn camera_ray(&self, x: u32, y: u32, sampler: Option<&mut Sampler>) -> Ray {
         let w = f64::from(self.width);
         let h = f64::from(self.height);
-        (0..self.width * self.height)
+
+        match sampler {
+            None => self.camera.get_ray(f64::from(x) / w, f64::from(y) / h),
+            Some(sampler) => {
+                let u = (f64::from(x) + sampler.next_f64()) / w;
+                let v = (f64::from(y) + sampler.next_f64()) / h;
+                self.camera.get_ray_at(u, v, sampler.next_f64())
+            }
+        }
+    }
+
+    /// Renders the image together with its depth, normal, albedo, object id,
+    /// direct and reflected light, shadow and occlusion passes, all from the
+    /// same rays and samples as `render`. The direct and reflected passes are
+    /// only filled in by integrators that split them, see
+    /// `Integrator::direct_and_reflected`, and are black otherwise.
+    pub fn render_aovs(&self) -> AovBuffers {
+        let pixels = (0..self.width * self.height)
             .into_par_iter()
             .map(|pixel| {
-
                 let x = pixel % self.width;
                 let y = pixel / self.width;
 
-                let u = f64::from(x) / w;
-                let v = f64::from(y) / h;
+                if let Some(settings) = &self.options.adaptive {
+                    return self.sample_aovs_adaptive(x, y, settings);
+                }
 
-                let ray = self.camera.get_ray(u, v);
+                if self.options.samples <= 1 {
+                    return self.trace_aovs(x, y, None);
+                }
 
-                Ray::cast_ray(ray, &self.objects, &self.lights, &self.options, 0)
-                    .unwrap_or(self.bg_color).to_u32(gamma_correction)
+                let mut sampler = Sampler::new(u64::from(pixel));
+                let samples: Vec<AovSample> = (0..self.options.samples)
+                    .map(|_| self.trace_aovs(x, y, Some(&mut sampler)))
+                    .collect();
+                AovSample::average(&samples)
             })
-            .collect()
+            .collect();
+
+        AovBuffers {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
     }
 
-    pub fn render_to_file(&self, filename: String) {
-        let mut imgbuf = ImageBuffer::new(self.width, self.height);
+    /// `sample_adaptive` for all the passes, stopping once the image is clean.
+    fn sample_aovs_adaptive(&self, x: u32, y: u32, settings: &AdaptiveSampling) -> AovSample {
+        let mut sampler = Sampler::new(u64::from(y) * u64::from(self.width) + u64::from(x));
+        let mut estimate = Estimate::new();
+        let mut samples = Vec::new();
+
+        while estimate.samples < settings.min_samples.max(1) {
+            let sample = self.trace_aovs(x, y, Some(&mut sampler));
+            estimate.add(sample.beauty);
+            samples.push(sample);
+        }
+
+        while !estimate.converged(settings) {
+            for _ in 0..settings.batch.max(1) {
+                let sample = self.trace_aovs(x, y, Some(&mut sampler));
+                estimate.add(sample.beauty);
+                samples.push(sample);
+            }
+        }
+
+        AovSample::average(&samples)
+    }
+
+    /// The passes for a single camera ray through pixel `(x, y)`, its beauty
+    /// being what `trace` returns for it.
+    fn trace_aovs(&self, x: u32, y: u32, sampler: Option<&mut Sampler>) -> AovSample {
+        let mut own = Sampler::new(u64::from(y) * u64::from(self.width) + u64::from(x));
+        let (ray, sampler) = match sampler {
+            Some(sampler) => (self.camera_ray(x, y, Some(sampler)), sampler),
+            None => (self.camera_ray(x, y, None), &mut own),
+        };
+        let beauty = self.options.integrator.radiance(self, ray, sampler);
+
+        let hit = Ray::intersect(ray, &self.objects).filter(|_| self.options.max_rays > 0);
+        let (direct, reflected) = self
+            .options
+            .integrator
+            .direct_and_reflected(self, ray, hit, sampler)
+            .unwrap_or((Color::black(), Color::black()));
+
+        let intersection = match hit {
+            Some(intersection) => intersection,
+            None => {
+                return AovSample {
+                    beauty,
+                    direct,
+                    reflected,
+                    ..AovSample::background(self.bg_color)
+                }
+            }
+        };
+
+        AovSample {
+            beauty,
+            depth: intersection.distance,
+            normal: intersection.normal,
+            albedo: intersection.material.color,
+            object: Some(intersection.object),
+            direct,
+            reflected,
+            shadow: 1.0 - Light::visibility(&self.objects, &self.lights, intersection, ray.time),
+            occlusion: self.options.ambient_occlusion.clone().unwrap_or_default().visibility(
+                &self.objects,
+                intersection,
+                ray.direction,
+                ray.time,
+                &mut Sampler::for_point(intersection.hit_point),
+            ),
+        }
+    }
 
+    pub fn render(&self) -> Vec<u32> {
+        self.render_with_progress(&CancelToken::new(), |_| {})
+            .expect("render without a cancel token was cancelled")
+    }
+
+    /// Renders the image tile by tile, in the order and size given by the
+    /// options, calling `progress` as each tile finishes.
+    pub fn render_with_progress<F>(&self, cancel: &CancelToken, progress: F) -> Result<Vec<u32>, Cancelled>
+    where
+        F: Fn(&Progress) + Sync,
+    {
         let gamma_correction = self.options.gamma.recip();
+        let buffer = Mutex::new(vec![0u32; (self.width * self.height) as usize]);
 
-        let w = f64::from(self.width);
-        let h = f64::from(self.height);
+        self.render_tiles(
+            cancel,
+            |x, y| self.sample_pixel(x, y).to_u32(gamma_correction),
+            |status, pixels| {
+                let tile = status.tile;
+                let mut buffer = buffer.lock().unwrap();
+                for (row, line) in pixels.chunks(tile.width as usize).enumerate() {
+                    let offset = ((tile.y + row as u32) * self.width + tile.x) as usize;
+                    buffer[offset..offset + line.len()].copy_from_slice(line);
+                }
+                drop(buffer);
+
+                progress(status);
+            },
+        )?;
+
+        Ok(buffer.into_inner().unwrap())
+    }
+
+    /// One sample per pixel, meant to be accumulated with the other passes of a
+    /// progressive render. Each pass draws its own jitter, so averaging passes
+    /// `0..n` converges like `n` samples per pixel. With adaptive sampling a
+    /// single pass already samples every pixel until it is clean. Finished
+    /// tiles are handed to `done` in row order.
+    pub fn render_pass<F>(&self, pass: u32, cancel: &CancelToken, done: F) -> Result<(), Cancelled>
+    where
+        F: Fn(&Progress, &[Color]) + Sync,
+    {
+        let jitter = self.options.samples > 1;
+        let pixels = u64::from(self.width) * u64::from(self.height);
+
+        self.render_tiles(
+            cancel,
+            |x, y| {
+                if self.options.adaptive.is_some() {
+                    self.sample_pixel(x, y)
+                } else if jitter {
+                    let pixel = u64::from(y) * u64::from(self.width) + u64::from(x);
+                    let mut sampler = Sampler::new(u64::from(pass) * pixels + pixel);
+                    self.trace(x, y, Some(&mut sampler))
+                } else {
+                    self.trace(x, y, None)
+                }
+            },
+            |status, colors| done(status, &colors),
+        )
+    }
+
+    /// Shades every tile with `shade`, handing each finished tile's pixels to
+    /// `done` in row order. Threads pick tiles up in the configured order and
+    /// check `cancel` before starting each one.
+    fn render_tiles<T, S, F>(&self, cancel: &CancelToken, shade: S, done: F) -> Result<(), Cancelled>
+    where
+        T: Send,
+        S: Fn(u32, u32) -> T + Sync,
+        F: Fn(&Progress, Vec<T>) + Sync,
+    {
+        let tiles = Tile::split(self.width, self.height, self.options.tile_size, self.options.tile_order);
+        let next = AtomicUsize::new(0);
+        let completed = AtomicUsize::new(0);
+        let start = Instant::now();
+
+        (0..rayon::current_num_threads()).into_par_iter().for_each(|_| loop {
+            if cancel.is_cancelled() {
+                return;
+            }
+
+            let tile = match tiles.get(next.fetch_add(1, Ordering::SeqCst)) {
+                Some(tile) => *tile,
+                None => return,
+            };
+
+            let pixels = (0..tile.pixels())
+                .map(|i| shade(tile.x + i % tile.width, tile.y + i / tile.width))
+                .collect();
+
+            let status = Progress {
+                tile,
+                completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                total: tiles.len(),
+                elapsed: start.elapsed(),
+            };
+            done(&status, pixels);
+        });
+
+        // A cancel that comes once every tile is done loses nothing.
+        if completed.load(Ordering::SeqCst) < tiles.len() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn render_image(&self) -> RgbImage {
+        self.render_image_with_counts().0
+    }
 
-        // Iterate over the coordinates and pixels of the image
-        for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-            let u = f64::from(x) / w;
-            let v = f64::from(y) / h;
+    /// Renders the image together with a heat map of how many samples each
+    /// pixel took, white being the most any pixel may take. Mostly of
+    /// interest with adaptive sampling.
+    pub fn render_image_with_counts(&self) -> (RgbImage, RgbImage) {
+        let gamma_correction = self.options.gamma.recip();
+        let (pixels, counts): (Vec<image::Rgb<u8>>, Vec<u32>) = (0..self.width * self.height)
+            .into_par_iter()
+            .map(|pixel| {
+                let x = pixel % self.width;
+                let y = pixel / self.width;
 
-            let ray = self.camera.get_ray(u, v);
+                let (color, samples) = self.sample_pixel_counted(x, y);
+                (color.gamma_rgb(gamma_correction), samples)
+            })
+            .unzip();
 
-            let color = Ray::cast_ray(ray, &self.objects, &self.lights, &self.options, 0)
-                .unwrap_or(self.bg_color);
+        let max = match &self.options.adaptive {
+            Some(settings) => settings.max_samples,
+            None => self.options.samples,
+        };
+        let image = ImageBuffer::from_fn(self.width, self.height, |x, y| pixels[(y * self.width + x) as usize]);
+        (image, adaptive::sample_map(self.width, self.height, &counts, max))
+    }
+
+    pub fn render_to_file(&self, filename: String) {
+        self.render_image().save(filename).unwrap();
+    }
+}
 
-            *pixel = color.gamma_rgb(gamma_correction);
+#[cfg(test)]
+mod test {
+    use crate::adaptive::{self, AdaptiveSampling};
+    use crate::camera::Camera;
+    use crate::cfg::{Cfg, IntegratorKind};
+    use crate::color::Color;
+    use crate::light::{Light, LightType};
+    use crate::material::Material;
+    use crate::renderer::Renderer;
+    use crate::shapes::sphere::Sphere;
+    use crate::tiles::CancelToken;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_cancel() {
+        let renderer = Renderer {
+            width: 8,
+            height: 8,
+            camera: Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), 60.0, 1.0, 0.0),
+            objects: Vec::new(),
+            lights: Vec::new(),
+            bg_color: Color::black(),
+            options: Cfg::default(),
+        };
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(renderer.render_with_progress(&cancel, |_| {}).is_err());
+
+        // Cancelled as the last tile finishes, the image is still complete.
+        let cancel = CancelToken::new();
+        let result = renderer.render_with_progress(&cancel, |progress| {
+            if progress.completed == progress.total {
+                cancel.cancel();
+            }
+        });
+        assert_eq!(result.unwrap().len(), 64);
+    }
+
+    #[test]
+    fn test_aovs_follow_the_integrator() {
+        let renderer = Renderer {
+            width: 4,
+            height: 4,
+            camera: Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), 30.0, 1.0, 0.0),
+            objects: vec![Box::new(Sphere {
+                position: Vec3::zero(),
+                radius: 1.0,
+                material: Material { color: Color::white(), ..Material::neutral() },
+            })],
+            lights: vec![Light {
+                light_type: LightType::Point,
+                position: Vec3::new(2.0, 2.0, 5.0),
+                intensity: 1.0,
+                color: Color::white(),
+            }],
+            bg_color: Color::new(0.0, 0.0, 1.0),
+            options: Cfg {
+                samples: 4,
+                integrator: IntegratorKind::Path.build(),
+                ..Cfg::default()
+            },
+        };
+
+        // The beauty pass is the image, and the Whitted only passes stay dark.
+        let aovs = renderer.render_aovs();
+        for (pixel, sample) in aovs.pixels.iter().enumerate() {
+            let expected = renderer.sample_pixel(pixel as u32 % 4, pixel as u32 / 4);
+            let found = sample.beauty.channels();
+            assert!(expected.channels().iter().zip(&found).all(|(a, b)| (a - b).abs() < 1e-9));
+            assert_eq!(sample.direct.channels(), [0.0; 3]);
         }
+        assert!(aovs.pixels.iter().any(|sample| sample.object.is_some()));
+    }
+
+    #[test]
+    fn test_image_with_counts() {
+        let renderer = Renderer {
+            width: 4,
+            height: 4,
+            camera: Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), 30.0, 1.0, 0.0),
+            objects: vec![Box::new(Sphere {
+                position: Vec3::zero(),
+                radius: 1.0,
+                material: Material { color: Color::white(), ..Material::neutral() },
+            })],
+            lights: Vec::new(),
+            bg_color: Color::new(0.0, 0.0, 1.0),
+            options: Cfg {
+                samples: 4,
+                adaptive: Some(AdaptiveSampling::default()),
+                ..Cfg::default()
+            },
+        };
 
-        imgbuf.save(filename).unwrap();
+        // The map comes from the very samples the image was made of.
+        let (image, map) = renderer.render_image_with_counts();
+        let gamma_correction = renderer.options.gamma.recip();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let (color, samples) = renderer.sample_pixel_counted(x, y);
+            assert_eq!(*pixel, color.gamma_rgb(gamma_correction));
+            let max = AdaptiveSampling::default().max_samples;
+            assert_eq!(map.get_pixel(x, y), &adaptive::heat(f64::from(samples) / f64::from(max)).gamma_rgb(1.0));
+        }
     }
-}
\ No newline at end of file
+}
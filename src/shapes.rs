@@ -1,18 +1,83 @@
+use crate::export::{ExportOptions, Tessellation};
 use crate::material::Material;
+use crate::medium::DensityField;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector::Vec3;
 
 use std::fmt::Debug;
-use std::marker::Sync;
+use std::marker::{Send, Sync};
 
-pub trait Shape: Debug + Sync {
+pub trait Shape: Debug + Send + Sync {
     fn intersect(&self, ray: Ray) -> Option<f64>;
     fn material(&self) -> Material;
     fn normal(&self, hit_point: Vec3) -> Vec3;
+
+    /// Surface coordinates of a hit point, for shapes that can be textured.
+    fn uv(&self, _hit_point: Vec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    /// Normal for a hit made by a ray at `time`. Only shapes that move while
+    /// the shutter is open need to override this.
+    fn normal_at(&self, hit_point: Vec3, _time: f64) -> Vec3 {
+        self.normal(hit_point)
+    }
+
+    fn uv_at(&self, hit_point: Vec3, _time: f64) -> (f64, f64) {
+        self.uv(hit_point)
+    }
+
+    /// Material at a hit point, for shapes made of more than one.
+    fn material_at(&self, _hit_point: Vec3, _time: f64) -> Material {
+        self.material()
+    }
+
+    /// Surface area, for shapes that can light the scene when emissive.
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    /// A uniformly distributed point on the surface and the normal there.
+    /// Shapes that return `None` can still glow, but cast no light.
+    fn sample_surface(&self, _sampler: &mut Sampler) -> Option<(Vec3, Vec3)> {
+        None
+    }
+
+    /// Varying density of the medium filling the shape, for shapes whose
+    /// material has one that is not spread evenly.
+    fn density_field(&self) -> Option<&dyn DensityField> {
+        None
+    }
+
+    /// Bounding volume and primitive tests `intersect` makes for a ray, shown
+    /// by the BVH cost debug integrator.
+    fn traversal_cost(&self, _ray: Ray) -> u32 {
+        1
+    }
+
+    /// Whether `point` is inside the solid the surface encloses, for shapes
+    /// that enclose one and can be combined by `Csg`.
+    fn contains(&self, _point: Vec3) -> Option<bool> {
+        None
+    }
+
+    /// Triangles approximating the surface, for shapes that can be exported
+    /// to other programs.
+    fn tessellate(&self, _options: &ExportOptions) -> Option<Tessellation> {
+        None
+    }
 }
 
 pub mod sphere;
 pub mod plane;
 pub mod aabb;
+pub mod csg;
 pub mod naabb;
-pub mod triangle;
\ No newline at end of file
+pub mod triangle;
+pub mod heightfield;
+pub mod mesh;
+pub mod moving;
+pub mod transformed;
+pub mod volume;
+pub mod voxels;
\ No newline at end of file
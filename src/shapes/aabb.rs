@@ -1,4 +1,5 @@
 use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vector::Vec3;
@@ -31,8 +32,9 @@ impl Shape for Aabb {
         tmin = tmin.max(ta.x.max(ta.y.max(ta.z)));
         tmax = tmax.min(tb.x.min(tb.y.min(tb.z)));
 
+        // From inside the box, the ray leaves it where it would have entered.
         if tmin < tmax && tmax > 0.0 {
-            Some(tmin)
+            Some(if tmin > 0.0 { tmin } else { tmax })
         } else {
             None
         }
@@ -64,9 +66,72 @@ impl Shape for Aabb {
 
         dist = (extent.z - local.z.abs()).abs();
         if dist < min {
-            ret = Vec3 { x: 0.0, y: 0.0, z: local.y.signum() };
+            ret = Vec3 { x: 0.0, y: 0.0, z: local.z.signum() };
         }
 
         ret
     }
+
+    fn contains(&self, point: Vec3) -> Option<bool> {
+        Some(
+            (self.min.x..=self.max.x).contains(&point.x)
+                && (self.min.y..=self.max.y).contains(&point.y)
+                && (self.min.z..=self.max.z).contains(&point.z),
+        )
+    }
+
+    fn tessellate(&self, _options: &ExportOptions) -> Option<Tessellation> {
+        Some(Tessellation::cuboid(self.min, self.max, self.material))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::aabb::Aabb;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    fn unit_box() -> Aabb {
+        Aabb {
+            min: Vec3::new(-1.0, -1.0, -1.0),
+            max: Vec3::new(1.0, 1.0, 1.0),
+            material: Material::neutral(),
+        }
+    }
+
+    #[test]
+    fn test_intersect() {
+        let aabb = unit_box();
+        let ray = |origin: Vec3, direction: Vec3| Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+
+        let outside = ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((aabb.intersect(outside).unwrap() - 4.0).abs() < 1e-9);
+
+        // Rays starting inside, such as refracted ones, hit the far side.
+        let inside = ray(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((aabb.intersect(inside).unwrap() - 1.0).abs() < 1e-9);
+
+        let away = ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(aabb.intersect(away).is_none());
+    }
+
+    #[test]
+    fn test_normal() {
+        let aabb = unit_box();
+        let faces = [
+            (Vec3::new(1.0, 0.2, 0.3), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(0.2, -1.0, 0.3), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(0.2, -0.5, 1.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(0.2, 0.5, -1.0), Vec3::new(0.0, 0.0, -1.0)),
+        ];
+        for (hit_point, normal) in faces.iter() {
+            assert_eq!(aabb.normal(*hit_point), *normal);
+        }
+    }
 }
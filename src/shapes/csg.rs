@@ -0,0 +1,204 @@
+use crate::shapes::Shape;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+/// How `Csg` combines its shapes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    /// Everything inside any of the shapes, without the surfaces inside others.
+    Union,
+    /// Everything inside all of the shapes.
+    Intersection,
+    /// Everything inside the first shape and none of the others.
+    Difference,
+}
+
+/// How far on either side of a surface `Csg` looks to tell inside from outside.
+const PROBE: f64 = crate::EPSILON * 10.0;
+
+/// Surfaces a ray may cross before giving up on finding the combined one.
+const MAX_CROSSINGS: usize = 64;
+
+/// Solid shapes combined by constructive solid geometry. Rays hit only the
+/// surface of the combined solid, which keeps the materials of the shapes it
+/// is made of. Surfaces cut out by a difference face into the hole.
+#[derive(Debug)]
+pub struct Csg {
+    operation: Operation,
+    shapes: Vec<Box<dyn Shape>>,
+}
+
+impl Csg {
+    /// Panics unless every shape encloses a solid, see `Shape::contains`.
+    pub fn new(operation: Operation, shapes: Vec<Box<dyn Shape>>) -> Csg {
+        assert!(
+            !shapes.is_empty() && shapes.iter().all(|shape| shape.contains(Vec3::zero()).is_some()),
+            "constructive solid geometry needs shapes that enclose solids"
+        );
+        Csg { operation, shapes }
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    fn inside(&self, point: Vec3) -> bool {
+        let mut inside = self.shapes.iter().map(|shape| shape.contains(point) == Some(true));
+        match self.operation {
+            Operation::Union => inside.any(|inside| inside),
+            Operation::Intersection => inside.all(|inside| inside),
+            Operation::Difference => inside.next().unwrap_or(false) && !inside.any(|inside| inside),
+        }
+    }
+
+    /// The shape `hit_point` is on the surface of, and the combined solid's
+    /// outward normal there.
+    fn surface(&self, hit_point: Vec3, time: f64) -> Option<(&dyn Shape, Vec3)> {
+        self.shapes.iter().enumerate().find_map(|(i, shape)| {
+            let normal = shape.normal_at(hit_point, time);
+            let on_surface = shape.contains(hit_point - normal * PROBE) == Some(true)
+                && shape.contains(hit_point + normal * PROBE) == Some(false);
+            if !on_surface {
+                None
+            } else if self.operation == Operation::Difference && i > 0 {
+                Some((shape.as_ref(), -normal))
+            } else {
+                Some((shape.as_ref(), normal))
+            }
+        })
+    }
+}
+
+impl Shape for Csg {
+    /// Steps from one surface of the shapes to the next until it finds one
+    /// the ray enters or leaves the combined solid through.
+    fn intersect(&self, ray: Ray) -> Option<f64> {
+        let mut start = 0.0;
+
+        for _ in 0..MAX_CROSSINGS {
+            let moved = Ray {
+                origin: ray.origin + ray.direction * start,
+                ..ray
+            };
+            let distance = self
+                .shapes
+                .iter()
+                .filter_map(|shape| shape.intersect(moved))
+                .filter(|distance| *distance > 0.0)
+                .min_by(|a, b| a.total_cmp(b))?;
+
+            let hit = start + distance;
+            let point = ray.origin + ray.direction * hit;
+            if self.inside(point - ray.direction * PROBE) != self.inside(point + ray.direction * PROBE) {
+                return Some(hit);
+            }
+            start = hit + PROBE;
+        }
+        None
+    }
+
+    fn material(&self) -> Material {
+        self.shapes[0].material()
+    }
+
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        self.normal_at(hit_point, 0.0)
+    }
+
+    fn uv(&self, hit_point: Vec3) -> (f64, f64) {
+        self.uv_at(hit_point, 0.0)
+    }
+
+    fn normal_at(&self, hit_point: Vec3, time: f64) -> Vec3 {
+        match self.surface(hit_point, time) {
+            Some((_, normal)) => normal,
+            None => self.shapes[0].normal_at(hit_point, time),
+        }
+    }
+
+    fn uv_at(&self, hit_point: Vec3, time: f64) -> (f64, f64) {
+        match self.surface(hit_point, time) {
+            Some((shape, _)) => shape.uv_at(hit_point, time),
+            None => (0.0, 0.0),
+        }
+    }
+
+    fn material_at(&self, hit_point: Vec3, time: f64) -> Material {
+        match self.surface(hit_point, time) {
+            Some((shape, _)) => shape.material_at(hit_point, time),
+            None => self.material(),
+        }
+    }
+
+    fn traversal_cost(&self, ray: Ray) -> u32 {
+        self.shapes.iter().map(|shape| shape.traversal_cost(ray)).sum()
+    }
+
+    fn contains(&self, point: Vec3) -> Option<bool> {
+        Some(self.inside(point))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::aabb::Aabb;
+    use crate::shapes::csg::{Csg, Operation};
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    fn shapes() -> Vec<Box<dyn Shape>> {
+        let red = Material {
+            color: Color::new(1.0, 0.0, 0.0),
+            ..Material::neutral()
+        };
+        vec![
+            Box::new(Aabb {
+                min: Vec3::new(-1.0, -1.0, -1.0),
+                max: Vec3::new(1.0, 1.0, 1.0),
+                material: Material::neutral(),
+            }),
+            Box::new(Sphere {
+                position: Vec3::new(0.0, 0.0, 1.0),
+                radius: 0.5,
+                material: red,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_operations() {
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        // The sphere bulges out of the front of the box.
+        let union = Csg::new(Operation::Union, shapes());
+        assert!((union.intersect(ray).unwrap() - 3.5).abs() < 1e-9);
+
+        // The sphere's back half leaves a dimple in the front face.
+        let difference = Csg::new(Operation::Difference, shapes());
+        let distance = difference.intersect(ray).unwrap();
+        assert!((distance - 4.5).abs() < 1e-9);
+        let hit_point = ray.origin + ray.direction * distance;
+        assert!((difference.normal(hit_point) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!((difference.material_at(hit_point, 0.0).color.channels()[0] - 1.0).abs() < 1e-9);
+
+        // Off the dimple, the front face is hit as it is.
+        let beside = Ray {
+            origin: Vec3::new(0.8, 0.0, 5.0),
+            ..ray
+        };
+        assert!((difference.intersect(beside).unwrap() - 4.0).abs() < 1e-9);
+
+        let intersection = Csg::new(Operation::Intersection, shapes());
+        assert!((intersection.intersect(ray).unwrap() - 4.0).abs() < 1e-9);
+        assert!(intersection.intersect(beside).is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::shapes::Shape;
+use crate::shapes::triangle::intersect_triangle;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+use image::error::{ImageError, ParameterError, ParameterErrorKind};
+use ndarray::Array2;
+use std::path::Path;
+
+/// A regular grid of height samples spanning `min` to `min + size` in the xz plane.
+/// Heights are in units of `size.y` above `min.y`, so that samples from 0 to 1
+/// fill the box, and are stored as given. Rows run along z and columns along x. Only the samples are kept in memory; triangles and vertex
+/// normals are rebuilt on the fly while the grid is walked.
+#[derive(Debug)]
+pub struct Heightfield {
+    pub min: Vec3,
+    pub size: Vec3,
+    pub material: Material,
+    heights: Array2<f64>,
+    low: f64,
+    high: f64,
+}
+
+impl Heightfield {
+    /// A heightfield over the samples in `heights`, indexed by row then column.
+    /// Grids with fewer than 2 rows or columns are rejected.
+    pub fn from_array(heights: Array2<f64>, min: Vec3, size: Vec3, material: Material) -> image::ImageResult<Heightfield> {
+        let (rows, cols) = heights.dim();
+        if rows < 2 || cols < 2 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        }
+
+        let low = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+        let high = heights.iter().cloned().fold(-f64::INFINITY, f64::max);
+
+        Ok(Heightfield {
+            min,
+            size,
+            material,
+            heights,
+            low,
+            high,
+        })
+    }
+
+    /// Loads a grayscale height map, black being `min.y` and white `min.y + size.y`.
+    /// Images narrower or shorter than 2 pixels are rejected.
+    pub fn from_image<P: AsRef<Path>>(path: P, min: Vec3, size: Vec3, material: Material) -> image::ImageResult<Heightfield> {
+        let img = image::open(path)?.to_luma16();
+        let (w, h) = img.dimensions();
+        let heights = Array2::from_shape_fn((h as usize, w as usize), |(z, x)| {
+            f64::from(img.get_pixel(x as u32, z as u32)[0]) / f64::from(u16::MAX)
+        });
+        Heightfield::from_array(heights, min, size, material)
+    }
+
+    fn cells(&self) -> (usize, usize) {
+        let (rows, cols) = self.heights.dim();
+        (cols - 1, rows - 1)
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        let (nx, nz) = self.cells();
+        (self.size.x / nx as f64, self.size.z / nz as f64)
+    }
+
+    fn height(&self, x: usize, z: usize) -> f64 {
+        self.min.y + self.heights[[z, x]] * self.size.y
+    }
+
+    fn vertex(&self, x: usize, z: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        Vec3::new(self.min.x + x as f64 * dx, self.height(x, z), self.min.z + z as f64 * dz)
+    }
+
+    /// Vertex normal from central differences, falling back to one sided ones at the border.
+    fn vertex_normal(&self, x: usize, z: usize) -> Vec3 {
+        let (nx, nz) = self.cells();
+        let (dx, dz) = self.cell_size();
+
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(nx));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(nz));
+
+        let slope_x = (self.height(x1, z) - self.height(x0, z)) / ((x1 - x0) as f64 * dx);
+        let slope_z = (self.height(x, z1) - self.height(x, z0)) / ((z1 - z0) as f64 * dz);
+
+        Vec3::new(-slope_x, 1.0, -slope_z).normalize()
+    }
+
+    /// Grid coordinates of a world point, clamped to the cell containing it.
+    fn locate(&self, point: Vec3) -> (usize, usize, f64, f64) {
+        let (nx, nz) = self.cells();
+        let (dx, dz) = self.cell_size();
+
+        let fx = ((point.x - self.min.x) / dx).max(0.0);
+        let fz = ((point.z - self.min.z) / dz).max(0.0);
+        let x = (fx.floor() as usize).min(nx - 1);
+        let z = (fz.floor() as usize).min(nz - 1);
+
+        (x, z, (fx - x as f64).min(1.0), (fz - z as f64).min(1.0))
+    }
+
+    fn bounds(&self, ray: Ray) -> Option<(f64, f64)> {
+        let lo = Vec3::new(self.min.x, self.min.y + self.low * self.size.y, self.min.z);
+        let hi = Vec3::new(self.min.x + self.size.x, self.min.y + self.high * self.size.y, self.min.z + self.size.z);
+
+        let inv_dir = 1.0 / ray.direction;
+        let t0s = (lo - ray.origin) * inv_dir;
+        let t1s = (hi - ray.origin) * inv_dir;
+
+        let tmin = t0s.x.min(t1s.x).max(t0s.y.min(t1s.y)).max(t0s.z.min(t1s.z));
+        let tmax = t0s.x.max(t1s.x).min(t0s.y.max(t1s.y)).min(t0s.z.max(t1s.z));
+
+        if tmin <= tmax && tmax > 0.0 {
+            Some((tmin.max(0.0), tmax))
+        } else {
+            None
+        }
+    }
+
+    /// Tests the two triangles of cell `(x, z)`, split along the `(x, z)`-`(x + 1, z + 1)` diagonal.
+    fn intersect_cell(&self, ray: Ray, x: usize, z: usize) -> Option<f64> {
+        let v00 = self.vertex(x, z);
+        let v10 = self.vertex(x + 1, z);
+        let v01 = self.vertex(x, z + 1);
+        let v11 = self.vertex(x + 1, z + 1);
+
+        let first = intersect_triangle(ray, v00, v10, v11);
+        let second = intersect_triangle(ray, v00, v11, v01);
+
+        match (first, second) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+impl Shape for Heightfield {
+    /// Walks the cells under the ray with a 2D DDA, only testing triangles in
+    /// cells whose height range overlaps the ray's height across the cell.
+    fn intersect(&self, ray: Ray) -> Option<f64> {
+        let (t_enter, t_exit) = self.bounds(ray)?;
+        let (nx, nz) = self.cells();
+        let (dx, dz) = self.cell_size();
+
+        let start = ray.origin + ray.direction * t_enter;
+        let (mut x, mut z, _, _) = self.locate(start);
+
+        let step_x: isize = if ray.direction.x > 0.0 { 1 } else { -1 };
+        let step_z: isize = if ray.direction.z > 0.0 { 1 } else { -1 };
+        let delta_x = (dx / ray.direction.x).abs();
+        let delta_z = (dz / ray.direction.z).abs();
+
+        let boundary_x = self.min.x + (x + usize::from(step_x > 0)) as f64 * dx;
+        let boundary_z = self.min.z + (z + usize::from(step_z > 0)) as f64 * dz;
+        let mut next_x = if ray.direction.x != 0.0 {
+            (boundary_x - ray.origin.x) / ray.direction.x
+        } else {
+            f64::INFINITY
+        };
+        let mut next_z = if ray.direction.z != 0.0 {
+            (boundary_z - ray.origin.z) / ray.direction.z
+        } else {
+            f64::INFINITY
+        };
+
+        let mut t = t_enter;
+        loop {
+            let t_leave = next_x.min(next_z).min(t_exit);
+
+            let y0 = ray.origin.y + ray.direction.y * t;
+            let y1 = ray.origin.y + ray.direction.y * t_leave;
+            let corners = [self.height(x, z), self.height(x + 1, z), self.height(x, z + 1), self.height(x + 1, z + 1)];
+            let cell_low = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+            let cell_high = corners.iter().cloned().fold(-f64::INFINITY, f64::max);
+
+            if y0.min(y1) <= cell_high && y0.max(y1) >= cell_low {
+                if let Some(hit) = self.intersect_cell(ray, x, z) {
+                    return Some(hit);
+                }
+            }
+
+            if t_leave >= t_exit {
+                return None;
+            }
+
+            if next_x < next_z {
+                if (step_x < 0 && x == 0) || (step_x > 0 && x + 1 >= nx) {
+                    return None;
+                }
+                x = (x as isize + step_x) as usize;
+                t = next_x;
+                next_x += delta_x;
+            } else {
+                if (step_z < 0 && z == 0) || (step_z > 0 && z + 1 >= nz) {
+                    return None;
+                }
+                z = (z as isize + step_z) as usize;
+                t = next_z;
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    /// Barycentric blend of the vertex normals of the triangle containing the hit point.
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        let (x, z, u, w) = self.locate(hit_point);
+
+        let n00 = self.vertex_normal(x, z);
+        let n11 = self.vertex_normal(x + 1, z + 1);
+
+        let normal = if u >= w {
+            n00 * (1.0 - u) + self.vertex_normal(x + 1, z) * (u - w) + n11 * w
+        } else {
+            n00 * (1.0 - w) + self.vertex_normal(x, z + 1) * (w - u) + n11 * u
+        };
+
+        normal.normalize()
+    }
+
+    fn uv(&self, hit_point: Vec3) -> (f64, f64) {
+        (
+            ((hit_point.x - self.min.x) / self.size.x).clamp(0.0, 1.0),
+            ((hit_point.z - self.min.z) / self.size.z).clamp(0.0, 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::shapes::heightfield::Heightfield;
+    use crate::vector::Vec3;
+    use ndarray::Array2;
+
+    fn ramp() -> Heightfield {
+        let heights = Array2::from_shape_fn((5, 5), |(_, x)| x as f64 / 4.0);
+        Heightfield::from_array(heights, Vec3::zero(), Vec3::new(4.0, 4.0, 4.0), Material::neutral()).unwrap()
+    }
+
+    #[test]
+    fn test_ramp_hit() {
+        let field = ramp();
+        let ray = Ray {
+            origin: Vec3::new(2.5, 10.0, 1.5),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let t = field.intersect(ray).unwrap();
+        assert!((t - 7.5).abs() < 1e-9);
+
+        let normal = field.normal(ray.origin + ray.direction * t);
+        let expected = Vec3::new(-1.0, 1.0, 0.0).normalize();
+        assert!((normal - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_grazing_miss() {
+        let field = ramp();
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 4.5, 2.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(field.intersect(ray).is_none());
+    }
+
+    #[test]
+    fn test_image_too_small() {
+        let path = std::env::temp_dir().join(format!("rusty_tracer_heightfield_{}.png", std::process::id()));
+        image::GrayImage::new(1, 3).save(&path).unwrap();
+        let result = Heightfield::from_image(&path, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), Material::neutral());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_array_too_small() {
+        let heights = Array2::zeros((1, 4));
+        assert!(Heightfield::from_array(heights, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), Material::neutral()).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::bvh::{Bounds, Bvh};
+use crate::color::Color;
+use crate::export::{ExportOptions, Tessellation};
+use crate::shapes::Shape;
+use crate::shapes::triangle::intersect_triangle;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::SurfaceTextures;
+use crate::vector::Vec3;
+
+/// A triangle mesh sharing vertices between its faces, with a BVH over the
+/// faces. Faces wind counter-clockwise seen from the front. Vertex normals give
+/// smooth shading, vertex uvs texture coordinates, and vertex colors and
+/// textures vary the material when there are any.
+#[derive(Debug)]
+pub struct Mesh {
+    pub material: Material,
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    colors: Vec<Color>,
+    textures: SurfaceTextures,
+    triangles: Vec<[usize; 3]>,
+    bvh: Bvh,
+    /// Running total of the face areas, to pick faces by area with.
+    areas: Vec<f64>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3>, triangles: Vec<[usize; 3]>, material: Material) -> Mesh {
+        assert!(
+            triangles.iter().flatten().all(|index| *index < vertices.len()),
+            "a mesh face refers to a vertex it does not have"
+        );
+
+        let bounds: Vec<Bounds> = triangles
+            .iter()
+            .map(|[a, b, c]| Bounds::from_points(&[vertices[*a], vertices[*b], vertices[*c]]))
+            .collect();
+        let areas = triangles
+            .iter()
+            .scan(0.0, |total, [a, b, c]| {
+                *total += (vertices[*b] - vertices[*a]).cross(vertices[*c] - vertices[*a]).length() / 2.0;
+                Some(*total)
+            })
+            .collect();
+
+        Mesh {
+            material,
+            bvh: Bvh::build(&bounds),
+            vertices,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            textures: SurfaceTextures::default(),
+            triangles,
+            areas,
+        }
+    }
+
+    /// Shades the mesh smoothly with one normal per vertex.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Mesh {
+        assert_eq!(normals.len(), self.vertices.len(), "a mesh needs one normal per vertex");
+        self.normals = normals.into_iter().map(|normal| normal.normalize()).collect();
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Mesh {
+        assert_eq!(uvs.len(), self.vertices.len(), "a mesh needs one uv per vertex");
+        self.uvs = uvs;
+        self
+    }
+
+    /// Tints the mesh with one color per vertex, see `Material::tinted`.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Mesh {
+        assert_eq!(colors.len(), self.vertices.len(), "a mesh needs one color per vertex");
+        self.colors = colors;
+        self
+    }
+
+    /// Textures the mesh, looked up by its uvs.
+    pub fn with_textures(mut self, textures: SurfaceTextures) -> Mesh {
+        self.textures = textures;
+        self
+    }
+
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[(f64, f64)] {
+        &self.uvs
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    fn corners(&self, face: usize) -> (Vec3, Vec3, Vec3) {
+        let [a, b, c] = self.triangles[face];
+        (self.vertices[a], self.vertices[b], self.vertices[c])
+    }
+
+    fn face_normal(&self, face: usize) -> Vec3 {
+        let (a, b, c) = self.corners(face);
+        (b - a).cross(c - a).normalize()
+    }
+
+    /// The face a hit point lies on and its barycentric coordinates there, the
+    /// weights of the face's second and third vertex.
+    fn locate(&self, point: Vec3) -> Option<(usize, f64, f64)> {
+        let mut best: Option<(usize, f64, f64, f64)> = None;
+
+        self.bvh.for_each_containing(point, 1e-6, |face| {
+            let (a, b, c) = self.corners(face);
+            let ab = b - a;
+            let ac = c - a;
+            let ap = point - a;
+
+            let d00 = ab.dot(ab);
+            let d01 = ab.dot(ac);
+            let d11 = ac.dot(ac);
+            let denom = d00 * d11 - d01 * d01;
+            if denom <= 0.0 {
+                return;
+            }
+            let v = (d11 * ap.dot(ab) - d01 * ap.dot(ac)) / denom;
+            let w = (d00 * ap.dot(ac) - d01 * ap.dot(ab)) / denom;
+
+            // Distance off the face's plane, plus how far outside its edges.
+            let outside = (-v).max(-w).max(v + w - 1.0).max(0.0);
+            let error = ap.dot(ab.cross(ac).normalize()).abs() + outside * d00.max(d11).sqrt();
+
+            if best.is_none_or(|(_, _, _, best_error)| error < best_error) {
+                best = Some((face, v, w, error));
+            }
+        });
+
+        best.map(|(face, v, w, _)| (face, v.clamp(0.0, 1.0), w.clamp(0.0, 1.0)))
+    }
+
+    /// Vertex uvs blended across a face, or the barycentric coordinates.
+    fn blend_uv(&self, face: usize, v: f64, w: f64) -> (f64, f64) {
+        if self.uvs.is_empty() {
+            return (v, w);
+        }
+
+        let [a, b, c] = self.triangles[face];
+        let u = 1.0 - v - w;
+        (
+            self.uvs[a].0 * u + self.uvs[b].0 * v + self.uvs[c].0 * w,
+            self.uvs[a].1 * u + self.uvs[b].1 * v + self.uvs[c].1 * w,
+        )
+    }
+}
+
+impl Shape for Mesh {
+    fn intersect(&self, ray: Ray) -> Option<f64> {
+        self.bvh
+            .intersect(ray, |face| {
+                let (a, b, c) = self.corners(face);
+                intersect_triangle(ray, a, b, c)
+            })
+            .0
+            .map(|(_, distance)| distance)
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    /// Vertex normals blended across the hit face, or the face's own normal.
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        let (face, v, w) = match self.locate(hit_point) {
+            Some(found) => found,
+            None => return Vec3::new(0.0, 1.0, 0.0),
+        };
+
+        if self.normals.is_empty() {
+            return self.face_normal(face);
+        }
+
+        let [a, b, c] = self.triangles[face];
+        (self.normals[a] * (1.0 - v - w) + self.normals[b] * v + self.normals[c] * w).normalize()
+    }
+
+    fn uv(&self, hit_point: Vec3) -> (f64, f64) {
+        match self.locate(hit_point) {
+            Some((face, v, w)) => self.blend_uv(face, v, w),
+            None => (0.0, 0.0),
+        }
+    }
+
+    /// The material tinted by the vertex colors blended across the hit face,
+    /// then textured.
+    fn material_at(&self, hit_point: Vec3, _time: f64) -> Material {
+        if self.colors.is_empty() && self.textures.is_empty() {
+            return self.material;
+        }
+        let (face, v, w) = match self.locate(hit_point) {
+            Some(found) => found,
+            None => return self.material,
+        };
+
+        let material = if self.colors.is_empty() {
+            self.material
+        } else {
+            let [a, b, c] = self.triangles[face];
+            self.material.tinted(self.colors[a] * (1.0 - v - w) + self.colors[b] * v + self.colors[c] * w)
+        };
+        self.textures.apply(material, self.blend_uv(face, v, w))
+    }
+
+    fn area(&self) -> f64 {
+        self.areas.last().cloned().unwrap_or(0.0)
+    }
+
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(Vec3, Vec3)> {
+        let target = sampler.next_f64() * self.area();
+        let face = self.areas.partition_point(|total| *total <= target).min(self.triangles.len().checked_sub(1)?);
+        let (a, b, c) = self.corners(face);
+
+        let u = sampler.next_f64().sqrt();
+        let v = sampler.next_f64();
+        let point = a * (1.0 - u) + b * (u * (1.0 - v)) + c * (u * v);
+        Some((point, self.face_normal(face)))
+    }
+
+    fn traversal_cost(&self, ray: Ray) -> u32 {
+        self.bvh
+            .intersect(ray, |face| {
+                let (a, b, c) = self.corners(face);
+                intersect_triangle(ray, a, b, c)
+            })
+            .1
+    }
+
+    /// The mesh as it is, but with the corners of faces split apart where it
+    /// has no vertex normals, to keep them flat.
+    fn tessellate(&self, _options: &ExportOptions) -> Option<Tessellation> {
+        if !self.normals.is_empty() {
+            return Some(Tessellation {
+                vertices: self.vertices.clone(),
+                normals: self.normals.clone(),
+                uvs: self.uvs.clone(),
+                triangles: self.triangles.clone(),
+                material: self.material,
+            });
+        }
+
+        let mut tessellation = Tessellation {
+            vertices: Vec::with_capacity(self.triangles.len() * 3),
+            normals: Vec::with_capacity(self.triangles.len() * 3),
+            uvs: Vec::new(),
+            triangles: Vec::with_capacity(self.triangles.len()),
+            material: self.material,
+        };
+        for (face, corners) in self.triangles.iter().enumerate() {
+            // Faces without area have no normal, nor anything to show.
+            let normal = self.face_normal(face);
+            if !normal.length().is_finite() {
+                continue;
+            }
+
+            let start = tessellation.vertices.len();
+            for corner in corners {
+                tessellation.vertices.push(self.vertices[*corner]);
+                tessellation.normals.push(normal);
+                if !self.uvs.is_empty() {
+                    tessellation.uvs.push(self.uvs[*corner]);
+                }
+            }
+            tessellation.triangles.push([start, start + 1, start + 2]);
+        }
+        Some(tessellation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::shapes::mesh::Mesh;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_quad() {
+        // A unit square in the xz plane facing up, as two faces.
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ];
+        let mesh = Mesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]], Material::neutral())
+            .with_uvs(vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]);
+
+        let ray = Ray {
+            origin: Vec3::new(0.75, 2.0, 0.25),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let t = mesh.intersect(ray).unwrap();
+        assert!((t - 2.0).abs() < 1e-9);
+
+        let hit = ray.origin + ray.direction * t;
+        assert!((mesh.normal(hit) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        let (u, v) = mesh.uv(hit);
+        assert!((u - 0.75).abs() < 1e-9 && (v - 0.25).abs() < 1e-9);
+        assert!((mesh.area() - 1.0).abs() < 1e-9);
+    }
+}
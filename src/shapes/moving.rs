@@ -0,0 +1,137 @@
+use crate::shapes::transformed::local_ray;
+use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::motion::Motion;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+/// Wraps a shape and moves, turns and scales it over the camera's shutter
+/// interval. The wrapped shape is given in its own space, around the origin
+/// its motion turns and scales it about.
+#[derive(Debug)]
+pub struct Moving {
+    pub shape: Box<dyn Shape>,
+    pub motion: Motion,
+}
+
+impl Moving {
+    /// From world space to the shape's space at `time`, or `None` while the
+    /// shape is scaled flat.
+    fn to_local(&self, time: f64) -> Option<Mat4> {
+        self.motion.transform(time).matrix().inverse()
+    }
+}
+
+impl Shape for Moving {
+    fn intersect(&self, world_ray: Ray) -> Option<f64> {
+        let (ray, stretch) = local_ray(&self.to_local(world_ray.time)?, world_ray);
+        self.shape.intersect(ray).map(|distance| distance / stretch)
+    }
+
+    fn material(&self) -> Material {
+        self.shape.material()
+    }
+
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        self.normal_at(hit_point, 0.0)
+    }
+
+    fn normal_at(&self, hit_point: Vec3, time: f64) -> Vec3 {
+        match self.to_local(time) {
+            Some(to_local) => {
+                let normal = self.shape.normal_at(to_local.transform_point(hit_point), time);
+                to_local.transpose().transform_vector(normal).normalize()
+            }
+            None => self.shape.normal_at(hit_point, time),
+        }
+    }
+
+    fn uv_at(&self, hit_point: Vec3, time: f64) -> (f64, f64) {
+        match self.to_local(time) {
+            Some(to_local) => self.shape.uv_at(to_local.transform_point(hit_point), time),
+            None => (0.0, 0.0),
+        }
+    }
+
+    fn material_at(&self, hit_point: Vec3, time: f64) -> Material {
+        match self.to_local(time) {
+            Some(to_local) => self.shape.material_at(to_local.transform_point(hit_point), time),
+            None => self.shape.material(),
+        }
+    }
+
+    /// The wrapped shape where it is when the shutter opens.
+    fn tessellate(&self, options: &ExportOptions) -> Option<Tessellation> {
+        let to_world = self.motion.transform(0.0).matrix();
+        let normals = to_world.inverse()?.transpose();
+        let tessellation = self.shape.tessellate(options)?;
+        Some(tessellation.transformed(|p| to_world.transform_point(p), |n| normals.transform_vector(n)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::animation::{Curve, Track};
+    use crate::material::Material;
+    use crate::motion::{Motion, Transform};
+    use crate::ray::Ray;
+    use crate::shapes::aabb::Aabb;
+    use crate::shapes::moving::Moving;
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    fn ray_at(x: f64, y: f64, time: f64) -> Ray {
+        Ray {
+            origin: Vec3::new(x, y, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time,
+        }
+    }
+
+    #[test]
+    fn test_motion() {
+        // A unit sphere moving 4 along x while doubling in size.
+        let sphere = Moving {
+            shape: Box::new(Sphere {
+                position: Vec3::zero(),
+                radius: 1.0,
+                material: Material::neutral(),
+            }),
+            motion: Motion::Linear {
+                start: Transform::identity(),
+                end: Transform {
+                    scale: Vec3::new(2.0, 2.0, 2.0),
+                    ..Transform::translation(Vec3::new(4.0, 0.0, 0.0))
+                },
+                t0: 0.0,
+                t1: 1.0,
+            },
+        };
+        assert!((sphere.intersect(ray_at(0.0, 0.0, 0.0)).unwrap() - 4.0).abs() < 1e-9);
+        assert!(sphere.intersect(ray_at(4.0, 0.0, 0.0)).is_none());
+        assert!((sphere.intersect(ray_at(2.0, 0.0, 0.5)).unwrap() - 3.5).abs() < 1e-9);
+        assert!((sphere.intersect(ray_at(4.0, 0.0, 1.0)).unwrap() - 3.0).abs() < 1e-9);
+        let normal = sphere.normal_at(Vec3::new(4.0, 0.0, 2.0), 1.0);
+        assert!((normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        // A bar along x turning a quarter about z to lie along y.
+        let bar = Moving {
+            shape: Box::new(Aabb {
+                min: Vec3::new(0.0, -0.5, -0.5),
+                max: Vec3::new(2.0, 0.5, 0.5),
+                material: Material::neutral(),
+            }),
+            motion: Motion::Keyframed(
+                Track::new(0.0, Transform::identity(), Curve::Linear)
+                    .key(1.0, Transform::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0), Curve::Linear),
+            ),
+        };
+        assert!((bar.intersect(ray_at(1.5, 0.0, 0.0)).unwrap() - 4.5).abs() < 1e-9);
+        assert!(bar.intersect(ray_at(0.0, 1.5, 0.0)).is_none());
+        assert!(bar.intersect(ray_at(1.5, 0.0, 1.0)).is_none());
+        assert!((bar.intersect(ray_at(0.0, 1.5, 1.0)).unwrap() - 4.5).abs() < 1e-9);
+    }
+}
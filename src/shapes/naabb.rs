@@ -1,4 +1,5 @@
 use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vector::Vec3;
@@ -18,6 +19,7 @@ impl Shape for Naabb {
         let ray = Ray {
             origin: self.rotation.apply(&world_ray.origin),
             direction: self.rotation.apply(&world_ray.direction),
+            time: world_ray.time,
         };
 
         let mut tmin = -f64::INFINITY;
@@ -81,4 +83,9 @@ impl Shape for Naabb {
 
         self.rotation.invert(&ret)
     }
+
+    fn tessellate(&self, _options: &ExportOptions) -> Option<Tessellation> {
+        let rotation = &self.rotation;
+        Some(Tessellation::cuboid(self.min, self.max, self.material).transformed(|p| rotation.invert(&p), |n| rotation.invert(&n)))
+    }
 }
\ No newline at end of file
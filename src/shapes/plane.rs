@@ -1,4 +1,5 @@
 use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vector::Vec3;
@@ -36,4 +37,30 @@ impl Shape for Plane {
     fn normal(&self, _point: Vec3) -> Vec3 {
         -self.normal
     }
+
+    /// The half-space behind the visible side.
+    fn contains(&self, point: Vec3) -> Option<bool> {
+        Some((point - self.position).dot(self.normal) > 0.0)
+    }
+
+    /// A square of side `options.plane_size` around the plane's position.
+    fn tessellate(&self, options: &ExportOptions) -> Option<Tessellation> {
+        let normal = -self.normal.normalize();
+        let axis = if normal.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+        let tangent = axis.cross(normal).normalize() * (options.plane_size / 2.0);
+        let bitangent = normal.cross(tangent);
+
+        Some(Tessellation {
+            vertices: vec![
+                self.position - tangent - bitangent,
+                self.position + tangent - bitangent,
+                self.position + tangent + bitangent,
+                self.position - tangent + bitangent,
+            ],
+            normals: vec![normal; 4],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+            material: self.material,
+        })
+    }
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector::Vec3;
 
+use std::f64::consts::PI;
+
 #[derive(Debug)]
 pub struct Sphere {
     pub position: Vec3,
@@ -46,4 +50,59 @@ impl Shape for Sphere {
     fn normal(&self, point: Vec3) -> Vec3 {
         (point - self.position).normalize()
     }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(Vec3, Vec3)> {
+        let z = 1.0 - 2.0 * sampler.next_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.next_f64();
+        let normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+        Some((self.position + normal * self.radius, normal))
+    }
+
+    fn contains(&self, point: Vec3) -> Option<bool> {
+        Some((point - self.position).length() <= self.radius)
+    }
+
+    /// A globe of `options.segments` meridians and half as many parallels.
+    fn tessellate(&self, options: &ExportOptions) -> Option<Tessellation> {
+        let segments = options.segments.max(3) as usize;
+        let rings = (segments / 2).max(2);
+        let mut tessellation = Tessellation {
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            triangles: Vec::new(),
+            material: self.material,
+        };
+
+        // The seam gets two columns of vertices, to wrap the uvs around once.
+        for i in 0..=rings {
+            let theta = PI * i as f64 / rings as f64;
+            for j in 0..=segments {
+                let phi = 2.0 * PI * j as f64 / segments as f64;
+                let normal = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                tessellation.vertices.push(self.position + normal * self.radius);
+                tessellation.normals.push(normal);
+                tessellation.uvs.push((j as f64 / segments as f64, i as f64 / rings as f64));
+            }
+        }
+
+        let index = |i: usize, j: usize| i * (segments + 1) + j;
+        for i in 0..rings {
+            for j in 0..segments {
+                let (a, b, c, d) = (index(i, j), index(i + 1, j), index(i + 1, j + 1), index(i, j + 1));
+                if i > 0 {
+                    tessellation.triangles.push([a, d, c]);
+                }
+                if i + 1 < rings {
+                    tessellation.triangles.push([a, c, b]);
+                }
+            }
+        }
+        Some(tessellation)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+/// Wraps a shape and places it by an affine transform, for transforms the
+/// shape cannot take on by itself, such as a sphere stretched into an
+/// ellipsoid or a box turned at an angle.
+#[derive(Debug)]
+pub struct Transformed {
+    shape: Box<dyn Shape>,
+    to_world: Mat4,
+    to_local: Mat4,
+    /// Inverse transpose of `to_world`, which carries normals.
+    normals: Mat4,
+}
+
+impl Transformed {
+    /// `None` if `transform` flattens space and cannot be inverted.
+    pub fn new(shape: Box<dyn Shape>, transform: Mat4) -> Option<Transformed> {
+        let to_local = transform.inverse()?;
+        Some(Transformed {
+            shape,
+            to_world: transform,
+            normals: to_local.transpose(),
+            to_local,
+        })
+    }
+
+    fn local_point(&self, point: Vec3) -> Vec3 {
+        self.to_local.transform_point(point)
+    }
+}
+
+/// The ray in the space `to_local` leads to, with a unit direction, and how
+/// much longer distances are there.
+pub(crate) fn local_ray(to_local: &Mat4, world_ray: Ray) -> (Ray, f64) {
+    let direction = to_local.transform_vector(world_ray.direction);
+    let stretch = direction.length();
+    let ray = Ray {
+        origin: to_local.transform_point(world_ray.origin),
+        direction: direction / stretch,
+        time: world_ray.time,
+    };
+    (ray, stretch)
+}
+
+impl Shape for Transformed {
+    fn intersect(&self, world_ray: Ray) -> Option<f64> {
+        let (ray, stretch) = local_ray(&self.to_local, world_ray);
+        self.shape.intersect(ray).map(|distance| distance / stretch)
+    }
+
+    fn material(&self) -> Material {
+        self.shape.material()
+    }
+
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        self.normal_at(hit_point, 0.0)
+    }
+
+    fn uv(&self, hit_point: Vec3) -> (f64, f64) {
+        self.uv_at(hit_point, 0.0)
+    }
+
+    fn normal_at(&self, hit_point: Vec3, time: f64) -> Vec3 {
+        let normal = self.shape.normal_at(self.local_point(hit_point), time);
+        self.normals.transform_vector(normal).normalize()
+    }
+
+    fn uv_at(&self, hit_point: Vec3, time: f64) -> (f64, f64) {
+        self.shape.uv_at(self.local_point(hit_point), time)
+    }
+
+    fn material_at(&self, hit_point: Vec3, time: f64) -> Material {
+        self.shape.material_at(self.local_point(hit_point), time)
+    }
+
+    fn traversal_cost(&self, world_ray: Ray) -> u32 {
+        self.shape.traversal_cost(local_ray(&self.to_local, world_ray).0)
+    }
+
+    fn contains(&self, point: Vec3) -> Option<bool> {
+        self.shape.contains(self.local_point(point))
+    }
+
+    fn tessellate(&self, options: &ExportOptions) -> Option<Tessellation> {
+        let tessellation = self.shape.tessellate(options)?;
+        Some(tessellation.transformed(|p| self.to_world.transform_point(p), |n| self.normals.transform_vector(n)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::Material;
+    use crate::matrix::Mat4;
+    use crate::ray::Ray;
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::transformed::Transformed;
+    use crate::shapes::Shape;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn test_ellipsoid() {
+        // A unit sphere stretched to 3 along x and moved to (0, 0, -10).
+        let transform = Mat4::translation(Vec3::new(0.0, 0.0, -10.0)) * Mat4::scaling(Vec3::new(3.0, 1.0, 1.0));
+        let sphere = Sphere {
+            position: Vec3::zero(),
+            radius: 1.0,
+            material: Material::neutral(),
+        };
+        let ellipsoid = Transformed::new(Box::new(sphere), transform).unwrap();
+
+        let ray = Ray {
+            origin: Vec3::new(10.0, 0.0, -10.0),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!((ellipsoid.intersect(ray).unwrap() - 7.0).abs() < 1e-9);
+        assert!((ellipsoid.normal(Vec3::new(3.0, 0.0, -10.0)) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert_eq!(ellipsoid.contains(Vec3::new(2.5, 0.0, -10.0)), Some(true));
+        assert_eq!(ellipsoid.contains(Vec3::new(0.0, 1.5, -10.0)), Some(false));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::shapes::Shape;
+use crate::export::{ExportOptions, Tessellation};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector::Vec3;
 
 #[derive(Debug)]
@@ -43,4 +45,58 @@ impl Shape for Triangle {
         let ac = self.c - self.a;
         -ab.cross(ac).normalize()
     }
-}
\ No newline at end of file
+
+    fn area(&self) -> f64 {
+        (self.b - self.a).cross(self.c - self.a).length() / 2.0
+    }
+
+    fn sample_surface(&self, sampler: &mut Sampler) -> Option<(Vec3, Vec3)> {
+        let u = sampler.next_f64().sqrt();
+        let v = sampler.next_f64();
+        let point = self.a * (1.0 - u) + self.b * (u * (1.0 - v)) + self.c * (u * v);
+        Some((point, self.normal(point)))
+    }
+
+    /// The triangle wound to face the way of its normal.
+    fn tessellate(&self, _options: &ExportOptions) -> Option<Tessellation> {
+        Some(Tessellation {
+            vertices: vec![self.a, self.b, self.c],
+            normals: vec![self.normal(self.a); 3],
+            uvs: Vec::new(),
+            triangles: vec![[0, 2, 1]],
+            material: self.material,
+        })
+    }
+}
+
+/// Möller–Trumbore ray/triangle test, two sided.
+pub(crate) fn intersect_triangle(ray: Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<f64> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(ac);
+    let det = ab.dot(p);
+
+    if det.abs() < crate::EPSILON {
+        return None;
+    }
+
+    let inv_det = det.recip();
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(ab);
+    let v = ray.direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = ac.dot(q) * inv_det;
+    if t > crate::EPSILON {
+        Some(t)
+    } else {
+        None
+    }
+}
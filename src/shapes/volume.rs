@@ -0,0 +1,319 @@
+use crate::color::Color;
+use crate::shapes::Shape;
+use crate::material::Material;
+use crate::medium::{DensityField, Medium};
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+use ndarray::Array3;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Temperature in kelvin below which fire gives off no visible light.
+const DRAPER_POINT: f64 = 798.0;
+
+/// How the samples of a raw grid file are stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RawFormat {
+    /// One byte per sample, 255 being a density of 1.
+    U8,
+    /// Little endian 32 bit floats.
+    F32,
+}
+
+/// Reads a grid of `dims` samples along x, y and z from a headerless file,
+/// x running fastest and z slowest. The grid is indexed `[z, y, x]`. Negative
+/// samples are clamped to 0, and files holding NaN or infinite ones rejected.
+pub fn read_raw<P: AsRef<Path>>(path: P, dims: (usize, usize, usize), format: RawFormat) -> io::Result<Array3<f64>> {
+    let (nx, ny, nz) = dims;
+    let size = match format {
+        RawFormat::U8 => 1,
+        RawFormat::F32 => 4,
+    };
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let expected = nx
+        .checked_mul(ny)
+        .and_then(|samples| samples.checked_mul(nz))
+        .and_then(|samples| samples.checked_mul(size))
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(|| invalid(format!("a {}x{}x{} grid cannot be read", nx, ny, nz)))?;
+
+    let bytes = fs::read(path)?;
+    if bytes.len() != expected {
+        return Err(invalid(format!(
+            "expected {} bytes for a {}x{}x{} grid, found {}",
+            expected,
+            nx,
+            ny,
+            nz,
+            bytes.len()
+        )));
+    }
+
+    let values: Vec<f64> = match format {
+        RawFormat::U8 => bytes.iter().map(|byte| f64::from(*byte) / 255.0).collect(),
+        RawFormat::F32 => bytes
+            .chunks_exact(4)
+            .map(|chunk| f64::from(f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])))
+            .collect(),
+    };
+    if let Some(index) = values.iter().position(|value| !value.is_finite()) {
+        return Err(invalid(format!("sample {} is not a finite number", index)));
+    }
+    let values = values.into_iter().map(|value| value.max(0.0)).collect();
+
+    Array3::from_shape_vec((nz, ny, nx), values).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+}
+
+/// Smoke, clouds or fire filling the box from `min` to `min + size`, with the
+/// density of its medium given by a grid of samples at the centers of equal
+/// cells. Densities are blended trilinearly between the samples. Fire gets a
+/// second grid of temperatures, glowing in the color of a black body.
+#[derive(Debug)]
+pub struct GridVolume {
+    pub min: Vec3,
+    pub size: Vec3,
+    pub material: Material,
+    densities: Array3<f64>,
+    max_density: f64,
+    temperatures: Option<Array3<f64>>,
+    /// Light given off per unit of distance by fire at 1000 K.
+    emission: f64,
+}
+
+impl GridVolume {
+    /// A volume of `medium` scaled by `densities`, indexed `[z, y, x]`, with
+    /// negative densities clamped to 0. Panics unless the grid has a sample
+    /// along every axis and all of them are numbers.
+    pub fn new(mut densities: Array3<f64>, min: Vec3, size: Vec3, medium: Medium) -> GridVolume {
+        assert!(!densities.is_empty(), "a grid volume needs at least one sample along each axis");
+        assert!(!densities.iter().any(|density| density.is_nan()), "grid volume densities must not be NaN");
+        densities.mapv_inplace(|density| density.max(0.0));
+        let max_density = densities.iter().cloned().fold(0.0, f64::max);
+
+        GridVolume {
+            min,
+            size,
+            material: Material {
+                medium: Some(medium),
+                ..Material::neutral()
+            },
+            densities,
+            max_density,
+            temperatures: None,
+            emission: 0.0,
+        }
+    }
+
+    /// Makes the volume glow where `temperatures` in kelvin, laid out like the
+    /// densities, are hot enough. Brightness grows with the fourth power of
+    /// the temperature, `emission` being the light given off at 1000 K.
+    pub fn with_temperatures(mut self, temperatures: Array3<f64>, emission: f64) -> GridVolume {
+        assert!(!temperatures.is_empty(), "a grid of temperatures needs at least one sample along each axis");
+        self.temperatures = Some(temperatures);
+        self.emission = emission;
+        self
+    }
+
+    fn max(&self) -> Vec3 {
+        self.min + self.size
+    }
+
+    /// Trilinear blend of `grid` at a world point, clamped to the border samples.
+    fn sample(&self, grid: &Array3<f64>, point: Vec3) -> f64 {
+        let (nz, ny, nx) = grid.dim();
+        let local = (point - self.min) * self.size.inv();
+
+        let axis = |f: f64, n: usize| {
+            let f = (f * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (f.floor() as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), f - i as f64)
+        };
+        let (x0, x1, fx) = axis(local.x, nx);
+        let (y0, y1, fy) = axis(local.y, ny);
+        let (z0, z1, fz) = axis(local.z, nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let row = |y: usize, z: usize| lerp(grid[[z, y, x0]], grid[[z, y, x1]], fx);
+        let slice = |z: usize| lerp(row(y0, z), row(y1, z), fy);
+
+        lerp(slice(z0), slice(z1), fz)
+    }
+}
+
+impl Shape for GridVolume {
+    /// The box's near side, or its far side for rays starting inside it.
+    fn intersect(&self, ray: Ray) -> Option<f64> {
+        let inv_dir = 1.0 / ray.direction;
+        let t0s = (self.min - ray.origin) * inv_dir;
+        let t1s = (self.max() - ray.origin) * inv_dir;
+
+        let tmin = t0s.x.min(t1s.x).max(t0s.y.min(t1s.y)).max(t0s.z.min(t1s.z));
+        let tmax = t0s.x.max(t1s.x).min(t0s.y.max(t1s.y)).min(t0s.z.max(t1s.z));
+
+        if tmin > tmax || tmax <= crate::EPSILON {
+            None
+        } else if tmin > crate::EPSILON {
+            Some(tmin)
+        } else {
+            Some(tmax)
+        }
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    /// Outward normal of the face nearest to the hit point.
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        let center = self.min + self.size * 0.5;
+        let local = (hit_point - center) * (self.size * 0.5).inv();
+
+        if local.x.abs() >= local.y.abs() && local.x.abs() >= local.z.abs() {
+            Vec3::new(local.x.signum(), 0.0, 0.0)
+        } else if local.y.abs() >= local.z.abs() {
+            Vec3::new(0.0, local.y.signum(), 0.0)
+        } else {
+            Vec3::new(0.0, 0.0, local.z.signum())
+        }
+    }
+
+    fn density_field(&self) -> Option<&dyn DensityField> {
+        Some(self)
+    }
+}
+
+impl DensityField for GridVolume {
+    fn density(&self, point: Vec3) -> f64 {
+        self.sample(&self.densities, point)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    fn emission(&self, point: Vec3) -> Color {
+        let temperatures = match &self.temperatures {
+            Some(temperatures) => temperatures,
+            None => return Color::black(),
+        };
+
+        let kelvin = self.sample(temperatures, point);
+        if kelvin < DRAPER_POINT {
+            return Color::black();
+        }
+        Color::blackbody(kelvin) * (self.emission * (kelvin / 1000.0).powi(4))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::Cfg;
+    use crate::color::Color;
+    use crate::medium::{DensityField, Heterogeneous, Medium};
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::shapes::Shape;
+    use crate::shapes::volume::{read_raw, GridVolume, RawFormat};
+    use crate::vector::Vec3;
+    use ndarray::Array3;
+
+    #[test]
+    fn test_grid_volume() {
+        // Density rising from 0 to 1 along x across a unit box.
+        let densities = Array3::from_shape_fn((2, 2, 2), |(_, _, x)| x as f64);
+        let medium = Medium {
+            absorption: Color::white(),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        };
+        let volume = GridVolume::new(densities, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), medium);
+        assert!((volume.density(Vec3::new(0.5, 0.3, 0.9)) - 0.5).abs() < 1e-9);
+        assert!((volume.density(Vec3::new(0.1, 0.5, 0.5))).abs() < 1e-9);
+
+        // A ray starting inside leaves through the far face.
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.5, 0.5),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!((volume.intersect(ray).unwrap() - 1.0).abs() < 1e-9);
+        assert!((volume.normal(Vec3::new(1.0, 0.5, 0.5)) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+
+        // Ratio tracking averages to exp(-integral of the density), which
+        // ramps from 0 to 1 across the middle half of the box and stays at 1.
+        let tracked = Heterogeneous { medium, field: &volume };
+        let mut sampler = Sampler::new(1);
+        let runs = 20000;
+        let mean: f64 = (0..runs)
+            .map(|_| tracked.ratio_tracking(ray, 1.0, &mut sampler).channels()[0])
+            .sum::<f64>()
+            / f64::from(runs);
+        assert!((mean - (-0.5f64).exp()).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_delta_tracking() {
+        // In an even density, rays get through with probability exp(-σ·d).
+        let medium = Medium {
+            absorption: Color::white() * 0.7,
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        };
+        let densities = Array3::from_elem((2, 2, 2), 1.0);
+        let volume = GridVolume::new(densities, Vec3::zero(), Vec3::new(2.0, 2.0, 2.0), medium);
+        let tracked = Heterogeneous { medium, field: &volume };
+        let ray = Ray {
+            origin: Vec3::new(0.0, 1.0, 1.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+
+        let mut sampler = Sampler::new(3);
+        let runs = 20000;
+        let through = (0..runs)
+            .filter(|_| tracked.delta_tracking(ray, 1.5, &[], &[], &Cfg::default(), &mut sampler).1)
+            .count();
+        let probability = through as f64 / f64::from(runs);
+        assert!((probability - (-0.7f64 * 1.5).exp()).abs() < 1.5e-2);
+    }
+
+    #[test]
+    fn test_negative_densities() {
+        let medium = Medium {
+            absorption: Color::white(),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        };
+        let densities = Array3::from_shape_fn((2, 2, 2), |(_, _, x)| if x == 0 { -4.0 } else { 1.0 });
+        let volume = GridVolume::new(densities, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), medium);
+        assert!(volume.density(Vec3::new(0.1, 0.5, 0.5)).abs() < 1e-9);
+        assert!((volume.density(Vec3::new(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-9);
+        assert!((volume.max_density() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_raw_grid_samples() {
+        let path = std::env::temp_dir().join(format!("rusty_tracer_volume_{}.raw", std::process::id()));
+        let write = |values: [f32; 2]| {
+            let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+            std::fs::write(&path, bytes).unwrap();
+            read_raw(&path, (2, 1, 1), RawFormat::F32)
+        };
+        let clamped = write([-2.0, 0.5]);
+        let nan = write([f32::NAN, 0.5]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(clamped.unwrap().iter().cloned().collect::<Vec<f64>>(), vec![0.0, 0.5]);
+        assert_eq!(nan.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_empty_raw_grid() {
+        // Rejected before the file is even opened.
+        let error = read_raw("missing.raw", (0, 4, 4), RawFormat::U8).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::bsdf::{Bsdf, Principled};
+use crate::color::Color;
+use crate::shapes::Shape;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A grid of cubes of `voxel_size` with their lowest corner at `min`, each
+/// either empty or filled with one of up to 255 palette materials. Rays walk
+/// the grid voxel by voxel, so large models cost little more than small ones.
+#[derive(Debug)]
+pub struct VoxelGrid {
+    pub min: Vec3,
+    pub voxel_size: f64,
+    dims: (usize, usize, usize),
+    /// Palette index of every voxel, x running fastest and z slowest. Index 0
+    /// is empty.
+    voxels: Vec<u8>,
+    palette: Vec<Material>,
+}
+
+impl VoxelGrid {
+    /// An empty grid of `dims` voxels along x, y and z. `palette` holds the
+    /// materials for the indices from 1 up, and is padded with neutral ones.
+    /// Grids must span 1 to 256 voxels along every axis, as MagicaVoxel's do.
+    pub fn new(dims: (usize, usize, usize), min: Vec3, voxel_size: f64, palette: Vec<Material>) -> io::Result<VoxelGrid> {
+        if [dims.0, dims.1, dims.2].iter().any(|n| *n == 0 || *n > 256) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "voxel grid size out of range"));
+        }
+
+        let mut materials = vec![Material::neutral()];
+        materials.extend(palette.into_iter().take(255));
+        materials.resize(256, Material::neutral());
+
+        Ok(VoxelGrid {
+            min,
+            voxel_size,
+            dims,
+            voxels: vec![0; dims.0 * dims.1 * dims.2],
+            palette: materials,
+        })
+    }
+
+    /// Loads the first model of a MagicaVoxel `.vox` file. MagicaVoxel's z up
+    /// becomes y up, its models facing the viewer down -z. The palette and
+    /// the metal, glass and emissive materials of the file carry over, while
+    /// its scene graph is ignored.
+    pub fn from_vox<P: AsRef<Path>>(path: P, min: Vec3, voxel_size: f64) -> io::Result<VoxelGrid> {
+        let bytes = fs::read(path)?;
+        let VoxFile { models, palette, materials } = VoxFile::parse(&bytes)?;
+        let VoxModel { size, voxels } = models.into_iter().next().ok_or_else(|| invalid("the file has no models"))?;
+
+        let palette: Vec<Material> = (1..256).map(|index| vox_material(palette[index], materials.get(&index))).collect();
+        let (sx, sy, sz) = size;
+        let mut grid = VoxelGrid::new((sx, sz, sy), min, voxel_size, palette)?;
+        for (x, y, z, index) in voxels {
+            if x < sx && y < sy && z < sz {
+                grid.set(x, z, sy - 1 - y, index);
+            }
+        }
+        Ok(grid)
+    }
+
+    pub fn dims(&self) -> (usize, usize, usize) {
+        self.dims
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.dims.1 + y) * self.dims.0 + x
+    }
+
+    /// Palette index of the voxel at `(x, y, z)`, 0 when it is empty.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.voxels[self.index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, index: u8) {
+        let i = self.index(x, y, z);
+        self.voxels[i] = index;
+    }
+
+    /// Palette index of a voxel given by signed coordinates, empty outside the grid.
+    fn occupied(&self, voxel: [isize; 3]) -> u8 {
+        let (nx, ny, nz) = self.dims;
+        if voxel[0] < 0 || voxel[1] < 0 || voxel[2] < 0 {
+            return 0;
+        }
+        let (x, y, z) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize);
+        if x >= nx || y >= ny || z >= nz {
+            return 0;
+        }
+        self.get(x, y, z)
+    }
+
+    fn grid_coordinates(&self, point: Vec3) -> [f64; 3] {
+        let local = (point - self.min) / self.voxel_size;
+        [local.x, local.y, local.z]
+    }
+
+    /// Walks the voxels along `ray` with the 3D DDA of Amanatides and Woo.
+    /// Returns the distance to the first face between an empty and a filled
+    /// voxel, and how many voxels were visited.
+    fn traverse(&self, ray: Ray) -> (Option<f64>, u32) {
+        let (nx, ny, nz) = self.dims;
+        let size = [nx as f64, ny as f64, nz as f64];
+        let origin = self.grid_coordinates(ray.origin);
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+        // Clip the ray to the grid's box, in voxel units.
+        let mut t_enter = 0.0f64;
+        let mut t_exit = f64::INFINITY;
+        for axis in 0..3 {
+            let inv = (direction[axis] / self.voxel_size).recip();
+            let t0 = (0.0 - origin[axis]) * inv;
+            let t1 = (size[axis] - origin[axis]) * inv;
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter > t_exit || t_exit <= 0.0 {
+            return (None, 0);
+        }
+
+        let start = ray.origin + ray.direction * t_enter;
+        let local = self.grid_coordinates(start);
+        let mut voxel = [0isize; 3];
+        let mut step = [0isize; 3];
+        let mut t_max = [f64::INFINITY; 3];
+        let mut t_delta = [f64::INFINITY; 3];
+
+        for axis in 0..3 {
+            let limit = [nx, ny, nz][axis] as isize - 1;
+            voxel[axis] = (local[axis].floor() as isize).clamp(0, limit);
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = self.voxel_size / direction[axis];
+                t_max[axis] = t_enter + ((voxel[axis] + 1) as f64 - local[axis]) * t_delta[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -self.voxel_size / direction[axis];
+                t_max[axis] = t_enter + (local[axis] - voxel[axis] as f64) * t_delta[axis];
+            }
+        }
+
+        // A ray starting inside a filled voxel, like one refracted into glass,
+        // looks for the face leading out into an empty one instead.
+        let inside = t_enter <= 0.0 && self.occupied(voxel) != 0;
+        let mut t = t_enter;
+        let mut visited = 0;
+
+        loop {
+            visited += 1;
+            let filled = self.occupied(voxel) != 0;
+            if filled != inside && t > crate::EPSILON {
+                return (Some(t), visited);
+            }
+
+            let axis = if t_max[0] < t_max[1] && t_max[0] < t_max[2] {
+                0
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            if t_max[axis] > t_exit {
+                return (None, visited);
+            }
+
+            t = t_max[axis];
+            voxel[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+
+            if voxel[axis] < 0 || voxel[axis] >= [nx, ny, nz][axis] as isize {
+                // Leaving the grid from inside a filled voxel is leaving it.
+                return if inside { (Some(t), visited) } else { (None, visited) };
+            }
+        }
+    }
+
+    /// The filled voxel a hit point lies on the face of and the face's
+    /// normal, pointing into the empty voxel next to it.
+    fn face(&self, hit_point: Vec3) -> ([isize; 3], Vec3) {
+        let local = self.grid_coordinates(hit_point);
+
+        // The face lies on the grid plane the point is closest to.
+        let mut axis = 0;
+        for candidate in 1..3 {
+            if (local[candidate] - local[candidate].round()).abs() < (local[axis] - local[axis].round()).abs() {
+                axis = candidate;
+            }
+        }
+
+        let mut below = [local[0].floor() as isize, local[1].floor() as isize, local[2].floor() as isize];
+        let plane = local[axis].round() as isize;
+        below[axis] = plane - 1;
+        let mut above = below;
+        above[axis] = plane;
+
+        let mut normal = [0.0; 3];
+        if self.occupied(below) != 0 && self.occupied(above) == 0 {
+            normal[axis] = 1.0;
+            (below, Vec3::from_slice(&normal))
+        } else {
+            normal[axis] = -1.0;
+            (above, Vec3::from_slice(&normal))
+        }
+    }
+}
+
+impl Shape for VoxelGrid {
+    fn intersect(&self, ray: Ray) -> Option<f64> {
+        self.traverse(ray).0
+    }
+
+    fn material(&self) -> Material {
+        self.palette[1]
+    }
+
+    fn material_at(&self, hit_point: Vec3, _time: f64) -> Material {
+        let (voxel, _) = self.face(hit_point);
+        self.palette[usize::from(self.occupied(voxel))]
+    }
+
+    fn normal(&self, hit_point: Vec3) -> Vec3 {
+        self.face(hit_point).1
+    }
+
+    fn traversal_cost(&self, ray: Ray) -> u32 {
+        self.traverse(ray).1
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid vox file: {}", message))
+}
+
+/// A model of a `.vox` file, along MagicaVoxel's axes.
+struct VoxModel {
+    size: (usize, usize, usize),
+    /// Coordinates and palette index of every filled voxel.
+    voxels: Vec<(usize, usize, usize, u8)>,
+}
+
+/// The parts of a `.vox` file that make up its models and their looks.
+struct VoxFile {
+    models: Vec<VoxModel>,
+    /// RGBA colors indexed by palette index.
+    palette: [[u8; 4]; 256],
+    /// Properties of the `MATL` chunks, by palette index.
+    materials: HashMap<usize, HashMap<String, String>>,
+}
+
+/// Little endian reader over the bytes of a file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(count).filter(|end| *end <= self.bytes.len());
+        let end = end.ok_or_else(|| invalid("unexpected end of file"))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn count(&mut self) -> io::Result<usize> {
+        let value = self.i32()?;
+        usize::try_from(value).map_err(|_| invalid("negative size"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.count()?;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+}
+
+impl VoxFile {
+    fn parse(bytes: &[u8]) -> io::Result<VoxFile> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(4)? != b"VOX " {
+            return Err(invalid("missing the VOX header"));
+        }
+        reader.i32()?;
+
+        if reader.take(4)? != b"MAIN" {
+            return Err(invalid("missing the MAIN chunk"));
+        }
+        let content = reader.count()?;
+        reader.count()?;
+        reader.take(content)?;
+
+        let mut vox = VoxFile {
+            models: Vec::new(),
+            palette: default_palette(),
+            materials: HashMap::new(),
+        };
+        let mut size = None;
+
+        while reader.position < bytes.len() {
+            let id = reader.take(4)?;
+            let content = reader.count()?;
+            let children = reader.count()?;
+            let mut chunk = Reader {
+                bytes: reader.take(content)?,
+                position: 0,
+            };
+            reader.take(children)?;
+
+            match id {
+                b"SIZE" => {
+                    // MagicaVoxel models span at most 256 voxels along each axis.
+                    let dims = (chunk.count()?, chunk.count()?, chunk.count()?);
+                    if [dims.0, dims.1, dims.2].iter().any(|n| *n == 0 || *n > 256) {
+                        return Err(invalid("model size out of range"));
+                    }
+                    size = Some(dims);
+                }
+                b"XYZI" => {
+                    let size = size.take().ok_or_else(|| invalid("XYZI chunk without a SIZE chunk"))?;
+                    let count = chunk.count()?;
+                    let mut voxels = Vec::with_capacity(count.min(content / 4));
+                    for _ in 0..count {
+                        let voxel = chunk.take(4)?;
+                        voxels.push((usize::from(voxel[0]), usize::from(voxel[1]), usize::from(voxel[2]), voxel[3]));
+                    }
+                    vox.models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    // The chunk's i-th color is palette index i + 1.
+                    for index in 0..255 {
+                        let color = chunk.take(4)?;
+                        vox.palette[index + 1] = [color[0], color[1], color[2], color[3]];
+                    }
+                }
+                b"MATL" => {
+                    let index = chunk.count()?;
+                    let pairs = chunk.count()?;
+                    let mut properties = HashMap::new();
+                    for _ in 0..pairs {
+                        let key = chunk.string()?;
+                        properties.insert(key, chunk.string()?);
+                    }
+                    vox.materials.insert(index, properties);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(vox)
+    }
+}
+
+/// MagicaVoxel's palette for files without an `RGBA` chunk: a 6x6x6 color
+/// cube without black, followed by ramps of red, green, blue and grey.
+fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let levels = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut index = 1;
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+    for channel in 0..4 {
+        for value in ramp {
+            palette[index] = match channel {
+                0 => [value, 0, 0, 0xff],
+                1 => [0, value, 0, 0xff],
+                2 => [0, 0, value, 0xff],
+                _ => [value, value, value, 0xff],
+            };
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+/// Turns a palette color and the properties of its `MATL` chunk into a material.
+fn vox_material(rgba: [u8; 4], properties: Option<&HashMap<String, String>>) -> Material {
+    let color = Color::from_u8(rgba[0], rgba[1], rgba[2]);
+    let base = Material {
+        color,
+        diffuse: 0.8,
+        specular: 0.1,
+        specular_exponent: 10.0,
+        ..Material::neutral()
+    };
+
+    let properties = match properties {
+        Some(properties) => properties,
+        None => return base,
+    };
+    let value = |key: &str| properties.get(key).and_then(|value| value.parse::<f64>().ok());
+    let roughness = value("_rough").unwrap_or(0.1).clamp(0.0, 1.0);
+
+    match properties.get("_type").map(String::as_str) {
+        Some("_metal") => {
+            let metallic = value("_metal").unwrap_or(1.0).clamp(0.0, 1.0);
+            Material {
+                reflectiveness: metallic,
+                roughness,
+                bsdf: Some(Bsdf::Principled(Principled {
+                    base_color: color,
+                    metallic,
+                    roughness,
+                    ..Principled::default()
+                })),
+                ..base
+            }
+        }
+        Some("_glass") => {
+            // MagicaVoxel stores the index of refraction less one.
+            let ior = value("_ior").map_or(1.5, |ior| if ior < 1.0 { ior + 1.0 } else { ior });
+            let transparency = value("_trans").or_else(|| value("_alpha")).unwrap_or(1.0).clamp(0.0, 1.0);
+            Material {
+                opacity: 1.0 - transparency,
+                reflectiveness: 0.05,
+                roughness,
+                bsdf: Some(Bsdf::Dielectric { ior, roughness, tint: color }),
+                ..base
+            }
+        }
+        Some("_emit") => {
+            let strength = value("_emit").unwrap_or(1.0) * (1.0 + value("_flux").unwrap_or(0.0));
+            Material {
+                emission: color * strength,
+                ..base
+            }
+        }
+        _ => base,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::shapes::voxels::{VoxFile, VoxelGrid};
+    use crate::vector::Vec3;
+
+    fn chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as i32).to_le_bytes());
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend(content);
+        bytes
+    }
+
+    #[test]
+    fn test_traversal() {
+        let mut grid = VoxelGrid::new((4, 4, 4), Vec3::zero(), 0.5, vec![Material::neutral(); 2]).unwrap();
+        grid.set(2, 1, 3, 2);
+
+        // Down onto the top of the filled voxel, through the empty ones above it.
+        let ray = Ray {
+            origin: Vec3::new(1.2, 5.0, 1.7),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let t = grid.intersect(ray).unwrap();
+        assert!((t - 4.0).abs() < 1e-9);
+        let hit = ray.origin + ray.direction * t;
+        assert!((grid.normal(hit) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+
+        // Along x into its side, seen from the far side of the grid.
+        let ray = Ray {
+            origin: Vec3::new(5.0, 0.7, 1.6),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let t = grid.intersect(ray).unwrap();
+        assert!((t - 3.5).abs() < 1e-9);
+        assert!((grid.normal(ray.origin + ray.direction * t) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+
+        let miss = Ray {
+            origin: Vec3::new(0.2, 5.0, 0.2),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert!(grid.intersect(miss).is_none());
+    }
+
+    /// A `.vox` file with one model of `dims` holding a single voxel.
+    fn vox_file(dims: [i32; 3]) -> Vec<u8> {
+        let mut size = Vec::new();
+        for value in dims {
+            size.extend(value.to_le_bytes());
+        }
+        let mut xyzi = 1i32.to_le_bytes().to_vec();
+        xyzi.extend([1, 2, 3, 7]);
+
+        let mut children = chunk(b"SIZE", &size);
+        children.extend(chunk(b"XYZI", &xyzi));
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150i32.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend((children.len() as i32).to_le_bytes());
+        bytes.extend(children);
+        bytes
+    }
+
+    #[test]
+    fn test_vox_parse() {
+        let vox = VoxFile::parse(&vox_file([2, 3, 4])).unwrap();
+        assert_eq!(vox.models.len(), 1);
+        assert_eq!(vox.models[0].size, (2, 3, 4));
+        assert_eq!(vox.models[0].voxels, vec![(1, 2, 3, 7)]);
+        assert_eq!(vox.palette[1], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(vox.palette[255], [0x11, 0x11, 0x11, 0xff]);
+
+        for dims in [[0, 3, 4], [2, 257, 4], [2, 3, i32::MAX]] {
+            assert!(VoxFile::parse(&vox_file(dims)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_size_out_of_range() {
+        for dims in [(0, 4, 4), (4, 257, 4), (usize::MAX, usize::MAX, 2)] {
+            assert!(VoxelGrid::new(dims, Vec3::zero(), 1.0, Vec::new()).is_err());
+        }
+        assert!(VoxelGrid::new((256, 1, 256), Vec3::zero(), 1.0, Vec::new()).is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::bsdf::{Bsdf, Principled};
+use crate::color::Color;
+use crate::material::Material;
+
+use image::DynamicImage;
+use std::fmt;
+use std::sync::Arc;
+
+/// An image looked up by texture coordinates, with `(0, 0)` at its top left
+/// corner and `(1, 1)` at its bottom right, repeating beyond. Texels are
+/// blended bilinearly and hold linear colors.
+pub struct Texture {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl Texture {
+    /// Converts an image, whose colors are taken to be sRGB encoded when `srgb`
+    /// is set, as color textures usually are, and linear otherwise.
+    pub fn from_image(image: &DynamicImage, srgb: bool) -> Texture {
+        let image = image.to_rgb8();
+        let decode = |byte: u8| {
+            let value = f64::from(byte) / 255.0;
+            if !srgb {
+                value
+            } else if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        Texture {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            texels: image.pixels().map(|pixel| Color::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]))).collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        if self.texels.is_empty() {
+            return Color::white();
+        }
+
+        let x = u.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = v.rem_euclid(1.0) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |x: f64, y: f64| {
+            let column = (x as i64).rem_euclid(self.width as i64) as usize;
+            let row = (y as i64).rem_euclid(self.height as i64) as usize;
+            self.texels[row * self.width + column]
+        };
+
+        texel(x0, y0) * ((1.0 - fx) * (1.0 - fy))
+            + texel(x0 + 1.0, y0) * (fx * (1.0 - fy))
+            + texel(x0, y0 + 1.0) * ((1.0 - fx) * fy)
+            + texel(x0 + 1.0, y0 + 1.0) * (fx * fy)
+    }
+}
+
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Texture({}x{})", self.width, self.height)
+    }
+}
+
+/// Textures varying a material across a surface, in the way of glTF's
+/// metallic-roughness materials.
+#[derive(Clone, Debug, Default)]
+pub struct SurfaceTextures {
+    /// Multiplies the material's color, see `Material::tinted`.
+    pub base_color: Option<Arc<Texture>>,
+    /// Metalness in the blue channel and roughness in the green one,
+    /// multiplying those of the material.
+    pub metallic_roughness: Option<Arc<Texture>>,
+    /// Multiplies the material's emission.
+    pub emission: Option<Arc<Texture>>,
+}
+
+impl SurfaceTextures {
+    pub fn is_empty(&self) -> bool {
+        self.base_color.is_none() && self.metallic_roughness.is_none() && self.emission.is_none()
+    }
+
+    /// `material` as textured at `(u, v)`.
+    pub fn apply(&self, material: Material, (u, v): (f64, f64)) -> Material {
+        let mut material = match &self.base_color {
+            Some(texture) => material.tinted(texture.sample(u, v)),
+            None => material,
+        };
+
+        if let Some(texture) = &self.metallic_roughness {
+            let [_, roughness, metallic] = texture.sample(u, v).channels();
+            material.reflectiveness *= metallic;
+            material.roughness *= roughness;
+            if let Some(Bsdf::Principled(principled)) = material.bsdf {
+                material.bsdf = Some(Bsdf::Principled(Principled {
+                    metallic: principled.metallic * metallic,
+                    roughness: principled.roughness * roughness,
+                    ..principled
+                }));
+            }
+        }
+
+        if let Some(texture) = &self.emission {
+            material.emission = material.emission * texture.sample(u, v);
+        }
+        material
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::texture::Texture;
+    use image::{DynamicImage, RgbImage};
+
+    #[test]
+    fn test_sample() {
+        // Black on the left, white on the right.
+        let image = RgbImage::from_fn(2, 1, |x, _| image::Rgb([if x == 0 { 0 } else { 255 }; 3]));
+        let texture = Texture::from_image(&DynamicImage::ImageRgb8(image), false);
+
+        assert!((texture.sample(0.25, 0.5).channels()[0]).abs() < 1e-9);
+        assert!((texture.sample(0.5, 0.5).channels()[0] - 0.5).abs() < 1e-9);
+        assert!((texture.sample(0.75, 0.5).channels()[0] - 1.0).abs() < 1e-9);
+        // Repeating, the left edge blends with the right one.
+        assert!((texture.sample(0.0, 0.5).channels()[0] - 0.5).abs() < 1e-9);
+    }
+}
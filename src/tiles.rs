@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A rectangle of the image rendered as one unit of work.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The order tiles are handed out to the render threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Scanline,
+    /// Outwards from the center of the image, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles next to each other.
+    Hilbert,
+}
+
+impl Tile {
+    /// Splits a `width` x `height` image into tiles of at most `size` pixels a
+    /// side, listed in `order`.
+    pub fn split(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+        let size = size.max(1);
+        let columns = width.div_ceil(size);
+        let rows = height.div_ceil(size);
+
+        let cells: Vec<(u32, u32)> = match order {
+            TileOrder::Scanline => (0..rows)
+                .flat_map(|row| (0..columns).map(move |column| (column, row)))
+                .collect(),
+            TileOrder::Spiral => spiral(columns, rows),
+            TileOrder::Hilbert => hilbert(columns, rows),
+        };
+
+        cells
+            .into_iter()
+            .map(|(column, row)| {
+                let x = column * size;
+                let y = row * size;
+                Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                }
+            })
+            .collect()
+    }
+
+    pub fn pixels(&self) -> u32 {
+        self.width * self.height
+    }
+}
+
+/// Walks a square spiral out of the center cell, keeping the cells that fall on the grid.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = (columns * rows) as usize;
+    let mut cells = Vec::with_capacity(total);
+    if total == 0 {
+        return cells;
+    }
+
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 1;
+    let mut turn = 0;
+
+    cells.push((x as u32, y as u32));
+    while cells.len() < total {
+        for _ in 0..2 {
+            let (dx, dy) = directions[turn % 4];
+            for _ in 0..leg {
+                x += dx;
+                y += dy;
+                if x >= 0 && y >= 0 && x < i64::from(columns) && y < i64::from(rows) {
+                    cells.push((x as u32, y as u32));
+                }
+            }
+            turn += 1;
+        }
+        leg += 1;
+    }
+
+    cells
+}
+
+/// Cells of the grid in Hilbert curve order, walking the curve of the smallest
+/// power of two square covering the grid and skipping what falls outside.
+fn hilbert(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let n = columns.max(rows).max(1).next_power_of_two();
+
+    (0..n * n)
+        .map(|d| {
+            let (mut x, mut y) = (0, 0);
+            let mut t = d;
+            let mut s = 1;
+            while s < n {
+                let rx = 1 & (t / 2);
+                let ry = 1 & (t ^ rx);
+                if ry == 0 {
+                    if rx == 1 {
+                        x = s - 1 - x;
+                        y = s - 1 - y;
+                    }
+                    std::mem::swap(&mut x, &mut y);
+                }
+                x += s * rx;
+                y += s * ry;
+                t /= 4;
+                s *= 2;
+            }
+            (x, y)
+        })
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+/// Reported after every finished tile.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub tile: Tile,
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        self.completed as f64 / self.total.max(1) as f64
+    }
+
+    /// Estimated time left, assuming the remaining tiles take as long as the finished ones.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.completed == 0 {
+            return None;
+        }
+        let per_tile = self.elapsed.as_secs_f64() / self.completed as f64;
+        Some(Duration::from_secs_f64(per_tile * (self.total - self.completed) as f64))
+    }
+}
+
+/// Shared flag to stop a render early. Clones refer to the same flag, so one
+/// can be handed to a UI or request handler while the render holds another.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Returned by a render that was stopped through its `CancelToken`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "render cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod test {
+    use crate::tiles::{Tile, TileOrder};
+
+    #[test]
+    fn test_orders_cover_image() {
+        for &order in &[TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = Tile::split(100, 70, 32, order);
+            assert_eq!(tiles.len(), 12);
+            assert_eq!(tiles.iter().map(Tile::pixels).sum::<u32>(), 100 * 70);
+
+            let mut origins: Vec<(u32, u32)> = tiles.iter().map(|t| (t.x, t.y)).collect();
+            origins.sort_unstable();
+            origins.dedup();
+            assert_eq!(origins.len(), 12);
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_in_center() {
+        let tiles = Tile::split(96, 96, 32, TileOrder::Spiral);
+        assert_eq!((tiles[0].x, tiles[0].y), (32, 32));
+    }
+}